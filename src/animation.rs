@@ -0,0 +1,740 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::body::VBody;
+use crate::camera::VCamera;
+use crate::canvas::vcolor::VColor;
+use crate::light::VPointLight;
+use crate::material::VMaterial;
+use crate::matrix::VMatrix;
+use crate::scene::VScene;
+use crate::tuple::VTuple;
+use crate::F;
+
+/// Values that can be blended between two keyframes, `t` runs from 0 at
+/// `self` to 1 at `other`.
+pub trait Interpolate {
+    fn interpolated(&self, other: &Self, t: F) -> Self;
+}
+impl Interpolate for F {
+    fn interpolated(&self, other: &F, t: F) -> F {
+        self + (other - self) * t
+    }
+}
+impl Interpolate for VTuple {
+    fn interpolated(&self, other: &VTuple, t: F) -> VTuple {
+        *self + (*other - *self) * t
+    }
+}
+impl Interpolate for VColor {
+    fn interpolated(&self, other: &VColor, t: F) -> VColor {
+        *self + (*other - *self) * t
+    }
+}
+
+/// Timing curve applied between two keys, mapping the linear progress `t`
+/// from 0 to 1 onto the progress of the value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VEasing {
+    #[default]
+    Linear,
+    /// Quadratic, starts slowly.
+    EaseIn,
+    /// Quadratic, stops slowly.
+    EaseOut,
+    EaseInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    /// Winds up with growing oscillations before moving.
+    ElasticIn,
+    /// Overshoots and settles like a spring.
+    ElasticOut,
+}
+impl VEasing {
+    pub fn ease(&self, t: F) -> F {
+        let t = t.clamp(0.0, 1.0);
+        // Period of the elastic oscillation
+        let c4 = 2.0 * PI / 3.0;
+        match self {
+            VEasing::Linear => t,
+            VEasing::EaseIn => t * t,
+            VEasing::EaseOut => 1.0 - (1.0 - t).powi(2),
+            VEasing::EaseInOut if t < 0.5 => 2.0 * t * t,
+            VEasing::EaseInOut => 1.0 - (2.0 - 2.0 * t).powi(2) / 2.0,
+            VEasing::CubicIn => t.powi(3),
+            VEasing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            VEasing::CubicInOut if t < 0.5 => 4.0 * t.powi(3),
+            VEasing::CubicInOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+            _ if t == 0.0 || t == 1.0 => t,
+            VEasing::ElasticIn => {
+                -(2.0 as F).powf(10.0 * t - 10.0) * ((t * 10.0 - 10.75) * c4).sin()
+            }
+            VEasing::ElasticOut => {
+                (2.0 as F).powf(-10.0 * t) * ((t * 10.0 - 0.75) * c4).sin() + 1.0
+            }
+        }
+    }
+}
+
+/// How the values between keys are found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VInterpolation {
+    /// Straight from key to key.
+    #[default]
+    Linear,
+    /// Smooth curve through every key, the speed of each segment follows
+    /// the time between the keys.
+    CatmullRom,
+    /// Cubic Bezier segments, every key after the first is preceded by two
+    /// handles shaping the curve: `key, handle, handle, key, handle...`. Only
+    /// the times of the keys are used, values after the last complete
+    /// segment are ignored.
+    Bezier,
+}
+impl VInterpolation {
+    /// Number of values the curve passes through.
+    pub fn key_count(&self, value_count: usize) -> usize {
+        match self {
+            VInterpolation::Bezier if value_count > 0 => (value_count - 1) / 3 + 1,
+            _ => value_count,
+        }
+    }
+    /// Value at `at` for keys placed at the sorted `times`, with one time per
+    /// key (see [`key_count`](Self::key_count)). Before the first and after
+    /// the last key the value holds still, `None` without values.
+    pub fn value_at<T: Interpolate + Clone>(
+        &self,
+        times: &[F],
+        values: &[T],
+        at: F,
+        easing: VEasing,
+    ) -> Option<T> {
+        let keys = times.len().min(self.key_count(values.len()));
+        let key = |index: usize| match self {
+            VInterpolation::Bezier => &values[index * 3],
+            _ => &values[index],
+        };
+        // Index of the first key after `at`, keys on the same time jump to
+        // the last one of them
+        let next = times[..keys].partition_point(|time| *time <= at);
+        if keys == 0 {
+            return None;
+        } else if next == 0 {
+            return Some(key(0).clone());
+        } else if next == keys {
+            return Some(key(keys - 1).clone());
+        }
+        let i = next - 1;
+        let t = easing.ease((at - times[i]) / (times[next] - times[i]));
+        Some(match self {
+            VInterpolation::Linear => values[i].interpolated(&values[next], t),
+            VInterpolation::CatmullRom => {
+                let (t1, t2) = (times[i], times[next]);
+                let span = t2 - t1;
+                // Missing neighbours at the ends repeat the end key
+                let (t0, p0) = match i.checked_sub(1) {
+                    Some(before) if times[before] < t1 => (times[before], &values[before]),
+                    _ => (t1 - span, &values[i]),
+                };
+                let (t3, p3) = match times.get(next + 1) {
+                    Some(after) if next + 1 < keys && *after > t2 => (*after, &values[next + 1]),
+                    _ => (t2 + span, &values[next]),
+                };
+                catmull_rom(
+                    [t0, t1, t2, t3],
+                    [p0, &values[i], &values[next], p3],
+                    t1 + t * span,
+                )
+            }
+            VInterpolation::Bezier => {
+                let points = &values[i * 3..i * 3 + 4];
+                let lerp = |a: &T, b: &T| a.interpolated(b, t);
+                let (a, b, c) = (
+                    lerp(&points[0], &points[1]),
+                    lerp(&points[1], &points[2]),
+                    lerp(&points[2], &points[3]),
+                );
+                lerp(&lerp(&a, &b), &lerp(&b, &c))
+            }
+        })
+    }
+}
+// Barry and Goldman's pyramid, which evaluates non-uniform Catmull-Rom
+// splines with linear interpolations only.
+fn catmull_rom<T: Interpolate>(times: [F; 4], points: [&T; 4], at: F) -> T {
+    let lerp = |a: &T, b: &T, from: F, to: F| a.interpolated(b, (at - from) / (to - from));
+    let [t0, t1, t2, t3] = times;
+    let a1 = lerp(points[0], points[1], t0, t1);
+    let a2 = lerp(points[1], points[2], t1, t2);
+    let a3 = lerp(points[2], points[3], t2, t3);
+    let b1 = lerp(&a1, &a2, t0, t2);
+    let b2 = lerp(&a2, &a3, t1, t3);
+    lerp(&b1, &b2, t1, t2)
+}
+
+/// Value of a track at one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VKey<T> {
+    pub frame: F,
+    pub value: T,
+}
+impl<T> VKey<T> {
+    pub fn new(frame: F, value: T) -> Self {
+        Self { frame, value }
+    }
+}
+
+/// Keyframes of a single value. Before the first and after the last key
+/// the value holds still, a track without keys leaves the value alone. In
+/// Bezier mode the handles are keys as well, their frames only order them
+/// between the keys they belong to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "VTrackConfig<T>", into = "VTrackConfig<T>")]
+#[serde(bound(
+    serialize = "T: Serialize + Clone",
+    deserialize = "T: Deserialize<'de>"
+))]
+pub struct VTrack<T> {
+    keys: Vec<VKey<T>>,
+    pub interpolation: VInterpolation,
+    pub easing: VEasing,
+}
+// Keys are kept sorted by frame, also when they come from a scene file.
+#[derive(Serialize, Deserialize)]
+struct VTrackConfig<T> {
+    keys: Vec<VKey<T>>,
+    #[serde(default)]
+    interpolation: VInterpolation,
+    #[serde(default)]
+    easing: VEasing,
+}
+impl<T> From<VTrackConfig<T>> for VTrack<T> {
+    fn from(cfg: VTrackConfig<T>) -> Self {
+        VTrack::new(cfg.keys)
+            .with_interpolation(cfg.interpolation)
+            .with_easing(cfg.easing)
+    }
+}
+impl<T> From<VTrack<T>> for VTrackConfig<T> {
+    fn from(track: VTrack<T>) -> Self {
+        VTrackConfig {
+            keys: track.keys,
+            interpolation: track.interpolation,
+            easing: track.easing,
+        }
+    }
+}
+impl<T> Default for VTrack<T> {
+    fn default() -> Self {
+        Self {
+            keys: vec![],
+            interpolation: VInterpolation::Linear,
+            easing: VEasing::Linear,
+        }
+    }
+}
+impl<T> VTrack<T> {
+    pub fn new(mut keys: Vec<VKey<T>>) -> Self {
+        keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        Self {
+            keys,
+            ..Self::default()
+        }
+    }
+    pub fn with_key(mut self, frame: F, value: T) -> Self {
+        let index = self.keys.partition_point(|key| key.frame <= frame);
+        self.keys.insert(index, VKey::new(frame, value));
+        self
+    }
+    pub fn with_interpolation(mut self, interpolation: VInterpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+    pub fn with_easing(mut self, easing: VEasing) -> Self {
+        self.easing = easing;
+        self
+    }
+    pub fn keys(&self) -> &[VKey<T>] {
+        &self.keys
+    }
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+    pub fn last_frame(&self) -> Option<F> {
+        self.keys.last().map(|key| key.frame)
+    }
+}
+impl<T: Interpolate + Clone> VTrack<T> {
+    pub fn value_at(&self, frame: F) -> Option<T> {
+        let step = match self.interpolation {
+            VInterpolation::Bezier => 3,
+            _ => 1,
+        };
+        let times: Vec<F> = self
+            .keys
+            .iter()
+            .step_by(step)
+            .map(|key| key.frame)
+            .collect();
+        let values: Vec<T> = self.keys.iter().map(|key| key.value.clone()).collect();
+        self.interpolation
+            .value_at(&times, &values, frame, self.easing)
+    }
+}
+
+/// Keyframes of a camera. The camera keeps pointing the way it was set up
+/// when only `from` or only `to` is animated.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VCameraKeys {
+    pub from: VTrack<VTuple>,
+    pub to: VTrack<VTuple>,
+    pub up: VTrack<VTuple>,
+    pub fov: VTrack<F>,
+}
+impl VCameraKeys {
+    fn last_frame(&self) -> Option<F> {
+        last_of(&[
+            self.from.last_frame(),
+            self.to.last_frame(),
+            self.up.last_frame(),
+            self.fov.last_frame(),
+        ])
+    }
+    fn apply(&self, camera: &VCamera, frame: F) -> VCamera {
+        let fov = self.fov.value_at(frame).unwrap_or(camera.fov);
+        let mut transform = camera.transform;
+        if !(self.from.is_empty() && self.to.is_empty() && self.up.is_empty()) {
+            let inverse = camera.transform.inverted();
+            let forward = (inverse * VTuple::vector(0.0, 0.0, -1.0)).normalized();
+            let from = self
+                .from
+                .value_at(frame)
+                .unwrap_or(inverse * VTuple::point(0.0, 0.0, 0.0));
+            let to = self.to.value_at(frame).unwrap_or(from + forward);
+            let up = self
+                .up
+                .value_at(frame)
+                .unwrap_or(inverse * VTuple::vector(0.0, 1.0, 0.0));
+            transform = VCamera::new(1, 1, fov)
+                .positioned_and_pointed(from, to, up)
+                .transform;
+        }
+        VCamera::new(camera.hsize, camera.vsize, fov).with_transform(transform)
+    }
+}
+
+/// Keyframes of a point light.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VLightKeys {
+    pub position: VTrack<VTuple>,
+    pub intensity: VTrack<VColor>,
+}
+impl VLightKeys {
+    fn last_frame(&self) -> Option<F> {
+        last_of(&[self.position.last_frame(), self.intensity.last_frame()])
+    }
+    fn apply(&self, light: &VPointLight, frame: F) -> VPointLight {
+        VPointLight::new(
+            self.position.value_at(frame).unwrap_or(light.pos),
+            self.intensity.value_at(frame).unwrap_or(light.col),
+        )
+    }
+}
+
+/// Keyframes of a body. Translation, rotation (around x, then y, then z, in
+/// radians) and scale are applied on top of the body's own transform.
+/// Material keys replace the values of its Phong material, groups and CSG
+/// bodies hand the result to all of their children.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VBodyKeys {
+    pub translation: VTrack<VTuple>,
+    pub rotation: VTrack<VTuple>,
+    pub scale: VTrack<VTuple>,
+    pub color: VTrack<VColor>,
+    pub ambient: VTrack<F>,
+    pub diffuse: VTrack<F>,
+    pub specular: VTrack<F>,
+    pub shininess: VTrack<F>,
+}
+impl VBodyKeys {
+    fn last_frame(&self) -> Option<F> {
+        last_of(&[
+            self.translation.last_frame(),
+            self.rotation.last_frame(),
+            self.scale.last_frame(),
+            self.color.last_frame(),
+            self.ambient.last_frame(),
+            self.diffuse.last_frame(),
+            self.specular.last_frame(),
+            self.shininess.last_frame(),
+        ])
+    }
+    fn apply(&self, body: &VBody, frame: F) -> VBody {
+        let mut body = body.clone();
+        if !(self.translation.is_empty() && self.rotation.is_empty() && self.scale.is_empty()) {
+            let zero = VTuple::vector(0.0, 0.0, 0.0);
+            let t = self.translation.value_at(frame).unwrap_or(zero);
+            let r = self.rotation.value_at(frame).unwrap_or(zero);
+            let s = self
+                .scale
+                .value_at(frame)
+                .unwrap_or(VTuple::vector(1.0, 1.0, 1.0));
+            let transform = VMatrix::translation(t.x, t.y, t.z)
+                * VMatrix::rotation_z(r.z)
+                * VMatrix::rotation_y(r.y)
+                * VMatrix::rotation_x(r.x)
+                * VMatrix::scaling(s.x, s.y, s.z);
+            body = body.with_parent_transform(transform);
+        }
+        let material_keys = [
+            self.ambient.is_empty(),
+            self.diffuse.is_empty(),
+            self.specular.is_empty(),
+            self.shininess.is_empty(),
+        ];
+        if !self.color.is_empty() || material_keys.contains(&false) {
+            // Groups keep the materials of their bodies, only keyed fields change
+            body = body.map_material(&|material| match material {
+                VMaterial::VPhong(mut phong) => {
+                    phong.col = self.color.value_at(frame).unwrap_or(phong.col);
+                    phong.amb = self.ambient.value_at(frame).unwrap_or(phong.amb);
+                    phong.dif = self.diffuse.value_at(frame).unwrap_or(phong.dif);
+                    phong.spc = self.specular.value_at(frame).unwrap_or(phong.spc);
+                    phong.shi = self.shininess.value_at(frame).unwrap_or(phong.shi);
+                    VMaterial::from(phong)
+                }
+            });
+        }
+        body
+    }
+}
+
+/// Keyframed changes to a scene. Cameras are referenced by name, lights and
+/// bodies by their index in the world, entries without a match are ignored.
+/// Evaluated with [`VScene::at_frame`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VAnimation {
+    pub cameras: BTreeMap<String, VCameraKeys>,
+    pub lights: BTreeMap<usize, VLightKeys>,
+    pub bodies: BTreeMap<usize, VBodyKeys>,
+}
+impl VAnimation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_camera(mut self, name: &str, keys: VCameraKeys) -> Self {
+        self.cameras.insert(String::from(name), keys);
+        self
+    }
+    pub fn with_light(mut self, index: usize, keys: VLightKeys) -> Self {
+        self.lights.insert(index, keys);
+        self
+    }
+    pub fn with_body(mut self, index: usize, keys: VBodyKeys) -> Self {
+        self.bodies.insert(index, keys);
+        self
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cameras.is_empty() && self.lights.is_empty() && self.bodies.is_empty()
+    }
+    /// Frame of the last key in any track, 0 without keys.
+    pub fn last_frame(&self) -> F {
+        let cameras = self.cameras.values().map(VCameraKeys::last_frame);
+        let lights = self.lights.values().map(VLightKeys::last_frame);
+        let bodies = self.bodies.values().map(VBodyKeys::last_frame);
+        last_of(&cameras.chain(lights).chain(bodies).collect::<Vec<_>>()).unwrap_or(0.0)
+    }
+    /// Replaces the animated values of `scene` by those at `frame`, the
+    /// scene has to be in the state the keys were made for.
+    pub(crate) fn apply(&self, scene: &mut VScene, frame: F) {
+        for (name, keys) in self.cameras.iter() {
+            if let Some(camera) = scene.cameras.get_mut(name) {
+                *camera = keys.apply(camera, frame);
+            }
+        }
+        for (index, keys) in self.lights.iter() {
+            if let Some(light) = scene.world.lights.get_mut(*index) {
+                *light = keys.apply(light, frame);
+            }
+        }
+        for (index, keys) in self.bodies.iter() {
+            if let Some(body) = scene.world.bodies.get_mut(*index) {
+                *body = keys.apply(body, frame);
+            }
+        }
+    }
+}
+fn last_of(frames: &[Option<F>]) -> Option<F> {
+    frames.iter().flatten().copied().reduce(F::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::body::VIntersectable;
+    use crate::group::VGroup;
+    use crate::material::VPhong;
+    use crate::sphere::VSphere;
+    use crate::world::VWorld;
+    use crate::zequality::*;
+
+    fn animated_scene() -> VScene {
+        let sphere = VSphere::default().with_transform(VMatrix::translation(0.0, 1.0, 0.0));
+        let world = VWorld::new(
+            vec![VBody::from(sphere)],
+            vec![VPointLight::new(
+                VTuple::point(-10.0, 10.0, -10.0),
+                VColor::white(),
+            )],
+        );
+        VScene::new(world)
+            .with_camera(
+                "camera",
+                VCamera::new(40, 20, PI / 3.0).positioned_and_pointed(
+                    VTuple::point(0.0, 0.0, -5.0),
+                    VTuple::point(0.0, 0.0, 0.0),
+                    VTuple::vector(0.0, 1.0, 0.0),
+                ),
+            )
+            .with_animation(
+                VAnimation::new()
+                    .with_camera(
+                        "camera",
+                        VCameraKeys {
+                            from: VTrack::default()
+                                .with_key(0.0, VTuple::point(0.0, 0.0, -5.0))
+                                .with_key(10.0, VTuple::point(-5.0, 0.0, 0.0)),
+                            ..VCameraKeys::default()
+                        },
+                    )
+                    .with_light(
+                        0,
+                        VLightKeys {
+                            intensity: VTrack::default()
+                                .with_key(0.0, VColor::black())
+                                .with_key(4.0, VColor::white()),
+                            ..VLightKeys::default()
+                        },
+                    )
+                    .with_body(
+                        0,
+                        VBodyKeys {
+                            translation: VTrack::default()
+                                .with_key(0.0, VTuple::vector(0.0, 0.0, 0.0))
+                                .with_key(20.0, VTuple::vector(4.0, 0.0, 0.0)),
+                            ambient: VTrack::default().with_key(0.0, 0.0).with_key(10.0, 1.0),
+                            ..VBodyKeys::default()
+                        },
+                    ),
+            )
+    }
+
+    #[test]
+    fn easing_curves_keep_their_ends() {
+        let easings = [
+            VEasing::Linear,
+            VEasing::EaseIn,
+            VEasing::EaseOut,
+            VEasing::EaseInOut,
+            VEasing::CubicIn,
+            VEasing::CubicOut,
+            VEasing::CubicInOut,
+            VEasing::ElasticIn,
+            VEasing::ElasticOut,
+        ];
+        for easing in easings {
+            assert_zeq!(easing.ease(0.0), 0.0);
+            assert_zeq!(easing.ease(1.0), 1.0);
+        }
+        assert_zeq!(VEasing::EaseIn.ease(0.5), 0.25);
+        assert_zeq!(VEasing::EaseOut.ease(0.5), 0.75);
+        assert_zeq!(VEasing::EaseInOut.ease(0.25), 0.125);
+        assert_zeq!(VEasing::CubicIn.ease(0.5), 0.125);
+        assert_zeq!(VEasing::CubicInOut.ease(0.5), 0.5);
+        assert_zeq!(VEasing::CubicInOut.ease(0.75), 0.9375);
+        // Springs overshoot the target, winding up undershoots the start
+        assert!(VEasing::ElasticOut.ease(0.1) > 1.0);
+        assert!(VEasing::ElasticIn.ease(0.9) < 0.0);
+    }
+
+    #[test]
+    fn smooth_tracks_pass_through_their_keys() {
+        let keys = vec![
+            VKey::new(0.0, 0.0),
+            VKey::new(10.0, 1.0),
+            VKey::new(20.0, 2.0),
+            VKey::new(40.0, 0.0),
+        ];
+        let track = VTrack::new(keys.clone()).with_interpolation(VInterpolation::CatmullRom);
+
+        for key in keys.iter() {
+            assert_zeq!(track.value_at(key.frame).unwrap(), key.value);
+        }
+        // Instead of turning sharply at the peak the curve rounds it off
+        assert!(track.value_at(22.0).unwrap() > 2.0);
+        assert_zeq!(
+            track
+                .clone()
+                .with_interpolation(VInterpolation::Linear)
+                .value_at(22.0)
+                .unwrap(),
+            1.8
+        );
+
+        let path = VTrack::default()
+            .with_interpolation(VInterpolation::Bezier)
+            .with_key(0.0, VTuple::point(0.0, 0.0, 0.0))
+            .with_key(1.0, VTuple::point(0.0, 1.0, 0.0))
+            .with_key(2.0, VTuple::point(1.0, 1.0, 0.0))
+            .with_key(10.0, VTuple::point(1.0, 0.0, 0.0));
+        assert_zeq!(path.value_at(5.0).unwrap(), VTuple::point(0.5, 0.75, 0.0));
+        assert_zeq!(path.value_at(10.0).unwrap(), VTuple::point(1.0, 0.0, 0.0));
+        assert_zeq!(
+            path.with_easing(VEasing::EaseIn).value_at(0.0).unwrap(),
+            VTuple::point(0.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn tracks_interpolate_between_keys() {
+        let track = VTrack::new(vec![
+            VKey::new(10.0, 4.0),
+            VKey::new(0.0, 0.0),
+            VKey::new(20.0, 2.0),
+        ]);
+
+        assert_eq!(track.keys()[1], VKey::new(10.0, 4.0));
+        assert_zeq!(track.value_at(-5.0).unwrap(), 0.0);
+        assert_zeq!(track.value_at(2.5).unwrap(), 1.0);
+        assert_zeq!(track.value_at(10.0).unwrap(), 4.0);
+        assert_zeq!(track.value_at(15.0).unwrap(), 3.0);
+        assert_zeq!(track.value_at(25.0).unwrap(), 2.0);
+        assert_eq!(VTrack::<F>::default().value_at(1.0), None);
+
+        let colors = VTrack::default()
+            .with_key(0.0, VColor::black())
+            .with_key(2.0, VColor::new(1.0, 0.5, 0.0));
+        assert_zeq!(colors.value_at(1.0).unwrap(), VColor::new(0.5, 0.25, 0.0));
+    }
+
+    #[test]
+    fn evaluating_a_scene_at_a_frame() {
+        let scene = animated_scene();
+        let start = scene.at_frame(0.0);
+        let middle = scene.at_frame(10.0);
+
+        assert_eq!(start.world, scene.world_at(0.0));
+        assert_zeq!(start.world.lights[0].col, VColor::black());
+        assert_zeq!(middle.world.lights[0].col, VColor::white());
+        assert_zeq!(
+            middle.world.lights[0].pos,
+            VTuple::point(-10.0, 10.0, -10.0)
+        );
+
+        assert_zeq!(
+            middle.world.bodies[0].transform(),
+            VMatrix::translation(2.0, 1.0, 0.0)
+        );
+        assert_zeq!(middle.world.bodies[0].material().albedo(), VColor::white());
+        match middle.world.bodies[0].material() {
+            VMaterial::VPhong(phong) => assert_zeq!(phong.amb, 1.0),
+        }
+        // The unanimated scene is left alone
+        assert_zeq!(
+            scene.world.bodies[0].transform(),
+            VMatrix::translation(0.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn animated_materials_keep_the_bodies_of_a_group_apart() {
+        let phong = |col| {
+            VMaterial::from(VPhong {
+                col,
+                ..VPhong::default()
+            })
+        };
+        let group = VGroup::new(vec![
+            VBody::from(VSphere::default().with_material(phong(VColor::red()))),
+            VBody::from(VSphere::default().with_material(phong(VColor::green()))),
+        ]);
+        let world = VWorld::new(vec![VBody::from(group)], vec![]);
+        let ambient = VTrack::default().with_key(0.0, 0.0).with_key(10.0, 1.0);
+        let scene = VScene::new(world).with_animation(VAnimation::new().with_body(
+            0,
+            VBodyKeys {
+                ambient,
+                ..VBodyKeys::default()
+            },
+        ));
+
+        let world = scene.world_at(5.0);
+        let VBody::VGroup(ref group) = world.bodies[0] else {
+            panic!("the group is kept");
+        };
+        let materials: Vec<VPhong> = group
+            .children()
+            .iter()
+            .map(|child| match child.material() {
+                VMaterial::VPhong(phong) => phong,
+            })
+            .collect();
+        assert_zeq!(materials[0].col, VColor::red());
+        assert_zeq!(materials[1].col, VColor::green());
+        for material in materials {
+            assert_zeq!(material.amb, 0.5);
+            assert_zeq!(material.dif, VPhong::default().dif);
+        }
+    }
+
+    #[test]
+    fn animated_cameras_move_and_turn() {
+        let mut scene = animated_scene();
+        let camera = scene.at_frame(10.0).cameras["camera"];
+        let ray = camera.ray_for_subpixel(20, 10, 0.0, 0.0);
+
+        // Without keys for `to` the camera keeps its direction
+        assert_zeq!(ray.origin, VTuple::point(-5.0, 0.0, 0.0));
+        assert_zeq!(ray.direction, VTuple::vector(0.0, 0.0, 1.0));
+        assert_eq!((camera.hsize, camera.vsize), (40, 20));
+        assert_zeq!(
+            scene.at_frame(0.0).cameras["camera"].transform,
+            scene.cameras["camera"].transform
+        );
+
+        let keys = scene.animation.cameras.get_mut("camera").unwrap();
+        keys.to = VTrack::default().with_key(0.0, VTuple::point(0.0, 0.0, 0.0));
+        keys.fov = VTrack::default()
+            .with_key(0.0, PI / 3.0)
+            .with_key(10.0, PI / 2.0);
+        let camera = scene.at_frame(10.0).cameras["camera"];
+        let ray = camera.ray_for_subpixel(20, 10, 0.0, 0.0);
+
+        assert_zeq!(ray.direction, VTuple::vector(1.0, 0.0, 0.0));
+        assert_zeq!(camera.fov, PI / 2.0);
+    }
+
+    #[test]
+    fn animations_round_trip_through_scene_files() {
+        let scene = animated_scene();
+
+        assert_zeq!(scene.animation.last_frame(), 20.0);
+        assert_eq!(VAnimation::new().last_frame(), 0.0);
+        assert_eq!(VScene::from_ron(&scene.to_ron().unwrap()).unwrap(), scene);
+        assert_eq!(VScene::from_json(&scene.to_json().unwrap()).unwrap(), scene);
+        // Keys written out of order are sorted when loading
+        let track: VTrack<F> = serde_json::from_str(
+            r#"{"keys": [{"frame": 5, "value": 1}, {"frame": 0, "value": 0}], "easing": "EaseIn"}"#,
+        )
+        .unwrap();
+        assert_zeq!(track.value_at(2.5).unwrap(), 0.25);
+        assert_eq!(track.interpolation, VInterpolation::Linear);
+    }
+}
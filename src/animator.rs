@@ -1,70 +1,111 @@
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::animation::{Interpolate, VEasing, VInterpolation};
 use crate::F;
 
+/// Runs a callback for the frames of an animation. All frames are visited
+/// in order by default, a range, a step and skipping frames that were
+/// already written narrow that down.
 pub struct VAnimator {
     frame_count: usize,
+    frames: Range<usize>,
+    step: usize,
+    output: Option<VFrameOutput>,
+    skip_existing: bool,
 }
+// File names of the frames, `<path>/<name><frame number><suffix>`.
+#[derive(Debug, Clone, PartialEq)]
+struct VFrameOutput {
+    path: String,
+    name: String,
+    suffix: String,
+}
+#[derive(Debug, Clone, PartialEq)]
 pub struct Frame {
     current: usize,
     count: usize,
+    output: Option<String>,
 }
 
-pub struct LinearScale {
+/// Maps a domain of inputs, e.g. frame numbers, onto a range of values. The
+/// values are spread evenly over the domain unless given times of their
+/// own, and can be anything that interpolates, like `F`, `VTuple` or
+/// `VColor`.
+pub struct LinearScale<T = F> {
     domain: (F, F),
-    range: Vec<F>,
+    range: Vec<T>,
+    times: Vec<F>,
+    interpolation: VInterpolation,
+    easing: VEasing,
 }
 impl LinearScale {
     pub fn new() -> Self {
         Self {
             domain: (0.0, 1.0),
             range: vec![0.0, 1.0],
+            times: vec![],
+            interpolation: VInterpolation::Linear,
+            easing: VEasing::Linear,
         }
     }
-    pub fn with_range(mut self, range: Vec<F>) -> Self {
-        self.range = range;
-        self
+}
+impl<T: Interpolate + Clone> LinearScale<T> {
+    pub fn with_range<U>(self, range: Vec<U>) -> LinearScale<U> {
+        LinearScale {
+            domain: self.domain,
+            range,
+            times: self.times,
+            interpolation: self.interpolation,
+            easing: self.easing,
+        }
     }
     pub fn with_domain(mut self, start: F, end: F) -> Self {
         self.domain = (start, end);
         self
     }
-    pub fn scale(&self, input: F) -> F {
-        self.interpolate(self.normalize(input))
-    }
-    fn normalize(&self, input: F) -> F {
-        //Takes an arbitrary input float and converts it into a value between [0,1)
-        let cimput = input.clamp(self.domain.0, self.domain.1);
-        let progress = cimput - self.domain.0;
-        let distance = self.domain.1 - self.domain.0;
-        progress / distance
-    }
-    fn interpolate(&self, normalized_input: F) -> F {
-        let slice_count = self.range.len() - 1;
-        let slice_width = 1.0 / slice_count as F;
-        //hack to avoid outofbounds index for I=1.0
-        let mut offset_key = 0;
-        if normalized_input == 1.0 {
-            offset_key = 1;
-        }
-
-        let current_slice = (normalized_input * (slice_count as F).floor()) as usize - offset_key;
-        let current_slice_progess =
-            (normalized_input - slice_width * current_slice as F) / slice_width;
-
-        //0-------------------1
-        //.----|----|----|----.     ->Slice count = 4, slice width = / 1/4
-        //.------->I.               ->Normalized input = 0.4
-        //.                         Current slice idx = 1
-        //.----|-->                 current slice progress CSP = I-SW*CSI
-        //.currenct slice progress, normalized = CSP / SW
-
-        current_slice_progess * (self.range[current_slice + 1] - self.range[current_slice])
-            + self.range[current_slice]
+    /// Places the keys of the range at these inputs instead of spreading
+    /// them evenly, one time per key in increasing order.
+    pub fn with_times(mut self, times: Vec<F>) -> Self {
+        self.times = times;
+        self
+    }
+    pub fn with_interpolation(mut self, interpolation: VInterpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+    /// Eases every step from one key to the next.
+    pub fn with_easing(mut self, easing: VEasing) -> Self {
+        self.easing = easing;
+        self
+    }
+    pub fn scale(&self, input: F) -> T {
+        let (start, end) = self.domain;
+        let input = input.clamp(start.min(end), start.max(end));
+        let times = if self.times.is_empty() {
+            let keys = self.interpolation.key_count(self.range.len());
+            let step = (end - start) / (keys.max(2) - 1) as F;
+            (0..keys).map(|key| start + key as F * step).collect()
+        } else {
+            self.times.clone()
+        };
+        self.interpolation
+            .value_at(&times, &self.range, input, self.easing)
+            .expect("the range of a scale can not be empty")
     }
 }
 
 impl Frame {
     pub fn new(count: usize, current: usize) -> Self {
-        Self { current, count }
+        Self {
+            current,
+            count,
+            output: None,
+        }
     }
     pub fn currentf(&self) -> F {
         self.current as F
@@ -75,24 +116,102 @@ impl Frame {
     pub fn filename(&self, path: &str, name: &str, suffix: &str) -> String {
         format!("{}/{}{:06}{}", path, name, self.current, suffix)
     }
+    /// File name of this frame, if the animator was given an output.
+    pub fn output(&self) -> Option<&str> {
+        self.output.as_deref()
+    }
+    /// Writes the frame to its output file. The data goes to a temporary file
+    /// first, so an interrupted write never leaves a broken frame behind that
+    /// would be skipped when resuming.
+    pub fn save(&self, data: &[u8]) -> io::Result<()> {
+        let path = self.output.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "the animation has no output")
+        })?;
+        let path = Path::new(path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temporary = PathBuf::from(path);
+        temporary.as_mut_os_string().push(".tmp");
+        fs::write(&temporary, data)?;
+        fs::rename(&temporary, path)
+    }
     pub fn linear_scale(&self) -> LinearScale {
         LinearScale::new().with_domain(0.0, self.count as F)
     }
 }
 impl VAnimator {
     pub fn new(frame_count: usize) -> Self {
-        Self { frame_count }
+        Self {
+            frame_count,
+            frames: 0..frame_count,
+            step: 1,
+            output: None,
+            skip_existing: false,
+        }
+    }
+    /// Only visits the frames in `frames`, which is clipped to the frame
+    /// count.
+    pub fn with_frames(mut self, frames: Range<usize>) -> Self {
+        self.frames = frames;
+        self
+    }
+    /// Only visits every `step`th frame, counting from the first one.
+    pub fn with_step(mut self, step: usize) -> Self {
+        self.step = step.max(1);
+        self
+    }
+    /// Names the output file of every frame, see [`Frame::output`].
+    pub fn with_output(mut self, path: &str, name: &str, suffix: &str) -> Self {
+        self.output = Some(VFrameOutput {
+            path: String::from(path),
+            name: String::from(name),
+            suffix: String::from(suffix),
+        });
+        self
+    }
+    /// Leaves out frames whose output file exists, so an interrupted
+    /// animation picks up where it stopped.
+    pub fn with_skip_existing(mut self, skip_existing: bool) -> Self {
+        self.skip_existing = skip_existing;
+        self
     }
-    pub fn animate(&self, animate: fn(frame: Frame)) {
-        for current_frame in 0..self.frame_count {
-            animate(Frame::new(self.frame_count, current_frame));
+    /// The frames that will be visited, in order.
+    pub fn frames(&self) -> Vec<Frame> {
+        let end = self.frames.end.min(self.frame_count);
+        (self.frames.start..end)
+            .step_by(self.step)
+            .map(|current| {
+                let mut frame = Frame::new(self.frame_count, current);
+                frame.output = self
+                    .output
+                    .as_ref()
+                    .map(|o| frame.filename(&o.path, &o.name, &o.suffix));
+                frame
+            })
+            .filter(|frame| {
+                let written = frame.output().is_some_and(|path| Path::new(path).exists());
+                !(self.skip_existing && written)
+            })
+            .collect()
+    }
+    pub fn animate<A: FnMut(Frame)>(&self, mut animate: A) {
+        for frame in self.frames() {
+            animate(frame);
         }
     }
+    /// Like [`animate`](Self::animate) but works on several frames at once,
+    /// in no particular order.
+    pub fn animate_parallel<A: Fn(Frame) + Sync + Send>(&self, animate: A) {
+        self.frames().into_par_iter().for_each(animate);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::canvas::vcolor::VColor;
+    use crate::tuple::VTuple;
     use crate::zequality::*;
 
     #[test]
@@ -142,4 +261,140 @@ mod tests {
         let ls = f.linear_scale().with_range(vec![100.0, 200.0]);
         assert_zeq!(ls.scale(f.currentf()), 200.0);
     }
+    #[test]
+    fn scaling_with_uneven_times() {
+        let ls = LinearScale::new()
+            .with_domain(0.0, 10.0)
+            .with_range(vec![0.0, 10.0, 20.0])
+            .with_times(vec![0.0, 8.0, 10.0]);
+        assert_zeq!(ls.scale(4.0), 5.0);
+        assert_zeq!(ls.scale(8.0), 10.0);
+        assert_zeq!(ls.scale(9.0), 15.0);
+    }
+    #[test]
+    fn scaling_with_easing() {
+        let ls = LinearScale::new()
+            .with_range(vec![0.0, 10.0, 20.0])
+            .with_easing(VEasing::EaseIn);
+        assert_zeq!(ls.scale(0.25), 2.5);
+        assert_zeq!(ls.scale(0.5), 10.0);
+        assert_zeq!(ls.scale(0.75), 12.5);
+    }
+    #[test]
+    fn scaling_smoothly() {
+        let ls = LinearScale::new()
+            .with_range(vec![0.0, 1.0, 2.0, 3.0])
+            .with_interpolation(VInterpolation::CatmullRom);
+        assert_zeq!(ls.scale(1.0 / 3.0), 1.0);
+        assert_zeq!(ls.scale(0.5), 1.5);
+
+        let ls = LinearScale::new()
+            .with_domain(0.0, 100.0)
+            .with_range(vec![0.0, 0.0, 1.0, 1.0])
+            .with_interpolation(VInterpolation::Bezier);
+        assert_zeq!(ls.scale(50.0), 0.5);
+        assert!(ls.scale(10.0) < 0.1);
+    }
+    #[test]
+    fn scaling_vectors_and_colors() {
+        let f = Frame::new(10, 5);
+        let path = f.linear_scale().with_range(vec![
+            VTuple::point(0.0, 0.0, 0.0),
+            VTuple::point(0.0, 1.0, 0.0),
+            VTuple::point(1.0, 1.0, 0.0),
+            VTuple::point(1.0, 0.0, 0.0),
+        ]);
+        assert_zeq!(path.scale(f.currentf()), VTuple::point(0.5, 1.0, 0.0));
+        let path = path.with_interpolation(VInterpolation::Bezier);
+        assert_zeq!(path.scale(f.currentf()), VTuple::point(0.5, 0.75, 0.0));
+
+        let fade = LinearScale::new().with_range(vec![VColor::black(), VColor::white()]);
+        assert_zeq!(fade.scale(0.5), VColor::new(0.5, 0.5, 0.5));
+    }
+    #[test]
+    fn animating_a_range_of_frames() {
+        let mut visited = vec![];
+        VAnimator::new(10).animate(|frame| visited.push(frame.current()));
+        assert_eq!(visited, (0..10).collect::<Vec<_>>());
+
+        let animator = VAnimator::new(10).with_frames(3..20).with_step(3);
+        let mut visited = vec![];
+        animator.animate(|frame| visited.push(frame.current()));
+        assert_eq!(visited, vec![3, 6, 9]);
+        assert_eq!(animator.frames()[0].output(), None);
+        assert!(animator.frames()[0].save(b"frame").is_err());
+    }
+    #[test]
+    fn animating_frames_in_parallel() {
+        let visited = std::sync::Mutex::new(vec![]);
+        VAnimator::new(20)
+            .with_step(2)
+            .animate_parallel(|frame| visited.lock().unwrap().push(frame.current()));
+
+        let mut visited = visited.into_inner().unwrap();
+        visited.sort();
+        assert_eq!(visited, (0..20).step_by(2).collect::<Vec<_>>());
+    }
+    #[test]
+    fn rendering_frames_in_parallel() {
+        use crate::body::VBody;
+        use crate::camera::VCamera;
+        use crate::light::VPointLight;
+        use crate::renderer::{render, RenderSettings};
+        use crate::sphere::VSphere;
+        use crate::world::VWorld;
+
+        let world = VWorld::new(
+            vec![VBody::from(VSphere::default())],
+            vec![VPointLight::new(
+                VTuple::point(-10.0, 10.0, -10.0),
+                VColor::white(),
+            )],
+        );
+        let camera = VCamera::new(16, 16, 1.0).positioned_and_pointed(
+            VTuple::point(0.0, 0.0, -5.0),
+            VTuple::point(0.0, 0.0, 0.0),
+            VTuple::vector(0.0, 1.0, 0.0),
+        );
+        // Every worker of the global pool renders a frame and its tiles
+        let rendered = std::sync::Mutex::new(vec![]);
+        VAnimator::new(16).animate_parallel(|frame| {
+            let settings = RenderSettings::default().with_tile_size(4);
+            let canvas = render(&world, &camera, &settings).canvas;
+            rendered
+                .lock()
+                .unwrap()
+                .push((frame.current(), canvas.pixel_at(8, 8)));
+        });
+
+        let mut rendered = rendered.into_inner().unwrap();
+        rendered.sort_by_key(|(current, _)| *current);
+        assert_eq!(rendered.len(), 16);
+        assert!(rendered.iter().all(|(_, color)| *color == rendered[0].1));
+        assert_ne!(rendered[0].1, VColor::black());
+    }
+    #[test]
+    fn resuming_skips_written_frames() {
+        let dir = std::env::temp_dir().join(format!("ray_tracer_frames_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let animator = VAnimator::new(4)
+            .with_output(dir.to_str().unwrap(), "frame", ".txt")
+            .with_skip_existing(true);
+
+        let frames = animator.frames();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(
+            frames[1].output(),
+            Some(dir.join("frame000001.txt").to_str().unwrap())
+        );
+        frames[1].save(b"done").unwrap();
+        frames[2].save(b"done").unwrap();
+
+        let mut visited = vec![];
+        animator.animate(|frame| visited.push(frame.current()));
+        assert_eq!(visited, vec![0, 3]);
+        assert_eq!(fs::read(dir.join("frame000002.txt")).unwrap(), b"done");
+        assert_eq!(animator.with_skip_existing(false).frames().len(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
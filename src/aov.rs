@@ -0,0 +1,191 @@
+use crate::canvas::vcanvas::VCanvas;
+use crate::canvas::vcolor::VColor;
+use crate::rng::VRng;
+use crate::world::VSurface;
+use crate::F;
+
+/// Arbitrary output variables, per pixel data rendered next to the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VAov {
+    /// Ray parameter of the hit.
+    Depth,
+    /// World space normal.
+    Normal,
+    /// Material color without lighting.
+    Albedo,
+    /// Index of the hit body in the world plus one, 0 where nothing is hit.
+    ObjectId,
+    /// 1 where the surface is in shadow.
+    Shadow,
+}
+impl VAov {
+    pub const ALL: [VAov; 5] = [
+        VAov::Depth,
+        VAov::Normal,
+        VAov::Albedo,
+        VAov::ObjectId,
+        VAov::Shadow,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VAov::Depth => "depth",
+            VAov::Normal => "normal",
+            VAov::Albedo => "albedo",
+            VAov::ObjectId => "object_id",
+            VAov::Shadow => "shadow",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        VAov::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+}
+
+/// The first surface seen through every pixel, `None` where the ray hits
+/// nothing. Rendered with [`render_aovs`](crate::renderer::render_aovs).
+#[derive(Debug, Clone, PartialEq)]
+pub struct VAovBuffers {
+    pub width: usize,
+    pub height: usize,
+    /// Row by row.
+    pub surfaces: Vec<Option<VSurface>>,
+}
+impl VAovBuffers {
+    pub fn surface_at(&self, x: usize, y: usize) -> Option<VSurface> {
+        self.surfaces[y * self.width + x]
+    }
+
+    /// Raw values of the variable, meant for HDR output. Single values are
+    /// repeated in all three channels, pixels without a surface are black.
+    pub fn canvas(&self, aov: VAov) -> VCanvas {
+        self.map(|s| match aov {
+            VAov::Depth => gray(s.t),
+            VAov::Normal => VColor::new(s.normal.x, s.normal.y, s.normal.z),
+            VAov::Albedo => s.albedo,
+            VAov::ObjectId => gray((s.body + 1) as F),
+            VAov::Shadow => gray(if s.shadowed { 1.0 } else { 0.0 }),
+        })
+    }
+    /// The variable squeezed into [0, 1] to be looked at as PNG: depth fades
+    /// from white for the nearest to dark gray for the farthest hit, normals
+    /// map from [-1, 1] and every object gets a color of its own.
+    pub fn preview(&self, aov: VAov) -> VCanvas {
+        match aov {
+            VAov::Depth => {
+                let depths = self.surfaces.iter().flatten().map(|s| s.t);
+                let near = depths.clone().fold(F::INFINITY, F::min);
+                let far = depths.fold(0.0, F::max);
+                let range = (far - near).max(F::EPSILON);
+                self.map(|s| gray(1.0 - 0.9 * (s.t - near) / range))
+            }
+            VAov::Normal => self.map(|s| {
+                VColor::new(
+                    0.5 + 0.5 * s.normal.x,
+                    0.5 + 0.5 * s.normal.y,
+                    0.5 + 0.5 * s.normal.z,
+                )
+            }),
+            VAov::ObjectId => self.map(|s| {
+                let mut rng = VRng::new(s.body as u64);
+                VColor::new(
+                    rng.next_in(0.2, 1.0),
+                    rng.next_in(0.2, 1.0),
+                    rng.next_in(0.2, 1.0),
+                )
+            }),
+            _ => self.canvas(aov),
+        }
+    }
+    fn map<M: Fn(&VSurface) -> VColor>(&self, color: M) -> VCanvas {
+        let mut canvas = VCanvas::new(self.width, self.height);
+        for (i, surface) in self.surfaces.iter().enumerate() {
+            if let Some(surface) = surface {
+                canvas.write_pixel(i % self.width, i / self.width, color(surface));
+            }
+        }
+        canvas
+    }
+}
+fn gray(value: F) -> VColor {
+    VColor::new(value, value, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuple::VTuple;
+    use crate::zequality::*;
+
+    fn surface(t: F, body: usize, shadowed: bool) -> Option<VSurface> {
+        Some(VSurface {
+            t,
+            normal: VTuple::vector(0.0, 0.0, -1.0),
+            albedo: VColor::new(0.8, 1.0, 0.6),
+            body,
+            shadowed,
+        })
+    }
+
+    fn buffers() -> VAovBuffers {
+        VAovBuffers {
+            width: 2,
+            height: 2,
+            surfaces: vec![
+                surface(4.0, 0, false),
+                None,
+                surface(6.0, 2, true),
+                surface(5.0, 0, false),
+            ],
+        }
+    }
+
+    #[test]
+    fn aovs_are_known_by_name() {
+        for aov in VAov::ALL {
+            assert_eq!(VAov::from_name(aov.name()), Some(aov));
+        }
+        assert_eq!(VAov::from_name("beauty"), None);
+    }
+
+    #[test]
+    fn raw_aov_values() {
+        let b = buffers();
+
+        assert_zeq!(
+            b.canvas(VAov::Depth).pixel_at(0, 1),
+            VColor::new(6.0, 6.0, 6.0)
+        );
+        assert_zeq!(b.canvas(VAov::Depth).pixel_at(1, 0), VColor::black());
+        assert_zeq!(
+            b.canvas(VAov::Normal).pixel_at(0, 0),
+            VColor::new(0.0, 0.0, -1.0)
+        );
+        assert_zeq!(
+            b.canvas(VAov::Albedo).pixel_at(1, 1),
+            VColor::new(0.8, 1.0, 0.6)
+        );
+        assert_zeq!(
+            b.canvas(VAov::ObjectId).pixel_at(0, 1),
+            VColor::new(3.0, 3.0, 3.0)
+        );
+        assert_zeq!(b.canvas(VAov::Shadow).pixel_at(0, 1), VColor::white());
+        assert_zeq!(b.canvas(VAov::Shadow).pixel_at(0, 0), VColor::black());
+    }
+
+    #[test]
+    fn aov_previews_fit_into_the_unit_range() {
+        let b = buffers();
+
+        let depth = b.preview(VAov::Depth);
+        assert_zeq!(depth.pixel_at(0, 0), VColor::white());
+        assert_zeq!(depth.pixel_at(0, 1), VColor::new(0.1, 0.1, 0.1));
+        assert_zeq!(
+            b.preview(VAov::Normal).pixel_at(0, 0),
+            VColor::new(0.5, 0.5, 0.0)
+        );
+        let ids = b.preview(VAov::ObjectId);
+        assert_eq!(ids.pixel_at(0, 0), ids.pixel_at(1, 1));
+        assert_ne!(ids.pixel_at(0, 0), ids.pixel_at(0, 1));
+        assert_eq!(ids.pixel_at(1, 0), VColor::black());
+    }
+}
@@ -0,0 +1,418 @@
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::vcanvas::{VCanvas, VImageError};
+use crate::canvas::vcolor::VColor;
+use crate::tuple::VTuple;
+use crate::F;
+
+/// What rays see that miss every body of the world.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum VBackground {
+    Solid(VColor),
+    /// Sky blending from `bottom` straight down to `top` straight up.
+    Gradient {
+        bottom: VColor,
+        top: VColor,
+    },
+    Environment(VEnvironmentMap),
+}
+impl Default for VBackground {
+    fn default() -> Self {
+        VBackground::Solid(VColor::black())
+    }
+}
+impl VBackground {
+    /// Color seen looking in `direction`, which does not need to be
+    /// normalized.
+    pub fn color_at(&self, direction: VTuple) -> VColor {
+        match self {
+            VBackground::Solid(color) => *color,
+            VBackground::Gradient { bottom, top } => {
+                let t = 0.5 * (direction.normalized().y + 1.0);
+                *bottom * (1.0 - t) + *top * t
+            }
+            VBackground::Environment(map) => map.color_at(direction),
+        }
+    }
+}
+
+/// Equirectangular image surrounding the world, usually an HDR panorama.
+/// The center of the image is seen looking along +z, the top row straight
+/// up. Scene files only store where the image was loaded from, it is read
+/// again when the scene is loaded, relative to the working directory.
+///
+/// With `light_samples` above 0 the map also lights the bodies, see
+/// [`VEnvironmentMap::sample`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "VEnvironmentSource", into = "VEnvironmentSource")]
+pub struct VEnvironmentMap {
+    pub path: PathBuf,
+    /// Multiplies the colors of the image.
+    pub strength: F,
+    /// Turn of the image around the y axis in radians.
+    pub rotation: F,
+    /// Shadow rays traced towards the map per shading point, 0 only shows
+    /// it as background.
+    pub light_samples: usize,
+    image: Arc<VCanvas>,
+    distribution: Arc<VDistribution2D>,
+}
+impl VEnvironmentMap {
+    /// Wraps an image that has no file, it is lost when the scene is saved.
+    pub fn new(image: VCanvas) -> Self {
+        let distribution = VDistribution2D::for_environment(&image);
+        Self {
+            path: PathBuf::new(),
+            strength: 1.0,
+            rotation: 0.0,
+            light_samples: 0,
+            image: Arc::new(image),
+            distribution: Arc::new(distribution),
+        }
+    }
+    /// Reads a PNG, PPM or HDR image.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VImageError> {
+        Ok(Self {
+            path: PathBuf::from(path.as_ref()),
+            ..Self::new(VCanvas::load(path)?)
+        })
+    }
+    pub fn with_strength(mut self, strength: F) -> Self {
+        self.strength = strength;
+        self
+    }
+    pub fn with_rotation(mut self, rotation: F) -> Self {
+        self.rotation = rotation;
+        self
+    }
+    pub fn with_light_samples(mut self, light_samples: usize) -> Self {
+        self.light_samples = light_samples;
+        self
+    }
+    pub fn image(&self) -> &VCanvas {
+        &self.image
+    }
+
+    /// Bilinearly filtered color in `direction`, wrapping around
+    /// horizontally.
+    pub fn color_at(&self, direction: VTuple) -> VColor {
+        let (width, height) = (self.image.width, self.image.height);
+        if width == 0 || height == 0 {
+            return VColor::black();
+        }
+        let (u, v) = self.uv_of(direction);
+        let x = u * width as F - 0.5;
+        let y = (v * height as F - 0.5).clamp(0.0, (height - 1) as F);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let column = |x: F| (x as isize).rem_euclid(width as isize) as usize;
+        let (left, right) = (column(x0), column(x0 + 1.0));
+        let (top, bottom) = (y0 as usize, (y0 as usize + 1).min(height - 1));
+        let row = |y: usize| {
+            self.image.pixel_at(left, y) * (1.0 - fx) + self.image.pixel_at(right, y) * fx
+        };
+        (row(top) * (1.0 - fy) + row(bottom) * fy) * self.strength
+    }
+    /// Position of a direction on the image, both in [0, 1].
+    pub fn uv_of(&self, direction: VTuple) -> (F, F) {
+        let d = direction.normalized();
+        let u = (0.5 + (d.x.atan2(d.z) - self.rotation) / (2.0 * PI)).rem_euclid(1.0);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+    /// The normalized direction of a position on the image, the inverse of
+    /// `uv_of`.
+    pub fn direction_of(&self, u: F, v: F) -> VTuple {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        VTuple::vector(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            theta.sin() * phi.cos(),
+        )
+    }
+    /// Picks a direction towards the map for two uniform random numbers,
+    /// bright pixels proportionally more often. Returns the direction, the
+    /// light arriving from it and the probability density of picking it per
+    /// solid angle.
+    pub fn sample(&self, u1: F, u2: F) -> (VTuple, VColor, F) {
+        let (width, height) = (self.image.width, self.image.height);
+        if width == 0 || height == 0 {
+            return (VTuple::vector(0.0, 1.0, 0.0), VColor::black(), 0.0);
+        }
+        let (x, y, pdf) = self.distribution.sample(u1, u2);
+        let (u, v) = (x / width as F, y / height as F);
+        let sin_theta = (v * PI).sin();
+        let color = self
+            .image
+            .pixel_at((x as usize).min(width - 1), (y as usize).min(height - 1));
+        // From density per pixel area to density per solid angle
+        let pdf = if sin_theta > 0.0 {
+            pdf / (2.0 * PI * PI * sin_theta)
+        } else {
+            0.0
+        };
+        (self.direction_of(u, v), color * self.strength, pdf)
+    }
+}
+impl PartialEq for VEnvironmentMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.strength == other.strength
+            && self.rotation == other.rotation
+            && self.light_samples == other.light_samples
+            && (Arc::ptr_eq(&self.image, &other.image) || self.image == other.image)
+    }
+}
+
+// How an environment map is written to scene files.
+#[derive(Serialize, Deserialize)]
+struct VEnvironmentSource {
+    path: PathBuf,
+    #[serde(default = "full_strength")]
+    strength: F,
+    #[serde(default)]
+    rotation: F,
+    #[serde(default)]
+    light_samples: usize,
+}
+fn full_strength() -> F {
+    1.0
+}
+impl TryFrom<VEnvironmentSource> for VEnvironmentMap {
+    type Error = String;
+
+    fn try_from(source: VEnvironmentSource) -> Result<Self, Self::Error> {
+        let map = VEnvironmentMap::load(&source.path)
+            .map_err(|e| format!("{}: {}", source.path.display(), e))?;
+        Ok(map
+            .with_strength(source.strength)
+            .with_rotation(source.rotation)
+            .with_light_samples(source.light_samples))
+    }
+}
+impl From<VEnvironmentMap> for VEnvironmentSource {
+    fn from(map: VEnvironmentMap) -> Self {
+        Self {
+            path: map.path,
+            strength: map.strength,
+            rotation: map.rotation,
+            light_samples: map.light_samples,
+        }
+    }
+}
+
+// Piecewise constant density over the pixels of an image, sampled by
+// picking a row from the marginal distribution, then a column in that row.
+#[derive(Debug)]
+struct VDistribution2D {
+    width: usize,
+    height: usize,
+    /// Cumulative weight of the rows, `height + 1` entries from 0.
+    marginal: Vec<F>,
+    /// Cumulative weight of the pixels of every row, `width + 1` entries each.
+    conditional: Vec<Vec<F>>,
+}
+impl VDistribution2D {
+    // Weights pixels by luminance and by the solid angle they cover, which
+    // shrinks towards the poles. Black images fall back to even weights.
+    fn for_environment(image: &VCanvas) -> Self {
+        let (width, height) = (image.width, image.height);
+        let weight = |x: usize, y: usize| {
+            let c = image.pixel_at(x, y);
+            let luminance = 0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b;
+            luminance.max(0.0) * ((y as F + 0.5) / height as F * PI).sin()
+        };
+        let mut weights: Vec<Vec<F>> = (0..height)
+            .map(|y| (0..width).map(|x| weight(x, y)).collect())
+            .collect();
+        if weights.iter().flatten().all(|w| *w <= 0.0) {
+            weights = (0..height)
+                .map(|y| vec![((y as F + 0.5) / height as F * PI).sin(); width])
+                .collect();
+        }
+        let conditional: Vec<Vec<F>> = weights.iter().map(|row| cumulative(row)).collect();
+        let row_weights: Vec<F> = conditional.iter().map(|row| row[width]).collect();
+        Self {
+            width,
+            height,
+            marginal: cumulative(&row_weights),
+            conditional,
+        }
+    }
+    // Continuous pixel coordinates and the density per unit of image area.
+    fn sample(&self, u1: F, u2: F) -> (F, F, F) {
+        let total = self.marginal[self.height];
+        let (y, fy) = pick(&self.marginal, u1);
+        let row = &self.conditional[y];
+        let (x, fx) = pick(row, u2);
+        let weight = row[x + 1] - row[x];
+        let pdf = weight / total * (self.width * self.height) as F;
+        (x as F + fx, y as F + fy, pdf)
+    }
+}
+fn cumulative(weights: &[F]) -> Vec<F> {
+    let mut sums = Vec::with_capacity(weights.len() + 1);
+    sums.push(0.0);
+    for w in weights.iter() {
+        sums.push(sums[sums.len() - 1] + w);
+    }
+    sums
+}
+// Index of the interval of a cumulative distribution holding `u` times its
+// total, and how far into the interval it is. Empty intervals are never
+// picked.
+fn pick(cdf: &[F], u: F) -> (usize, F) {
+    let total = cdf[cdf.len() - 1];
+    // Staying below the total keeps trailing empty intervals out
+    let target = (u.clamp(0.0, 1.0) * total).min(total * (1.0 - F::EPSILON));
+    let index = cdf.partition_point(|sum| *sum <= target).max(1) - 1;
+    let width = cdf[index + 1] - cdf[index];
+    let offset = if width > 0.0 {
+        ((target - cdf[index]) / width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    (index, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::to_hdr::ToHDR;
+    use crate::zequality::*;
+
+    // Left half red, right half blue, the top row white.
+    fn panorama() -> VCanvas {
+        let mut c = VCanvas::new(8, 4);
+        for y in 0..4 {
+            for x in 0..8 {
+                let color = match (x, y) {
+                    (_, 0) => VColor::white(),
+                    (0..=3, _) => VColor::red(),
+                    _ => VColor::blue(),
+                };
+                c.write_pixel(x, y, color);
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn solid_and_gradient_backgrounds() {
+        let up = VTuple::vector(0.0, 3.0, 0.0);
+        let side = VTuple::vector(1.0, 0.0, 0.0);
+        let gradient = VBackground::Gradient {
+            bottom: VColor::black(),
+            top: VColor::new(0.4, 0.6, 1.0),
+        };
+
+        assert_zeq!(VBackground::default().color_at(up), VColor::black());
+        assert_zeq!(
+            VBackground::Solid(VColor::red()).color_at(side),
+            VColor::red()
+        );
+        assert_zeq!(gradient.color_at(up), VColor::new(0.4, 0.6, 1.0));
+        assert_zeq!(gradient.color_at(side), VColor::new(0.2, 0.3, 0.5));
+    }
+
+    #[test]
+    fn directions_map_onto_the_panorama() {
+        let map = VEnvironmentMap::new(panorama());
+
+        let (u, v) = map.uv_of(VTuple::vector(0.0, 0.0, 1.0));
+        assert_zeq!(u, 0.5);
+        assert_zeq!(v, 0.5);
+        assert_zeq!(map.uv_of(VTuple::vector(1.0, 0.0, 0.0)).0, 0.75);
+        assert_zeq!(map.uv_of(VTuple::vector(0.0, 0.0, -1.0)).0, 0.0);
+        assert_zeq!(map.uv_of(VTuple::vector(0.0, -2.0, 0.0)).1, 1.0);
+        let turned = VEnvironmentMap::new(panorama()).with_rotation(PI / 2.0);
+        assert_zeq!(turned.uv_of(VTuple::vector(1.0, 0.0, 0.0)).0, 0.5);
+    }
+
+    #[test]
+    fn looking_up_environment_colors() {
+        let map = VEnvironmentMap::new(panorama()).with_strength(2.0);
+
+        assert_zeq!(
+            map.color_at(VTuple::vector(-1.0, -0.3, 0.0)),
+            VColor::new(2.0, 0.0, 0.0)
+        );
+        assert_zeq!(
+            map.color_at(VTuple::vector(1.0, -0.3, 0.0)),
+            VColor::new(0.0, 0.0, 2.0)
+        );
+        assert_zeq!(
+            map.color_at(VTuple::vector(0.0, 1.0, 0.0)),
+            VColor::new(2.0, 2.0, 2.0)
+        );
+        // Halfway between the red and blue columns, across the seam as well
+        assert_zeq!(
+            map.color_at(VTuple::vector(0.0, -0.3, 1.0)),
+            VColor::new(1.0, 0.0, 1.0)
+        );
+        assert_zeq!(
+            map.color_at(VTuple::vector(0.0, -0.3, -1.0)),
+            VColor::new(1.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn directions_and_image_positions_convert_both_ways() {
+        let map = VEnvironmentMap::new(panorama()).with_rotation(0.7);
+        let d = VTuple::vector(0.3, -0.5, -0.8).normalized();
+        let (u, v) = map.uv_of(d);
+
+        assert_zeq!(map.direction_of(u, v), d);
+    }
+
+    #[test]
+    fn sampling_prefers_bright_pixels() {
+        let mut image = VCanvas::new(16, 8);
+        for y in 0..8 {
+            for x in 0..16 {
+                image.write_pixel(x, y, VColor::new(0.1, 0.1, 0.1));
+            }
+        }
+        image.write_pixel(11, 5, VColor::new(50.0, 50.0, 50.0));
+        let map = VEnvironmentMap::new(image);
+        let mut rng = crate::rng::VRng::new(7);
+
+        let count = 20000;
+        let mut bright = 0;
+        let mut solid_angle = 0.0;
+        for _ in 0..count {
+            let (direction, color, pdf) = map.sample(rng.next_f(), rng.next_f());
+            let (u, v) = map.uv_of(direction);
+            if ((u * 16.0) as usize, (v * 8.0) as usize) == (11, 5) {
+                assert_zeq!(color, VColor::new(50.0, 50.0, 50.0));
+                bright += 1;
+            }
+            solid_angle += 1.0 / pdf;
+        }
+        // The bright pixel outweighs the rest of the map put together
+        assert!(bright > count / 2);
+        // Every density integrates to the whole sphere
+        assert!((solid_angle / count as F - 4.0 * PI).abs() < 0.05 * 4.0 * PI);
+    }
+
+    #[test]
+    fn environment_maps_are_saved_by_path() {
+        let path = std::env::temp_dir().join(format!("ray_tracer_env_{}.hdr", std::process::id()));
+        std::fs::write(&path, panorama().to_hdr()).unwrap();
+        let map = VEnvironmentMap::load(&path).unwrap();
+        let background = VBackground::Environment(map.with_rotation(1.0).with_light_samples(16));
+
+        let json = serde_json::to_string(&background).unwrap();
+        assert!(!json.contains("image"));
+        let read: VBackground = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, background);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(serde_json::from_str::<VBackground>(&json).is_err());
+    }
+}
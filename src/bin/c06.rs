@@ -1,20 +1,17 @@
 use indicatif::ProgressBar;
-use itertools::Itertools;
 use ray_tracer::light::VPointLight;
 use ray_tracer::material::Illuminated;
 use ray_tracer::material::VMaterial;
 use ray_tracer::material::VPhong;
-use std::sync::Mutex;
 
 use ray_tracer::body::VIntersectable;
 use ray_tracer::canvas::to_png::ToPNG;
-use ray_tracer::canvas::vcanvas::*;
 use ray_tracer::canvas::vcolor::VColor;
 use ray_tracer::ray::*;
+use ray_tracer::renderer::*;
 use ray_tracer::sphere::*;
 use ray_tracer::tuple::*;
 use ray_tracer::F;
-use rayon::prelude::*;
 use std::fs::write;
 
 macro_rules! time_it {
@@ -34,7 +31,6 @@ fn ray_trace(canvas_size: usize) {
     let wall_size = 10.0;
 
     let canvas_pixel_world_size = wall_size / canvas_size as F;
-    let canvas_mutex = Mutex::new(VCanvas::new(canvas_size, canvas_size));
 
     let material = VMaterial::from(VPhong::default().with_color(VColor::red()));
     let sphere = VSphere::default().with_material(material);
@@ -51,10 +47,11 @@ fn ray_trace(canvas_size: usize) {
     let progress = ProgressBar::new(canvas_size.pow(2) as u64);
     progress.set_draw_rate(2);
 
-    (0..canvas_size)
-        .cartesian_product(0..canvas_size)
-        .par_bridge()
-        .for_each(|(x, y)| {
+    let canvas = render_pixels(
+        canvas_size,
+        canvas_size,
+        &RenderSettings::default(),
+        |x, y| {
             let half = wall_size / 2.0;
             let world_x = -half + canvas_pixel_world_size * x as f64;
             let world_y = half - canvas_pixel_world_size * y as f64;
@@ -62,21 +59,21 @@ fn ray_trace(canvas_size: usize) {
             let ray = VRay::new(ray_origin, (wall_point - ray_origin).normalized());
             let xs = sphere.intersect(ray);
 
-            let hit = xs.hit();
-            
-            if let Some(hit)=hit{
-                let pos=ray.position(hit.t);
-                let normal = hit.body.normal_at(pos);
-                let camv = -ray.direction;
-                let col= hit.body.material().lighting(light_source,pos,camv,normal,false);
-                let mut canvas = canvas_mutex.lock().unwrap();
-                canvas.write_pixel(x, y, col);
+            match xs.hit() {
+                Some(hit) => {
+                    let pos = ray.position(hit.t);
+                    let normal = hit.body.normal_at(pos);
+                    let camv = -ray.direction;
+                    hit.body.material().lighting(light_source, pos, camv, normal, false)
+                }
+                None => VColor::black(),
             }
-            progress.inc(1);
-        });
+        },
+        |done, _| progress.set_position(done as u64),
+    )
+    .canvas;
     progress.finish();
     println!("Writing ./output.png");
-    let canvas = canvas_mutex.lock().unwrap();
     let byte_array = canvas.to_png();
     write("output.png", byte_array).expect("Could not write output.png to disk");
 }
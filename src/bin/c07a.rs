@@ -1,113 +1,157 @@
 use indicatif::ProgressBar;
-use itertools::Itertools;
+use ray_tracer::animation::*;
 use ray_tracer::animator::*;
 use ray_tracer::body::VBody;
 use ray_tracer::camera::VCamera;
+use ray_tracer::canvas::animated::VAnimatedImage;
 use ray_tracer::canvas::to_png::ToPNG;
-use ray_tracer::canvas::vcanvas::*;
+use ray_tracer::canvas::vcanvas::VCanvas;
 use ray_tracer::canvas::vcolor::VColor;
 use ray_tracer::light::VPointLight;
 use ray_tracer::material::VMaterial;
 use ray_tracer::material::VPhong;
 use ray_tracer::matrix::VMatrix;
+use ray_tracer::renderer::*;
+use ray_tracer::scene::VScene;
 use ray_tracer::sphere::*;
 use ray_tracer::tuple::*;
 use ray_tracer::world::VWorld;
-use rayon::prelude::*;
+use ray_tracer::F;
 use std::f64::consts::PI;
-use std::fs::write;
-use std::sync::Mutex;
 
-//https://ezgif.com/apng-maker
+const FRAME_COUNT: usize = 100;
 
 fn main() {
-    let animator = VAnimator::new(100);
+    let scene = animated_scene();
+    // Frames written by an earlier, interrupted run are kept
+    let animator = VAnimator::new(FRAME_COUNT)
+        .with_output("animation", "output", ".png")
+        .with_skip_existing(true);
     animator.animate(|frame| {
-        //World params
-        let canvas_width = 600;
-        let canvas_height = 400;
+        let scene = scene.at_frame(frame.currentf());
+        let camera = scene.cameras["camera"];
+        let pixel_count = camera.hsize * camera.vsize;
 
-        let light_rotation = frame.linear_scale().with_range(vec![0.0, PI * 2.0]);
-        let light_transform = VMatrix::rotation_y(light_rotation.scale(frame.currentf()));
-        let light = VPointLight::new(
-            light_transform * VTuple::point(-3.0, 15.0, -3.0),
-            VColor::new(0.9, 0.9, 0.9),
-        );
-
-        let pixel_count = canvas_width * canvas_height;
-        let canvas_mutex = Mutex::new(VCanvas::new(canvas_width, canvas_height));
-        let camera = VCamera::new(canvas_width, canvas_height, PI / 3.0).positioned_and_pointed(
-            VTuple::point(-10.0, 10.0, -10.0),
-            VTuple::point(0.0, 0.0, 0.0),
-            VTuple::vector(0.0, 1.0, 0.0),
-        );
+        println!("Raytracing {} pixels. Please be patient...", pixel_count);
+        let progress = ProgressBar::new(pixel_count as u64);
+        progress.set_draw_rate(2);
+        let settings = RenderSettings::default();
+        let canvas = render_with_progress(&scene.world, &camera, &settings, |done, _| {
+            progress.set_position(done as u64)
+        })
+        .canvas;
+        progress.finish();
 
-        //World objects
-        let material1 = VMaterial::from(VPhong {
-            col: VColor::red(),
-            ..VPhong::default()
-        });
-        let sphere1 = VSphere::default()
-            .with_material(material1)
-            .with_transform(VMatrix::translation(-3.0, 1.0, -2.0));
+        let filename = frame.output().unwrap_or_default();
+        println!("Writing {filename:?}");
+        frame
+            .save(&canvas.to_png())
+            .unwrap_or_else(|e| panic!("Couldnt write {filename:?} to disk: {e}"));
+    });
 
-        let material2 = VMaterial::from(VPhong {
-            col: VColor::green(),
-            ..VPhong::default()
-        });
+    // Frames rendered by earlier runs were skipped, so all are read back
+    let camera = scene.cameras["camera"];
+    let mut animation = VAnimatedImage::new(camera.hsize, camera.vsize);
+    for frame in animator.with_skip_existing(false).frames() {
+        let filename = frame.output().unwrap_or_default();
+        let canvas =
+            VCanvas::load(filename).unwrap_or_else(|e| panic!("Couldnt read {filename:?}: {e}"));
+        animation.add_frame(&frame, &canvas).unwrap();
+    }
+    for filename in ["animation/animation.png", "animation/animation.gif"] {
+        println!("Writing {filename:?}");
+        animation
+            .save(filename)
+            .unwrap_or_else(|e| panic!("Couldnt write {filename:?} to disk: {e}"));
+    }
+}
 
-        let sphere2_translation = frame.linear_scale().with_range(vec![0.0, 5.0,0.0,5.0]);
-        let sphere2_transform = VMatrix::translation(0.0,sphere2_translation.scale(frame.currentf()),0.0);
-        let sphere2 = VSphere::default()
-            .with_material(material2)
-            .with_transform(sphere2_transform*VMatrix::translation(-3.0, 1.0, -6.0));
+fn animated_scene() -> VScene {
+    //World params
+    let canvas_width = 600;
+    let canvas_height = 400;
+    let last_frame = FRAME_COUNT as F;
 
-        let wall_mat = VMaterial::from(VPhong {
-            col: VColor::new(0.2, 0.2, 0.2),
-            spc: 0.0,
-            ..VPhong::default()
-        });
-        let floor = VSphere::default()
-            .with_material(wall_mat)
-            .with_transform(VMatrix::translation(0.0, 0.0, 0.0)*VMatrix::scaling(20.0, 0.01, 20.0));
-        let wall1 = VSphere::default()
-            .with_material(wall_mat)
-            .with_transform(VMatrix::translation(5.0, 0.0, 0.0)*VMatrix::scaling(0.01, 20.0, 20.0));
-        let wall2 = VSphere::default()
-            .with_material(wall_mat)
-            .with_transform(VMatrix::translation(0.0, 0.0, 5.0)*VMatrix::scaling(20.0, 20.0, 0.01));
+    let light = VPointLight::new(VTuple::point(-3.0, 15.0, -3.0), VColor::new(0.9, 0.9, 0.9));
+    // One turn around the y axis, smoothed into a circle
+    let mut light_path = VTrack::default().with_interpolation(VInterpolation::CatmullRom);
+    for step in 0..=8 {
+        let turn = step as F / 8.0;
+        let position = VMatrix::rotation_y(turn * PI * 2.0) * light.pos;
+        light_path = light_path.with_key(turn * last_frame, position);
+    }
 
-        let world = VWorld::new(
-            vec![
-                VBody::from(sphere1),
-                VBody::from(sphere2),
-                VBody::from(floor),
-                VBody::from(wall1),
-                VBody::from(wall2),
-            ],
-            vec![light],
-        );
+    let camera = VCamera::new(canvas_width, canvas_height, PI / 3.0).positioned_and_pointed(
+        VTuple::point(-10.0, 10.0, -10.0),
+        VTuple::point(0.0, 0.0, 0.0),
+        VTuple::vector(0.0, 1.0, 0.0),
+    );
 
-        println!("Raytracing {} pixels. Please be patient...", pixel_count);
-        let progress = ProgressBar::new(pixel_count as u64);
-        progress.set_draw_rate(2);
-        (0..canvas_width) //x
-            .cartesian_product(0..canvas_height) //y
-            .par_bridge()
-            .for_each(|(x, y)| {
-                let ray = camera.ray_for_pixel(x, y);
+    //World objects
+    let material1 = VMaterial::from(VPhong {
+        col: VColor::red(),
+        ..VPhong::default()
+    });
+    let sphere1 = VSphere::default()
+        .with_material(material1)
+        .with_transform(VMatrix::translation(-3.0, 1.0, -2.0));
 
-                let col = world.color_at(ray);
-                let mut canvas = canvas_mutex.lock().unwrap();
-                canvas.write_pixel(x, y, col);
-                progress.inc(1);
-            });
-        progress.finish();
+    let material2 = VMaterial::from(VPhong {
+        col: VColor::green(),
+        ..VPhong::default()
+    });
+    let sphere2 = VSphere::default()
+        .with_material(material2)
+        .with_transform(VMatrix::translation(-3.0, 1.0, -6.0));
+    let mut sphere2_bounce = VTrack::default();
+    for (step, height) in [0.0, 5.0, 0.0, 5.0].into_iter().enumerate() {
+        sphere2_bounce = sphere2_bounce.with_key(
+            step as F / 3.0 * last_frame,
+            VTuple::vector(0.0, height, 0.0),
+        );
+    }
 
-        let filename = frame.filename("animation", "output", ".png");
-        println!("Writing {filename:?}");
-        let canvas = canvas_mutex.lock().unwrap();
-        let byte_array = canvas.to_png();
-        write(filename, byte_array).expect("Couldnt write {filename:?} to disk");
+    let wall_mat = VMaterial::from(VPhong {
+        col: VColor::new(0.2, 0.2, 0.2),
+        spc: 0.0,
+        ..VPhong::default()
     });
+    let floor = VSphere::default()
+        .with_material(wall_mat)
+        .with_transform(VMatrix::scaling(20.0, 0.01, 20.0));
+    let wall1 = VSphere::default()
+        .with_material(wall_mat)
+        .with_transform(VMatrix::translation(5.0, 0.0, 0.0) * VMatrix::scaling(0.01, 20.0, 20.0));
+    let wall2 = VSphere::default()
+        .with_material(wall_mat)
+        .with_transform(VMatrix::translation(0.0, 0.0, 5.0) * VMatrix::scaling(20.0, 20.0, 0.01));
+
+    let world = VWorld::new(
+        vec![
+            VBody::from(sphere1),
+            VBody::from(sphere2),
+            VBody::from(floor),
+            VBody::from(wall1),
+            VBody::from(wall2),
+        ],
+        vec![light],
+    );
+    let animation = VAnimation::new()
+        .with_light(
+            0,
+            VLightKeys {
+                position: light_path,
+                ..VLightKeys::default()
+            },
+        )
+        .with_body(
+            1,
+            VBodyKeys {
+                translation: sphere2_bounce,
+                ..VBodyKeys::default()
+            },
+        );
+    VScene::new(world)
+        .with_camera("camera", camera)
+        .with_animation(animation)
 }
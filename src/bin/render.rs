@@ -0,0 +1,474 @@
+use indicatif::ProgressBar;
+use ray_tracer::aov::VAov;
+use ray_tracer::background::VBackground;
+use ray_tracer::canvas::to_hdr::ToHDR;
+use ray_tracer::canvas::to_png::ToPNG;
+use ray_tracer::canvas::to_ppm::ToPPM;
+use ray_tracer::canvas::tone_map::*;
+use ray_tracer::checkpoint::render_resumable;
+use ray_tracer::renderer::*;
+use ray_tracer::scene::VScene;
+use std::fs::{remove_file, write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: render <scene> [options]
+
+Renders a scene file (.ron, .json, .yml or .yaml).
+
+Options:
+  -o, --output <file>     output image, defaults to the scene name with .png
+  -f, --format <format>   png, ppm or hdr, defaults to the output file
+                          extension, ppm images are binary
+      --depth <bits>      bits per channel of png images, 8 or 16
+      --tone-map <curve>  clamp, reinhard or aces, defaults to clamp
+      --exposure <stops>  brightens or darkens the image before tone mapping
+      --srgb              gamma encodes the image for sRGB displays, hdr
+                          images are always written without tone mapping
+      --transparent       leaves the background transparent in png images
+      --premultiply       multiplies the colors of png images with their
+                          alpha
+  -W, --width <pixels>    overrides the camera width
+  -H, --height <pixels>   overrides the camera height, if only one of width
+                          and height is given the aspect ratio is kept
+  -c, --camera <name>     camera to render, needed if the scene has several
+  -s, --samples <count>   samples per pixel, defaults to 1
+  -t, --threads <count>   worker threads, defaults to one per core
+      --aov <names>       also writes the depth, normal, albedo, object_id or
+                          shadow of the first hit as separate images next to
+                          the output, comma separated or all. Raw values in
+                          hdr images, scaled to be viewable otherwise
+      --jitter            places the samples randomly inside each pixel
+      --seed <number>     seed for the random numbers, defaults to 0
+      --checkpoint <file> saves the progress to the file and resumes from
+                          it if it exists, it is removed once done
+      --checkpoint-interval <seconds>
+                          time between checkpoints, defaults to 60
+  -q, --quiet             print nothing but errors
+      --no-progress       do not show a progress bar
+  -h, --help              show this help";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Png,
+    Ppm,
+    Hdr,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    scene: PathBuf,
+    output: PathBuf,
+    format: Format,
+    tone_map: VToneMap,
+    deep: bool,
+    width: Option<usize>,
+    height: Option<usize>,
+    camera: Option<String>,
+    samples: usize,
+    threads: Option<usize>,
+    aovs: Vec<VAov>,
+    jitter: bool,
+    seed: u64,
+    transparent: bool,
+    checkpoint: Option<PathBuf>,
+    checkpoint_interval: usize,
+    quiet: bool,
+    progress: bool,
+}
+
+// Returns None if help was requested.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Options>, String> {
+    let mut scene = None;
+    let mut output = None;
+    let mut format = None;
+    let mut tone_map = VToneMap::default();
+    let mut deep = None;
+    let mut width = None;
+    let mut height = None;
+    let mut camera = None;
+    let mut samples = 1;
+    let mut threads = None;
+    let mut aovs = vec![];
+    let mut jitter = false;
+    let mut seed = 0;
+    let mut transparent = false;
+    let mut checkpoint = None;
+    let mut checkpoint_interval = 60;
+    let mut quiet = false;
+    let mut progress = true;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("{} expects a value", name))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => output = Some(PathBuf::from(value(&arg)?)),
+            "-f" | "--format" => format = Some(parse_format(&value(&arg)?)?),
+            "--depth" => match value(&arg)?.as_str() {
+                "8" => deep = Some(false),
+                "16" => deep = Some(true),
+                depth => return Err(format!("{} expects 8 or 16, got {:?}", arg, depth)),
+            },
+            "--tone-map" => tone_map.operator = parse_operator(&value(&arg)?)?,
+            "--exposure" => {
+                let text = value(&arg)?;
+                tone_map.exposure = text
+                    .parse()
+                    .map_err(|_| format!("{} expects a number, got {:?}", arg, text))?
+            }
+            "--srgb" => tone_map.srgb = true,
+            "--transparent" => transparent = true,
+            "--premultiply" => tone_map.premultiplied = true,
+            "-W" | "--width" => width = Some(parse_count(&arg, &value(&arg)?)?),
+            "-H" | "--height" => height = Some(parse_count(&arg, &value(&arg)?)?),
+            "-c" | "--camera" => camera = Some(value(&arg)?),
+            "-s" | "--samples" => samples = parse_count(&arg, &value(&arg)?)?,
+            "-t" | "--threads" => threads = Some(parse_count(&arg, &value(&arg)?)?),
+            "--aov" => aovs = parse_aovs(&value(&arg)?)?,
+            "--jitter" => jitter = true,
+            "--seed" => {
+                let text = value(&arg)?;
+                seed = text
+                    .parse()
+                    .map_err(|_| format!("{} expects a number, got {:?}", arg, text))?
+            }
+            "--checkpoint" => checkpoint = Some(PathBuf::from(value(&arg)?)),
+            "--checkpoint-interval" => checkpoint_interval = parse_count(&arg, &value(&arg)?)?,
+            "-q" | "--quiet" => quiet = true,
+            "--no-progress" => progress = false,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if scene.is_none() => scene = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    let scene: PathBuf = scene.ok_or("no scene file given")?;
+    let output = output.unwrap_or_else(|| scene.with_extension("png"));
+    let format = match format {
+        Some(format) => format,
+        None => format_of(&output)?,
+    };
+    // Only PNG images have an alpha channel and a choice of depth
+    if format != Format::Png {
+        let png_only = [
+            ("--transparent", transparent),
+            ("--premultiply", tone_map.premultiplied),
+            ("--depth", deep.is_some()),
+        ];
+        if let Some((name, _)) = png_only.iter().find(|(_, given)| *given) {
+            return Err(format!("{} only applies to png images", name));
+        }
+    }
+    Ok(Some(Options {
+        scene,
+        output,
+        format,
+        tone_map,
+        deep: deep.unwrap_or(false),
+        width,
+        height,
+        camera,
+        samples,
+        threads,
+        aovs,
+        jitter,
+        seed,
+        transparent,
+        checkpoint,
+        checkpoint_interval,
+        quiet,
+        progress: progress && !quiet,
+    }))
+}
+fn parse_format(name: &str) -> Result<Format, String> {
+    match name.to_lowercase().as_str() {
+        "png" => Ok(Format::Png),
+        "ppm" => Ok(Format::Ppm),
+        "hdr" => Ok(Format::Hdr),
+        _ => Err(format!(
+            "unknown image format {:?}, use png, ppm or hdr",
+            name
+        )),
+    }
+}
+fn parse_operator(name: &str) -> Result<VToneOperator, String> {
+    match name.to_lowercase().as_str() {
+        "clamp" => Ok(VToneOperator::Clamp),
+        "reinhard" => Ok(VToneOperator::Reinhard),
+        "aces" => Ok(VToneOperator::Aces),
+        _ => Err(format!(
+            "unknown tone map {:?}, use clamp, reinhard or aces",
+            name
+        )),
+    }
+}
+fn parse_aovs(names: &str) -> Result<Vec<VAov>, String> {
+    if names == "all" {
+        return Ok(VAov::ALL.to_vec());
+    }
+    names
+        .split(',')
+        .map(|name| {
+            VAov::from_name(name).ok_or_else(|| format!("unknown output variable {:?}", name))
+        })
+        .collect()
+}
+fn format_of(path: &Path) -> Result<Format, String> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    parse_format(ext)
+        .map_err(|_| format!("can not tell the image format of {:?}, pass --format", path))
+}
+fn parse_count(name: &str, value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!(
+            "{} expects a positive number, got {:?}",
+            name, value
+        )),
+    }
+}
+fn run(options: Options) -> Result<(), String> {
+    let scene =
+        VScene::load(&options.scene).map_err(|e| format!("{}: {}", options.scene.display(), e))?;
+    // An environment map sampled for lighting is enough to light the scene
+    let lit_by_environment = matches!(
+        &scene.world.background,
+        VBackground::Environment(map) if map.light_samples > 0
+    );
+    if scene.world.lights.is_empty() && !lit_by_environment {
+        return Err(format!(
+            "{}: the scene has no lights",
+            options.scene.display()
+        ));
+    }
+    let camera = scene
+        .camera(options.camera.as_deref())
+        .map_err(|e| format!("{}: {}", options.scene.display(), e))?;
+    let camera = match (options.width, options.height) {
+        (Some(w), Some(h)) => camera.resized(w, h),
+        (Some(w), None) => camera.resized(w, (w * camera.vsize / camera.hsize).max(1)),
+        (None, Some(h)) => camera.resized((h * camera.hsize / camera.vsize).max(1), h),
+        (None, None) => *camera,
+    };
+    let pixel_count = camera.hsize * camera.vsize;
+    if !options.quiet {
+        println!(
+            "Raytracing {}x{} pixels with {} samples each...",
+            camera.hsize, camera.vsize, options.samples
+        );
+    }
+    let progress = if options.progress {
+        ProgressBar::new(pixel_count as u64)
+    } else {
+        ProgressBar::hidden()
+    };
+    progress.set_draw_rate(2);
+    let mut settings = RenderSettings::default()
+        .with_samples(options.samples)
+        .with_jitter(options.jitter)
+        .with_seed(options.seed)
+        .with_transparent_background(options.transparent);
+    settings.threads = options.threads;
+    settings
+        .thread_pool()
+        .map_err(|e| format!("could not start worker threads: {}", e))?;
+    let canvas = match &options.checkpoint {
+        Some(path) => {
+            settings = settings
+                .with_checkpoint_interval(Duration::from_secs(options.checkpoint_interval as u64));
+            let outcome = render_resumable(&scene.world, &camera, &settings, path, |update| {
+                progress.set_length((update.passes * update.tiles) as u64);
+                progress.set_position((update.pass * update.tiles + update.tiles_done) as u64);
+                true
+            })
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+            remove_file(path).map_err(|e| format!("could not remove {}: {}", path.display(), e))?;
+            outcome.canvas
+        }
+        None => {
+            render_with_progress(&scene.world, &camera, &settings, |done, _| {
+                progress.set_position(done as u64)
+            })
+            .canvas
+        }
+    };
+    progress.finish_and_clear();
+
+    let data = match options.format {
+        Format::Png if options.deep => canvas.to_png16_with(&options.tone_map),
+        Format::Png => canvas.to_png_with(&options.tone_map),
+        Format::Ppm => canvas.to_binary_ppm_with(&options.tone_map),
+        Format::Hdr => canvas.to_hdr(),
+    };
+    write_image(&options.output, data, options.quiet)?;
+
+    if !options.aovs.is_empty() {
+        let aovs = render_aovs(&scene.world, &camera, &settings);
+        let ext = options.output.extension().and_then(|ext| ext.to_str());
+        for aov in options.aovs.iter() {
+            let data = match options.format {
+                Format::Png => aovs.preview(*aov).to_png(),
+                Format::Ppm => aovs.preview(*aov).to_binary_ppm(),
+                Format::Hdr => aovs.canvas(*aov).to_hdr(),
+            };
+            let path = match ext {
+                Some(ext) => options
+                    .output
+                    .with_extension(format!("{}.{}", aov.name(), ext)),
+                None => options.output.with_extension(aov.name()),
+            };
+            write_image(&path, data, options.quiet)?;
+        }
+    }
+    Ok(())
+}
+fn write_image(path: &Path, data: Vec<u8>, quiet: bool) -> Result<(), String> {
+    write(path, data).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    if !quiet {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+    if let Err(message) = run(options) {
+        eprintln!("error: {}", message);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Option<Options>, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn defaults_are_derived_from_the_scene() {
+        let options = parse("scenes/spheres.yml").unwrap().unwrap();
+
+        assert_eq!(options.output, PathBuf::from("scenes/spheres.png"));
+        assert_eq!(options.format, Format::Png);
+        assert_eq!(options.samples, 1);
+        assert!(options.progress);
+    }
+
+    #[test]
+    fn all_options_are_parsed() {
+        let options = parse("-o out.img -f ppm -W 640 --height 480 -c top -s 4 -t 2 -q scene.ron")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(options.output, PathBuf::from("out.img"));
+        assert_eq!(options.format, Format::Ppm);
+        assert_eq!((options.width, options.height), (Some(640), Some(480)));
+        assert_eq!(options.camera.as_deref(), Some("top"));
+        assert_eq!((options.samples, options.threads), (4, Some(2)));
+        assert_eq!(options.checkpoint, None);
+        assert!(!options.jitter);
+        assert!(options.quiet && !options.progress);
+    }
+
+    #[test]
+    fn aov_options_are_parsed() {
+        let options = parse("scene.ron --aov depth,object_id").unwrap().unwrap();
+        assert_eq!(options.aovs, vec![VAov::Depth, VAov::ObjectId]);
+
+        let options = parse("scene.ron --aov all").unwrap().unwrap();
+        assert_eq!(options.aovs, VAov::ALL.to_vec());
+        assert!(parse("scene.ron").unwrap().unwrap().aovs.is_empty());
+        assert!(parse("scene.ron --aov depth,beauty").is_err());
+    }
+
+    #[test]
+    fn sampling_options_are_parsed() {
+        let options = parse("scene.ron --jitter --seed 1234").unwrap().unwrap();
+
+        assert!(options.jitter);
+        assert_eq!(options.seed, 1234);
+        assert!(parse("scene.ron --seed -1").is_err());
+    }
+
+    #[test]
+    fn tone_map_options_are_parsed() {
+        let options = parse("scene.ron --tone-map aces --exposure -1.5 --srgb")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            options.tone_map,
+            VToneMap::new(VToneOperator::Aces)
+                .with_exposure(-1.5)
+                .with_srgb(true)
+        );
+        assert_eq!(
+            parse("scene.ron").unwrap().unwrap().tone_map,
+            VToneMap::default()
+        );
+        assert!(parse("scene.ron --tone-map filmic").is_err());
+
+        let options = parse("scene.ron --transparent --premultiply")
+            .unwrap()
+            .unwrap();
+        assert!(options.transparent);
+        assert!(options.tone_map.premultiplied);
+        assert!(!parse("scene.ron").unwrap().unwrap().transparent);
+        assert!(parse("scene.ron --depth 16").unwrap().unwrap().deep);
+        assert!(parse("scene.ron --depth 12").is_err());
+    }
+
+    #[test]
+    fn png_only_options_are_rejected_for_other_formats() {
+        for args in [
+            "scene.ron -o out.ppm --transparent",
+            "scene.ron -o out.hdr --transparent",
+            "scene.ron -o out.ppm --premultiply",
+            "scene.ron -f hdr --premultiply",
+            "scene.ron -o out.ppm --depth 16",
+            "scene.ron -o out.hdr --depth 8",
+        ] {
+            assert!(parse(args).is_err(), "{} was accepted", args);
+        }
+        assert!(parse("scene.ron -o out.png --transparent --premultiply --depth 16").is_ok());
+    }
+
+    #[test]
+    fn checkpoint_options_are_parsed() {
+        let options = parse("scene.ron --checkpoint scene.ckpt --checkpoint-interval 5")
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(options.checkpoint, Some(PathBuf::from("scene.ckpt")));
+        assert_eq!(options.checkpoint_interval, 5);
+        assert!(parse("scene.ron --checkpoint-interval 0").is_err());
+    }
+
+    #[test]
+    fn invalid_arguments_are_reported() {
+        assert!(parse("").is_err());
+        assert!(parse("scene.ron --samples 0").is_err());
+        assert!(parse("scene.ron --threads").is_err());
+        assert!(parse("scene.ron -o out.jpg").is_err());
+        assert_eq!(
+            parse("scene.ron -o out.hdr").unwrap().unwrap().format,
+            Format::Hdr
+        );
+        assert!(parse("scene.ron --bogus").is_err());
+        assert_eq!(parse("--help"), Ok(None));
+    }
+}
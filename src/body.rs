@@ -1,11 +1,17 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    bounds::VBounds,
+    csg::VCsg,
+    cube::VCube,
+    group::VGroup,
     intersections::{VIntersection, VIntersections},
     matrix::VMatrix,
     ray::VRay,
     sphere::VSphere,
     plane::VPlane,
+    smooth_triangle::VSmoothTriangle,
+    triangle::VTriangle,
     tuple::VTuple,
     F, material::VMaterial,
 };
@@ -14,20 +20,32 @@ pub trait VIntersectable {
     fn material(&self) -> VMaterial;
     fn transform(&self) -> VMatrix<4>;
     fn normal_at_in_object_space(&self, object_space_point: VTuple) -> VTuple;
-    fn intersect_in_object_space(&self, object_space_ray: VRay) -> Vec<(F, VBody)>;
+    /// Normal for bodies that interpolate across their surface, `u` and `v`
+    /// are the barycentric coordinates stored in the intersection.
+    fn normal_at_in_object_space_with_uv(&self, object_space_point: VTuple, _u: F, _v: F) -> VTuple {
+        self.normal_at_in_object_space(object_space_point)
+    }
+    fn intersect_in_object_space(&self, object_space_ray: VRay) -> Vec<VIntersection>;
+    fn bounds_in_object_space(&self) -> VBounds;
+    fn bounds(&self) -> VBounds {
+        self.bounds_in_object_space().transformed(self.transform())
+    }
     fn intersect(&self, ray: VRay) -> VIntersections {
         let object_space_ray = ray.transformed(self.transform().inverted());
-        let ts = self.intersect_in_object_space(object_space_ray);
+        let xs = self.intersect_in_object_space(object_space_ray);
         VIntersections::new(
-            ts.into_iter()
-                .map(|(t, body)| VIntersection::new(t, ray, body))
+            xs.into_iter()
+                .map(|i| VIntersection { ray, ..i })
                 .collect(),
         )
     }
     fn normal_at(&self, point: VTuple) -> VTuple {
+        self.normal_at_with_uv(point, 0.0, 0.0)
+    }
+    fn normal_at_with_uv(&self, point: VTuple, u: F, v: F) -> VTuple {
         let object_space_point = self.transform().inverted() * point;
 
-        let object_normal = self.normal_at_in_object_space(object_space_point);
+        let object_normal = self.normal_at_in_object_space_with_uv(object_space_point, u, v);
 
         let mut world_normal = self.transform().inverted().transposed() * object_normal;
         // Hack, to ensure we have a clean vector, as due the inverse transpose the
@@ -37,34 +55,120 @@ pub trait VIntersectable {
         world_normal.normalized()
     }
 }
-#[derive(Debug, Copy, Clone, PartialEq,Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum VBody {
     VSphere(VSphere),
-    VPlane(VPlane)
+    VPlane(VPlane),
+    VCube(VCube),
+    VGroup(VGroup),
+    VTriangle(VTriangle),
+    VSmoothTriangle(VSmoothTriangle),
+    VCsg(VCsg),
+}
+impl VBody {
+    /// Returns the body as seen from the parent's space, used to hand out
+    /// intersections with children of a group in world space.
+    pub fn with_parent_transform(self, parent: VMatrix<4>) -> VBody {
+        let transform = parent * self.transform();
+        match self {
+            VBody::VSphere(sphere) => VBody::from(sphere.with_transform(transform)),
+            VBody::VPlane(plane) => VBody::from(plane.with_transform(transform)),
+            VBody::VCube(cube) => VBody::from(cube.with_transform(transform)),
+            VBody::VGroup(group) => VBody::from(group.with_transform(transform)),
+            VBody::VTriangle(triangle) => VBody::from(triangle.with_transform(transform)),
+            VBody::VSmoothTriangle(triangle) => VBody::from(triangle.with_transform(transform)),
+            VBody::VCsg(csg) => VBody::from(csg.with_transform(transform)),
+        }
+    }
+    /// Assigns the material, for groups to every body inside the group.
+    pub fn with_material(self, material: VMaterial) -> VBody {
+        match self {
+            VBody::VSphere(sphere) => VBody::from(sphere.with_material(material)),
+            VBody::VPlane(plane) => VBody::from(plane.with_material(material)),
+            VBody::VCube(cube) => VBody::from(cube.with_material(material)),
+            VBody::VGroup(group) => VBody::from(group.with_material(material)),
+            VBody::VTriangle(triangle) => VBody::from(triangle.with_material(material)),
+            VBody::VSmoothTriangle(triangle) => VBody::from(triangle.with_material(material)),
+            VBody::VCsg(csg) => VBody::from(csg.with_material(material)),
+        }
+    }
+    /// Replaces the material with `map` applied to it, for groups and CSG
+    /// bodies to the material of every body inside, so each keeps its own.
+    pub fn map_material<M: Fn(VMaterial) -> VMaterial>(self, map: &M) -> VBody {
+        match self {
+            VBody::VGroup(group) => VBody::from(group.map_material(map)),
+            VBody::VCsg(csg) => VBody::from(csg.map_material(map)),
+            body => {
+                let material = map(body.material());
+                body.with_material(material)
+            }
+        }
+    }
 }
 impl VIntersectable for VBody {
-    fn intersect_in_object_space(&self, object_space_ray: VRay) -> Vec<(F, VBody)> {
+    fn intersect_in_object_space(&self, object_space_ray: VRay) -> Vec<VIntersection> {
         match *self {
             VBody::VSphere(ref sphere) => sphere.intersect_in_object_space(object_space_ray),
             VBody::VPlane(ref plane) => plane.intersect_in_object_space(object_space_ray),
+            VBody::VCube(ref cube) => cube.intersect_in_object_space(object_space_ray),
+            VBody::VGroup(ref group) => group.intersect_in_object_space(object_space_ray),
+            VBody::VTriangle(ref triangle) => triangle.intersect_in_object_space(object_space_ray),
+            VBody::VSmoothTriangle(ref triangle) => triangle.intersect_in_object_space(object_space_ray),
+            VBody::VCsg(ref csg) => csg.intersect_in_object_space(object_space_ray),
         }
     }
     fn transform(&self) -> VMatrix<4> {
         match *self {
             VBody::VSphere(ref sphere) => sphere.transform(),
             VBody::VPlane(ref plane) => plane.transform(),
+            VBody::VCube(ref cube) => cube.transform(),
+            VBody::VGroup(ref group) => group.transform(),
+            VBody::VTriangle(ref triangle) => triangle.transform(),
+            VBody::VSmoothTriangle(ref triangle) => triangle.transform(),
+            VBody::VCsg(ref csg) => csg.transform(),
         }
     }
     fn normal_at_in_object_space(&self, object_space_point: VTuple) -> VTuple {
         match *self {
             VBody::VSphere(ref sphere) => sphere.normal_at_in_object_space(object_space_point),
             VBody::VPlane(ref plane) => plane.normal_at_in_object_space(object_space_point),
+            VBody::VCube(ref cube) => cube.normal_at_in_object_space(object_space_point),
+            VBody::VGroup(ref group) => group.normal_at_in_object_space(object_space_point),
+            VBody::VTriangle(ref triangle) => triangle.normal_at_in_object_space(object_space_point),
+            VBody::VSmoothTriangle(ref triangle) => {
+                triangle.normal_at_in_object_space(object_space_point)
+            }
+            VBody::VCsg(ref csg) => csg.normal_at_in_object_space(object_space_point),
+        }
+    }
+    fn normal_at_in_object_space_with_uv(&self, object_space_point: VTuple, u: F, v: F) -> VTuple {
+        match *self {
+            VBody::VSmoothTriangle(ref triangle) => {
+                triangle.normal_at_in_object_space_with_uv(object_space_point, u, v)
+            }
+            _ => self.normal_at_in_object_space(object_space_point),
         }
     }
     fn material(&self)->VMaterial{
         match *self {
             VBody::VSphere(ref sphere)=>{sphere.material()}       
             VBody::VPlane(ref plane) => {plane.material()}      ,     
+            VBody::VCube(ref cube) => cube.material(),
+            VBody::VGroup(ref group) => group.material(),
+            VBody::VTriangle(ref triangle) => triangle.material(),
+            VBody::VSmoothTriangle(ref triangle) => triangle.material(),
+            VBody::VCsg(ref csg) => csg.material(),
+        }
+    }
+    fn bounds_in_object_space(&self) -> VBounds {
+        match *self {
+            VBody::VSphere(ref sphere) => sphere.bounds_in_object_space(),
+            VBody::VPlane(ref plane) => plane.bounds_in_object_space(),
+            VBody::VCube(ref cube) => cube.bounds_in_object_space(),
+            VBody::VGroup(ref group) => group.bounds_in_object_space(),
+            VBody::VTriangle(ref triangle) => triangle.bounds_in_object_space(),
+            VBody::VSmoothTriangle(ref triangle) => triangle.bounds_in_object_space(),
+            VBody::VCsg(ref csg) => csg.bounds_in_object_space(),
         }
     }
 }
@@ -77,4 +181,29 @@ impl From<VPlane> for VBody {
     fn from(plane: VPlane) -> Self {
         VBody::VPlane(plane)
     }
-}
\ No newline at end of file
+}
+impl From<VCube> for VBody {
+    fn from(cube: VCube) -> Self {
+        VBody::VCube(cube)
+    }
+}
+impl From<VGroup> for VBody {
+    fn from(group: VGroup) -> Self {
+        VBody::VGroup(group)
+    }
+}
+impl From<VTriangle> for VBody {
+    fn from(triangle: VTriangle) -> Self {
+        VBody::VTriangle(triangle)
+    }
+}
+impl From<VSmoothTriangle> for VBody {
+    fn from(triangle: VSmoothTriangle) -> Self {
+        VBody::VSmoothTriangle(triangle)
+    }
+}
+impl From<VCsg> for VBody {
+    fn from(csg: VCsg) -> Self {
+        VBody::VCsg(csg)
+    }
+}
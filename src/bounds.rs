@@ -0,0 +1,164 @@
+use crate::matrix::VMatrix;
+use crate::ray::VRay;
+use crate::tuple::VTuple;
+use crate::F;
+
+/// Axis aligned bounding box, used by groups to skip children a ray can not hit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VBounds {
+    pub min: VTuple,
+    pub max: VTuple,
+}
+impl VBounds {
+    pub fn new(min: VTuple, max: VTuple) -> Self {
+        Self { min, max }
+    }
+    pub fn empty() -> Self {
+        Self::new(
+            VTuple::point(F::INFINITY, F::INFINITY, F::INFINITY),
+            VTuple::point(F::NEG_INFINITY, F::NEG_INFINITY, F::NEG_INFINITY),
+        )
+    }
+    pub fn infinite() -> Self {
+        Self::new(
+            VTuple::point(F::NEG_INFINITY, F::NEG_INFINITY, F::NEG_INFINITY),
+            VTuple::point(F::INFINITY, F::INFINITY, F::INFINITY),
+        )
+    }
+    pub fn unit() -> Self {
+        Self::new(VTuple::point(-1.0, -1.0, -1.0), VTuple::point(1.0, 1.0, 1.0))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+    pub fn add_point(&mut self, p: VTuple) {
+        self.min = VTuple::point(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z));
+        self.max = VTuple::point(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z));
+    }
+    pub fn merged(&self, other: &VBounds) -> VBounds {
+        if other.is_empty() {
+            return *self;
+        }
+        let mut merged = *self;
+        merged.add_point(other.min);
+        merged.add_point(other.max);
+        merged
+    }
+    pub fn centroid(&self) -> VTuple {
+        VTuple::point(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+    /// Bounds of this box after applying `transform`. Computed per axis
+    /// (Arvo's method) rather than from the eight corners, so that infinite
+    /// extents such as those of a plane do not turn into NaN.
+    pub fn transformed(&self, transform: VMatrix<4>) -> VBounds {
+        if self.is_empty() {
+            return *self;
+        }
+        let min = [self.min.x, self.min.y, self.min.z];
+        let max = [self.max.x, self.max.y, self.max.z];
+        let mut new_min = [0.0; 3];
+        let mut new_max = [0.0; 3];
+        for i in 0..3 {
+            new_min[i] = transform[(i, 3)];
+            new_max[i] = transform[(i, 3)];
+            for j in 0..3 {
+                let m = transform[(i, j)];
+                if m == 0.0 {
+                    continue;
+                }
+                let a = m * min[j];
+                let b = m * max[j];
+                new_min[i] += a.min(b);
+                new_max[i] += a.max(b);
+            }
+        }
+        VBounds::new(
+            VTuple::point(new_min[0], new_min[1], new_min[2]),
+            VTuple::point(new_max[0], new_max[1], new_max[2]),
+        )
+    }
+    /// Slab test returning the entry and exit distance along the ray, if any.
+    pub fn intersect(&self, ray: VRay) -> Option<(F, F)> {
+        let (xmin, xmax) = check_axis(ray.origin.x, ray.direction.x, self.min.x, self.max.x);
+        let (ymin, ymax) = check_axis(ray.origin.y, ray.direction.y, self.min.y, self.max.y);
+        let (zmin, zmax) = check_axis(ray.origin.z, ray.direction.z, self.min.z, self.max.z);
+
+        let tmin = xmin.max(ymin).max(zmin);
+        let tmax = xmax.min(ymax).min(zmax);
+        if tmin > tmax {
+            None
+        } else {
+            Some((tmin, tmax))
+        }
+    }
+    pub fn intersects(&self, ray: VRay) -> bool {
+        !self.is_empty() && self.intersect(ray).is_some()
+    }
+}
+fn check_axis(origin: F, direction: F, min: F, max: F) -> (F, F) {
+    let tmin = (min - origin) / direction;
+    let tmax = (max - origin) / direction;
+    // A ray parallel to the slab and starting on its boundary yields 0/0,
+    // treat that as touching the slab.
+    let tmin = if tmin.is_nan() { F::NEG_INFINITY } else { tmin };
+    let tmax = if tmax.is_nan() { F::INFINITY } else { tmax };
+    if tmin > tmax {
+        (tmax, tmin)
+    } else {
+        (tmin, tmax)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::*;
+    use std::f64::consts::{PI, SQRT_2};
+
+    #[test]
+    fn adding_points_grows_the_bounds() {
+        let mut b = VBounds::empty();
+        b.add_point(VTuple::point(-5.0, 2.0, 0.0));
+        b.add_point(VTuple::point(7.0, 0.0, -3.0));
+
+        assert_zeq!(b.min, VTuple::point(-5.0, 0.0, -3.0));
+        assert_zeq!(b.max, VTuple::point(7.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn transforming_bounds() {
+        let b = VBounds::unit();
+        let t = VMatrix::rotation_x(PI / 4.0) * VMatrix::rotation_y(PI / 4.0);
+        let tb = b.transformed(t);
+
+        assert_zeq!(tb.min, VTuple::point(-SQRT_2, -1.70711, -1.70711));
+        assert_zeq!(tb.max, VTuple::point(SQRT_2, 1.70711, 1.70711));
+    }
+
+    #[test]
+    fn transforming_infinite_bounds_keeps_them_finite_where_possible() {
+        let plane = VBounds::new(
+            VTuple::point(F::NEG_INFINITY, 0.0, F::NEG_INFINITY),
+            VTuple::point(F::INFINITY, 0.0, F::INFINITY),
+        );
+        let tb = plane.transformed(VMatrix::translation(0.0, 2.0, 0.0));
+
+        assert_eq!(tb.min.y, 2.0);
+        assert_eq!(tb.max.y, 2.0);
+        assert_eq!(tb.min.x, F::NEG_INFINITY);
+    }
+
+    #[test]
+    fn a_ray_intersects_bounds() {
+        let b = VBounds::new(VTuple::point(5.0, -2.0, 0.0), VTuple::point(11.0, 4.0, 7.0));
+        let hit = VRay::new(VTuple::point(15.0, 1.0, 2.0), VTuple::vector(-1.0, 0.0, 0.0));
+        let miss = VRay::new(VTuple::point(15.0, 20.0, 2.0), VTuple::vector(-1.0, 0.0, 0.0));
+
+        assert!(b.intersects(hit));
+        assert!(!b.intersects(miss));
+    }
+}
@@ -1,8 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{matrix::VMatrix, ray::VRay, tuple::VTuple, F};
 
-#[derive(Debug,Clone, Copy,PartialEq,Deserialize)]
+#[derive(Debug,Clone, Copy,PartialEq, Serialize, Deserialize)]
+#[serde(from = "VCameraConfig", into = "VCameraConfig")]
 pub struct VCamera {
     pub transform: VMatrix<4>,
     pub vsize: usize,
@@ -12,6 +13,30 @@ pub struct VCamera {
     half_height: F,
     pixel_size: F,
 }
+// Only the user facing parameters are stored in scene files, the derived
+// half sizes are recomputed on load so they can never get out of sync.
+#[derive(Serialize, Deserialize)]
+struct VCameraConfig {
+    hsize: usize,
+    vsize: usize,
+    fov: F,
+    transform: VMatrix<4>,
+}
+impl From<VCameraConfig> for VCamera {
+    fn from(cfg: VCameraConfig) -> Self {
+        VCamera::new(cfg.hsize, cfg.vsize, cfg.fov).with_transform(cfg.transform)
+    }
+}
+impl From<VCamera> for VCameraConfig {
+    fn from(cam: VCamera) -> Self {
+        VCameraConfig {
+            hsize: cam.hsize,
+            vsize: cam.vsize,
+            fov: cam.fov,
+            transform: cam.transform,
+        }
+    }
+}
 impl VCamera {
     pub fn new(hsize: usize, vsize: usize, fov: F) -> Self {
         let half_width;
@@ -42,9 +67,19 @@ impl VCamera {
         self.transform = transform;
         self
     }
+    /// Same camera with a different resolution, keeping field of view and
+    /// transform.
+    pub fn resized(&self, hsize: usize, vsize: usize) -> Self {
+        VCamera::new(hsize, vsize, self.fov).with_transform(self.transform)
+    }
     pub fn ray_for_pixel(&self, x: usize, y: usize) -> VRay {
-        let offset_x = (0.5 + x as f64) * self.pixel_size;
-        let offset_y = (0.5 + y as f64) * self.pixel_size;
+        self.ray_for_subpixel(x, y, 0.5, 0.5)
+    }
+    /// Ray through a point inside the pixel, `dx` and `dy` range from 0 to 1
+    /// with 0.5 being the center. Used for supersampling.
+    pub fn ray_for_subpixel(&self, x: usize, y: usize, dx: F, dy: F) -> VRay {
+        let offset_x = (dx + x as f64) * self.pixel_size;
+        let offset_y = (dy + y as f64) * self.pixel_size;
 
         let world_x = self.half_width - offset_x;
         let world_y = self.half_height - offset_y;
@@ -159,6 +194,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn constructing_a_ray_through_a_pixel_corner() {
+        let c = VCamera::new(200, 100, PI / 2.0);
+
+        assert_eq!(c.ray_for_subpixel(100, 50, 0.5, 0.5), c.ray_for_pixel(100, 50));
+        assert_zeq!(
+            c.ray_for_subpixel(0, 0, 0.0, 0.0).direction,
+            VTuple::vector(1.0, 0.5, -1.0).normalized()
+        );
+    }
+
+    #[test]
+    fn resizing_keeps_the_field_of_view() {
+        let t = VMatrix::translation(1.0, 2.0, 3.0);
+        let c = VCamera::new(200, 125, PI / 2.0).with_transform(t).resized(400, 250);
+
+        assert_eq!(c.hsize, 400);
+        assert_zeq!(c.transform, t);
+        assert_zeq!(c.pixel_size, 0.005);
+    }
+
     #[test]
     fn pixel_size_for_horizontal_canvas() {
         let c = VCamera::new(200, 125, PI / 2.0);
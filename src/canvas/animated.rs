@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use png::{BitDepth, ColorType, Encoder};
+
+use crate::animator::Frame;
+use crate::canvas::to_rgba32::ToRGBA32;
+use crate::canvas::tone_map::VToneMap;
+use crate::canvas::vcanvas::{VCanvas, VImageError};
+use crate::F;
+
+/// Frames of an animation, kept as 8 bit RGBA until they are encoded as an
+/// animated PNG or a GIF. Frames are ordered by their number and may be
+/// added in any order, e.g. from [`VAnimator::animate_parallel`]. Missing
+/// numbers are left out.
+///
+/// [`VAnimator::animate_parallel`]: crate::animator::VAnimator::animate_parallel
+#[derive(Debug, Clone)]
+pub struct VAnimatedImage {
+    pub width: usize,
+    pub height: usize,
+    /// Frames per second.
+    pub frame_rate: F,
+    /// How often the animation is played, 0 repeats it forever.
+    pub loops: u16,
+    pub tone_map: VToneMap,
+    pub(crate) frames: BTreeMap<usize, Vec<u8>>,
+}
+impl VAnimatedImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            frame_rate: 25.0,
+            loops: 0,
+            tone_map: VToneMap::default(),
+            frames: BTreeMap::new(),
+        }
+    }
+    pub fn with_frame_rate(mut self, frame_rate: F) -> Self {
+        self.frame_rate = frame_rate;
+        self
+    }
+    pub fn with_loops(mut self, loops: u16) -> Self {
+        self.loops = loops;
+        self
+    }
+    /// Tone map for the frames added afterwards.
+    pub fn with_tone_map(mut self, tone_map: VToneMap) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+    /// Adds the render of a frame, replacing an earlier one with the same
+    /// number.
+    pub fn add_frame(&mut self, frame: &Frame, canvas: &VCanvas) -> Result<(), VImageError> {
+        if (canvas.width, canvas.height) != (self.width, self.height) {
+            return Err(VImageError::Invalid(format!(
+                "a {}x{} frame does not fit a {}x{} animation",
+                canvas.width, canvas.height, self.width, self.height
+            )));
+        }
+        self.frames
+            .insert(frame.current(), canvas.to_rgba32_with(&self.tone_map));
+        Ok(())
+    }
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn to_apng(&self) -> Result<Vec<u8>, VImageError> {
+        if self.frames.is_empty() {
+            return Err(invalid("an animation needs at least one frame"));
+        }
+        let mut data = Vec::new();
+        let mut encoder = Encoder::new(&mut data, self.width as u32, self.height as u32);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+        let (numerator, denominator) = self.frame_delay_ms();
+        encoder
+            .set_animated(self.frames.len() as u32, self.loops as u32)
+            .and_then(|_| encoder.set_frame_delay(numerator, denominator))
+            .map_err(encoding_error)?;
+        let mut writer = encoder.write_header().map_err(encoding_error)?;
+        for samples in self.frames.values() {
+            writer.write_image_data(samples).map_err(encoding_error)?;
+        }
+        writer.finish().map_err(encoding_error)?;
+        Ok(data)
+    }
+    /// Writes the animation, as an animated PNG for `.png` and `.apng` and
+    /// as a GIF for `.gif` files.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), VImageError> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .unwrap_or_default();
+        let data = match ext.as_str() {
+            "png" | "apng" => self.to_apng()?,
+            "gif" => self.to_gif()?,
+            _ => {
+                return Err(VImageError::Unsupported(format!(
+                    "animations can not be saved as {:?}",
+                    ext
+                )))
+            }
+        };
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+    // Delay between frames as a fraction of a second
+    fn frame_delay_ms(&self) -> (u16, u16) {
+        let delay = (1000.0 / self.frame_rate).round();
+        (delay.clamp(1.0, u16::MAX as F) as u16, 1000)
+    }
+}
+fn invalid(message: &str) -> VImageError {
+    VImageError::Invalid(String::from(message))
+}
+fn encoding_error(e: png::EncodingError) -> VImageError {
+    VImageError::Invalid(format!("could not encode PNG: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::vcolor::VColor;
+
+    fn flashing(count: usize) -> VAnimatedImage {
+        let mut animation = VAnimatedImage::new(3, 2).with_frame_rate(10.0);
+        for current in (0..count).rev() {
+            let mut canvas = VCanvas::new(3, 2);
+            canvas.write_pixel(current % 3, 0, VColor::red());
+            canvas.write_pixel(1, 1, VColor::new(0.0, 0.0, 1.0) * current as F);
+            animation
+                .add_frame(&Frame::new(count, current), &canvas)
+                .unwrap();
+        }
+        animation
+    }
+
+    #[test]
+    fn animations_are_written_as_apng() {
+        let animation = flashing(4).with_loops(2);
+        let data = animation.to_apng().unwrap();
+
+        let mut reader = png::Decoder::new(data.as_slice()).read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (4, 2));
+        let delay = reader.info().frame_control.unwrap();
+        assert_eq!((delay.delay_num, delay.delay_den), (100, 1000));
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        for current in 0..4 {
+            reader.next_frame(&mut buffer).unwrap();
+            assert_eq!(&buffer[(current % 3) * 4..][..4], &[255, 0, 0, 255]);
+            assert_eq!(buffer[4 * 4 + 2], (current.min(1) * 255) as u8);
+        }
+    }
+
+    #[test]
+    fn frames_have_to_fit_the_animation() {
+        let mut animation = VAnimatedImage::new(3, 2);
+
+        assert!(animation.to_apng().is_err());
+        assert!(animation
+            .add_frame(&Frame::new(1, 0), &VCanvas::new(2, 3))
+            .is_err());
+        assert_eq!(animation.frame_count(), 0);
+        assert!(matches!(
+            flashing(1).save("animation.webp"),
+            Err(VImageError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,231 @@
+use std::path::{Path, PathBuf};
+
+use crate::canvas::to_png::ToPNG;
+use crate::canvas::vcanvas::{VCanvas, VImageError};
+use crate::canvas::vcolor::VColor;
+use crate::zequality::ZEq;
+use crate::F;
+
+/// Differences between two images of the same size.
+#[derive(Debug, Clone)]
+pub struct VCanvasDiff {
+    /// Absolute difference of every channel.
+    pub image: VCanvas,
+    /// Root mean square error over all channels.
+    pub rmse: F,
+    /// Peak signal to noise ratio in dB for a peak of 1, infinite for equal
+    /// images.
+    pub psnr: F,
+    /// Largest difference of any channel and the pixel it is found at.
+    pub max_error: F,
+    pub max_error_at: (usize, usize),
+}
+impl VCanvasDiff {
+    /// Difference image scaled so the largest error is white.
+    pub fn heat_map(&self) -> VCanvas {
+        let scale = if self.max_error > 0.0 {
+            1.0 / self.max_error
+        } else {
+            0.0
+        };
+        let mut canvas = VCanvas::new(self.image.width, self.image.height);
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                canvas.write_pixel(x, y, self.image.pixel_at(x, y) * scale);
+            }
+        }
+        canvas
+    }
+}
+
+impl VCanvas {
+    pub fn diff(&self, other: &VCanvas) -> Result<VCanvasDiff, VImageError> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return Err(VImageError::Invalid(format!(
+                "can not compare a {}x{} with a {}x{} image",
+                self.width, self.height, other.width, other.height
+            )));
+        }
+        let mut image = VCanvas::new(self.width, self.height);
+        let mut squares = 0.0;
+        let mut max_error = 0.0;
+        let mut max_error_at = (0, 0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (a, b) = (self.pixel_at(x, y), other.pixel_at(x, y));
+                let d = VColor::new((a.r - b.r).abs(), (a.g - b.g).abs(), (a.b - b.b).abs());
+                squares += d.r * d.r + d.g * d.g + d.b * d.b;
+                let largest = d.r.max(d.g).max(d.b);
+                if largest > max_error {
+                    max_error = largest;
+                    max_error_at = (x, y);
+                }
+                image.write_pixel(x, y, d);
+            }
+        }
+        let channels = (self.width * self.height * 3).max(1) as F;
+        let rmse = (squares / channels).sqrt();
+        Ok(VCanvasDiff {
+            image,
+            rmse,
+            psnr: -20.0 * rmse.log10(),
+            max_error,
+            max_error_at,
+        })
+    }
+    /// Whether both images have the same size and no channel differs by
+    /// more than `tolerance`.
+    pub fn approx_eq(&self, other: &VCanvas, tolerance: F) -> bool {
+        self.diff(other)
+            .map(|diff| diff.max_error <= tolerance)
+            .unwrap_or(false)
+    }
+}
+impl ZEq<&VCanvas> for VCanvas {
+    fn zeq(&self, other: &VCanvas) -> bool {
+        (self.width, self.height) == (other.width, other.height)
+            && self
+                .get_pixels()
+                .iter()
+                .zip(other.get_pixels().iter())
+                .all(|(a, b)| a.zeq(*b))
+            && self
+                .get_alpha()
+                .iter()
+                .zip(other.get_alpha().iter())
+                .all(|(a, b)| a.zeq(*b))
+    }
+}
+
+/// Compares a render against a reference PNG, after putting it through the
+/// same 8 bit quantization. On a mismatch the render and a heat map of the
+/// differences are written next to the reference as `<name>.actual.png` and
+/// `<name>.diff.png` before panicking. A missing reference is created from
+/// the render, which also fails the assertion so it gets looked at.
+pub fn assert_matches_reference<P: AsRef<Path>>(canvas: &VCanvas, reference: P, tolerance: F) {
+    let reference = reference.as_ref();
+    let png = canvas.to_png();
+    let sibling = |suffix: &str| -> PathBuf {
+        let mut name = reference.file_stem().unwrap_or_default().to_os_string();
+        name.push(suffix);
+        reference.with_file_name(name)
+    };
+    if !reference.exists() {
+        std::fs::write(reference, &png).expect("Could not write the reference image");
+        panic!(
+            "reference image {} did not exist and was created, check it and run again",
+            reference.display()
+        );
+    }
+    let expected = VCanvas::load(reference).unwrap_or_else(|e| {
+        panic!("could not read {}: {}", reference.display(), e);
+    });
+    let actual = VCanvas::from_png(&png).expect("Could not read back the render");
+    let diff = actual.diff(&expected).unwrap_or_else(|e| panic!("{}", e));
+    if diff.max_error > tolerance {
+        let (actual_path, diff_path) = (sibling(".actual.png"), sibling(".diff.png"));
+        let _ = std::fs::write(&actual_path, png);
+        let _ = std::fs::write(&diff_path, diff.heat_map().to_png());
+        panic!(
+            "render differs from {}: max error {:.5} at {:?} (tolerance {}), RMSE {:.5}, \
+             PSNR {:.2} dB, see {} and {}",
+            reference.display(),
+            diff.max_error,
+            diff.max_error_at,
+            tolerance,
+            diff.rmse,
+            diff.psnr,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> VCanvas {
+        let mut c = VCanvas::new(4, 3);
+        for y in 0..3 {
+            for x in 0..4 {
+                c.write_pixel(x, y, VColor::new(x as F / 4.0, y as F / 3.0, 0.5));
+            }
+        }
+        c
+    }
+    fn temporary_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ray_tracer_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn diffing_equal_images() {
+        let diff = gradient().diff(&gradient()).unwrap();
+
+        assert_zeq!(diff.rmse, 0.0);
+        assert_eq!(diff.psnr, F::INFINITY);
+        assert_zeq!(diff.max_error, 0.0);
+        assert_zeq!(diff.heat_map().pixel_at(3, 2), VColor::black());
+    }
+
+    #[test]
+    fn diffing_different_images() {
+        let mut other = gradient();
+        other.write_pixel(2, 1, other.pixel_at(2, 1) + VColor::new(0.0, -0.5, 0.0));
+        other.write_pixel(0, 0, VColor::new(0.1, 0.0, 0.5));
+        let diff = gradient().diff(&other).unwrap();
+
+        assert_zeq!(diff.image.pixel_at(2, 1), VColor::new(0.0, 0.5, 0.0));
+        assert_zeq!(diff.max_error, 0.5);
+        assert_eq!(diff.max_error_at, (2, 1));
+        assert_zeq!(diff.rmse, ((0.25 + 0.01) / 36.0 as F).sqrt());
+        assert_zeq!(diff.psnr, -20.0 * diff.rmse.log10());
+        assert_zeq!(diff.heat_map().pixel_at(0, 0), VColor::new(0.2, 0.0, 0.0));
+
+        assert!(gradient().approx_eq(&other, 0.5));
+        assert!(!gradient().approx_eq(&other, 0.4));
+        assert!(gradient().diff(&VCanvas::new(4, 4)).is_err());
+    }
+
+    #[test]
+    fn canvases_compare_with_zeq() {
+        let mut close = gradient();
+        close.write_pixel(1, 1, close.pixel_at(1, 1) + VColor::new(0.00001, 0.0, 0.0));
+
+        assert_zeq!(gradient(), &close);
+        assert_nzeq!(gradient(), &VCanvas::new(4, 3));
+        assert_nzeq!(gradient(), &VCanvas::new(3, 4));
+        close.write_alpha(0, 0, 0.5);
+        assert_nzeq!(gradient(), &close);
+    }
+
+    #[test]
+    fn matching_a_reference_image() {
+        let dir = temporary_dir("reference");
+        let reference = dir.join("gradient.png");
+        std::fs::write(&reference, gradient().to_png()).unwrap();
+
+        assert_matches_reference(&gradient(), &reference, 0.0);
+
+        let mut changed = gradient();
+        changed.write_pixel(3, 0, VColor::black());
+        let result = std::panic::catch_unwind(|| {
+            assert_matches_reference(&changed, &reference, 0.01);
+        });
+        assert!(result.is_err());
+        assert!(dir.join("gradient.actual.png").exists());
+        let heat_map = VCanvas::load(dir.join("gradient.diff.png")).unwrap();
+        assert_eq!(heat_map.pixel_at(3, 0).r, 1.0);
+        assert_eq!(heat_map.pixel_at(0, 0), VColor::black());
+
+        let missing = dir.join("missing.png");
+        assert!(
+            std::panic::catch_unwind(|| assert_matches_reference(&changed, &missing, 0.0)).is_err()
+        );
+        assert!(missing.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use crate::canvas::to_hdr::from_rgbe;
+use crate::canvas::vcanvas::{VCanvas, VImageError};
+
+impl VCanvas {
+    /// Reads a Radiance HDR image as written by `to_hdr`, keeping colors
+    /// brighter than white. Flat and run length encoded scanlines are
+    /// supported, in the usual top to bottom `-Y <height> +X <width>` order.
+    pub fn from_hdr(data: &[u8]) -> Result<Self, VImageError> {
+        if !data.starts_with(b"#?") {
+            return Err(invalid("not a Radiance HDR image"));
+        }
+        // Header lines end with an empty line, the resolution follows
+        let mut offset = 0;
+        loop {
+            let line = line_at(data, &mut offset)?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(VImageError::Unsupported(format!("HDR format {}", format)));
+                }
+            }
+        }
+        let resolution = line_at(data, &mut offset)?;
+        let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => {
+                match (width.parse::<usize>(), height.parse::<usize>()) {
+                    (Ok(width), Ok(height)) => (width, height),
+                    _ => return Err(invalid("invalid resolution")),
+                }
+            }
+            _ => {
+                return Err(VImageError::Unsupported(format!(
+                    "HDR orientation {}",
+                    resolution
+                )))
+            }
+        };
+        let mut data = &data[offset..];
+        // The header is not trusted for allocating, every scanline takes up
+        // a minimum number of bytes
+        let pixel_count = width.checked_mul(height).and_then(|p| p.checked_mul(4));
+        let needed = min_scanline_size(width).checked_mul(height);
+        if pixel_count.is_none() || needed.is_none_or(|needed| needed > data.len()) {
+            return Err(invalid("image larger than its data"));
+        }
+
+        let mut canvas = VCanvas::new(width, height);
+        for y in 0..height {
+            let (rgbe, rest) = read_scanline(data, width)?;
+            for (x, rgbe) in rgbe.into_iter().enumerate() {
+                canvas.write_pixel(x, y, from_rgbe(rgbe));
+            }
+            data = rest;
+        }
+        Ok(canvas)
+    }
+}
+fn invalid(message: &str) -> VImageError {
+    VImageError::Invalid(String::from(message))
+}
+
+fn line_at<'a>(data: &'a [u8], offset: &mut usize) -> Result<&'a str, VImageError> {
+    let rest = &data[*offset..];
+    let end = rest
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or_else(|| invalid("unexpected end of header"))?;
+    *offset += end + 1;
+    std::str::from_utf8(&rest[..end]).map_err(|_| invalid("header is not text"))
+}
+// Bytes of the shortest scanline of `width` pixels, flat or made of the
+// longest runs of 127 pixels.
+fn min_scanline_size(width: usize) -> usize {
+    if (8..0x8000).contains(&width) {
+        4 + 4 * 2 * width.div_ceil(127)
+    } else {
+        width * 4
+    }
+}
+fn read_scanline(data: &[u8], width: usize) -> Result<(Vec<[u8; 4]>, &[u8]), VImageError> {
+    let end_of_data = || invalid("unexpected end of data");
+    let encoded = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && (data[2] as usize) << 8 | data[3] as usize == width;
+    if !encoded {
+        let bytes = data.get(..width * 4).ok_or_else(end_of_data)?;
+        let pixels = bytes.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect();
+        return Ok((pixels, &data[width * 4..]));
+    }
+    // Each of the four channels is stored separately as runs and literals
+    let mut pixels = vec![[0; 4]; width];
+    let mut offset = 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(offset).ok_or_else(end_of_data)? as usize;
+            offset += 1;
+            if count > 128 {
+                let count = count - 128;
+                let value = *data.get(offset).ok_or_else(end_of_data)?;
+                offset += 1;
+                if x + count > width {
+                    return Err(invalid("run past the end of a scanline"));
+                }
+                for pixel in pixels[x..x + count].iter_mut() {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid("invalid literal in a scanline"));
+                }
+                let values = data.get(offset..offset + count).ok_or_else(end_of_data)?;
+                for (pixel, value) in pixels[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = *value;
+                }
+                offset += count;
+                x += count;
+            }
+        }
+    }
+    Ok((pixels, &data[offset..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::to_hdr::ToHDR;
+    use crate::canvas::vcolor::VColor;
+    use crate::zequality::*;
+    use crate::F;
+
+    fn bright(width: usize, height: usize) -> VCanvas {
+        let mut c = VCanvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                c.write_pixel(x, y, VColor::new(x as F * 0.5, 12.0, y as F * 0.25));
+            }
+        }
+        c
+    }
+    fn close_to(read: &VCanvas, written: &VCanvas) -> bool {
+        read.get_pixels()
+            .iter()
+            .zip(written.get_pixels())
+            .all(|(a, b)| {
+                let largest = b.r.max(b.g).max(b.b);
+                (a.r - b.r)
+                    .abs()
+                    .max((a.g - b.g).abs())
+                    .max((a.b - b.b).abs())
+                    <= largest / 128.0
+            })
+    }
+
+    #[test]
+    fn hdr_images_round_trip() {
+        // 5 pixels wide is written flat, 300 run length encoded
+        for width in [5, 300] {
+            let c = bright(width, 3);
+            let read = VCanvas::from_hdr(&c.to_hdr()).unwrap();
+
+            assert_eq!((read.width, read.height), (width, 3));
+            assert!(close_to(&read, &c));
+            assert_zeq!(read.pixel_at(0, 0).g, 12.0);
+        }
+    }
+
+    #[test]
+    fn rejecting_broken_hdr() {
+        let data = bright(300, 2).to_hdr();
+
+        assert!(matches!(
+            VCanvas::from_hdr(b"P6 1 1 255\n"),
+            Err(VImageError::Invalid(_))
+        ));
+        assert!(matches!(
+            VCanvas::from_hdr(&data[..data.len() - 10]),
+            Err(VImageError::Invalid(_))
+        ));
+        assert!(matches!(
+            VCanvas::from_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0"),
+            Err(VImageError::Unsupported(_))
+        ));
+        assert!(matches!(
+            VCanvas::from_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n"),
+            Err(VImageError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn rejecting_huge_hdr_before_allocating() {
+        for resolution in ["-Y 100000 +X 100000", "-Y 1000000 +X 1000", "-Y 4 +X 1"] {
+            let data = format!("#?RADIANCE\n\n{}\n\0\0\0\0", resolution);
+            assert!(matches!(
+                VCanvas::from_hdr(data.as_bytes()),
+                Err(VImageError::Invalid(_))
+            ));
+        }
+    }
+}
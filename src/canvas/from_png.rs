@@ -0,0 +1,170 @@
+use png::{BitDepth, ColorType, Decoder, Transformations};
+
+use crate::canvas::vcanvas::{VCanvas, VImageError};
+use crate::canvas::vcolor::VColor;
+use crate::F;
+
+impl VCanvas {
+    /// Reads a PNG image, the inverse of `to_png`: channel values are mapped
+    /// to [0, 1] without any gamma decoding. Palettes, grayscale and 16 bit
+    /// images are supported, alpha is kept as it is stored.
+    pub fn from_png(data: &[u8]) -> Result<Self, VImageError> {
+        let mut decoder = Decoder::new(data);
+        // Palettes and low bit depths become plain 8 bit channels
+        decoder.set_transformations(Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let channels = match info.color_type {
+            ColorType::Grayscale => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+            ColorType::Indexed => {
+                return Err(VImageError::Unsupported(String::from(
+                    "palette was not expanded",
+                )))
+            }
+        };
+        let (bytes, max) = match info.bit_depth {
+            BitDepth::Eight => (1, 255.0),
+            BitDepth::Sixteen => (2, 65535.0),
+            depth => {
+                return Err(VImageError::Unsupported(format!(
+                    "bit depth {:?} was not expanded",
+                    depth
+                )))
+            }
+        };
+        let (width, height) = (info.width as usize, info.height as usize);
+        let mut canvas = VCanvas::new(width, height);
+        for y in 0..height {
+            let line = &buffer[y * info.line_size..(y + 1) * info.line_size];
+            for x in 0..width {
+                let value = |channel: usize| -> F {
+                    let offset = (x * channels + channel) * bytes;
+                    let raw = if bytes == 2 {
+                        u16::from_be_bytes([line[offset], line[offset + 1]]) as F
+                    } else {
+                        line[offset] as F
+                    };
+                    raw / max
+                };
+                let color = if channels < 3 {
+                    let gray = value(0);
+                    VColor::new(gray, gray, gray)
+                } else {
+                    VColor::new(value(0), value(1), value(2))
+                };
+                canvas.write_pixel(x, y, color);
+                if channels % 2 == 0 {
+                    canvas.write_alpha(x, y, value(channels - 1));
+                }
+            }
+        }
+        Ok(canvas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::to_png::ToPNG;
+    use crate::zequality::*;
+
+    fn encode(
+        width: u32,
+        height: u32,
+        color: ColorType,
+        depth: BitDepth,
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        drop(writer);
+        data
+    }
+
+    #[test]
+    fn png_images_round_trip() {
+        let mut c = VCanvas::new(3, 2);
+        c.write_pixel(0, 0, VColor::new(1.0, 0.0, 0.2));
+        c.write_pixel(2, 1, VColor::new(0.4, 0.6, 0.8));
+        let read = VCanvas::from_png(&c.to_png()).unwrap();
+
+        assert_eq!((read.width, read.height), (3, 2));
+        for y in 0..2 {
+            for x in 0..3 {
+                assert_zeq!(
+                    read.pixel_at(x, y),
+                    VColor::new(
+                        (c.pixel_at(x, y).r * 255.0).round() / 255.0,
+                        (c.pixel_at(x, y).g * 255.0).round() / 255.0,
+                        (c.pixel_at(x, y).b * 255.0).round() / 255.0
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reading_grayscale_and_16_bit_png() {
+        let gray = encode(2, 1, ColorType::Grayscale, BitDepth::Eight, &[0, 255]);
+        let gray = VCanvas::from_png(&gray).unwrap();
+        assert_zeq!(gray.pixel_at(1, 0), VColor::white());
+
+        let deep = encode(
+            1,
+            1,
+            ColorType::Rgb,
+            BitDepth::Sixteen,
+            &[0xff, 0xff, 0x80, 0x00, 0x00, 0x00],
+        );
+        let deep = VCanvas::from_png(&deep).unwrap();
+        assert_zeq!(deep.pixel_at(0, 0), VColor::new(1.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn sixteen_bit_png_keeps_more_precision() {
+        let mut c = VCanvas::new(2, 1);
+        c.write_pixel(0, 0, VColor::new(0.1234, 0.5, 1.0));
+        c.write_pixel(1, 0, VColor::new(2.0, -1.0, 0.00002));
+        let read = VCanvas::from_png(&c.to_png16()).unwrap();
+
+        let p = read.pixel_at(0, 0);
+        assert!((p.r - 0.1234).abs() < 1.0 / 65535.0);
+        assert_zeq!(p, VColor::new(0.1234, 0.5, 1.0));
+        assert_zeq!(read.pixel_at(1, 0), VColor::new(1.0, 0.0, 0.0));
+        assert!(read.pixel_at(1, 0).b > 0.0);
+    }
+
+    #[test]
+    fn png_keeps_the_alpha_channel() {
+        let mut c = VCanvas::new(2, 1);
+        c.write_pixel(0, 0, VColor::new(0.2, 0.4, 1.0));
+        c.write_alpha(0, 0, 0.4);
+        c.write_alpha(1, 0, 0.0);
+        let read = VCanvas::from_png(&c.to_png16()).unwrap();
+
+        assert_zeq!(read.alpha_at(0, 0), 0.4);
+        assert_zeq!(read.alpha_at(1, 0), 0.0);
+        assert_zeq!(read.pixel_at(0, 0), VColor::new(0.2, 0.4, 1.0));
+
+        let gray = encode(1, 1, ColorType::GrayscaleAlpha, BitDepth::Eight, &[255, 51]);
+        assert_zeq!(VCanvas::from_png(&gray).unwrap().alpha_at(0, 0), 0.2);
+        assert!(VCanvas::from_png(&c.to_png()).unwrap().alpha_at(1, 0) == 0.0);
+    }
+
+    #[test]
+    fn rejecting_broken_png() {
+        assert!(matches!(
+            VCanvas::from_png(b"\x89PNG\r\n\x1a\nbroken"),
+            Err(VImageError::Png(_))
+        ));
+    }
+}
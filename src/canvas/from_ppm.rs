@@ -0,0 +1,201 @@
+use crate::canvas::vcanvas::{VCanvas, VImageError};
+use crate::canvas::vcolor::VColor;
+use crate::F;
+
+impl VCanvas {
+    /// Reads a plain (P3) or binary (P6) PPM image. Comments and any maximum
+    /// value up to 65535 are accepted, channels are scaled to [0, 1].
+    pub fn from_ppm(data: &[u8]) -> Result<Self, VImageError> {
+        let mut tokens = Tokens { data, offset: 0 };
+        let binary = match tokens.next() {
+            Some(b"P3") => false,
+            Some(b"P6") => true,
+            Some(magic) if magic.starts_with(b"P") => {
+                return Err(VImageError::Unsupported(format!(
+                    "PPM variant {}",
+                    String::from_utf8_lossy(magic)
+                )))
+            }
+            _ => return Err(invalid("not a PPM image")),
+        };
+        let width = tokens.number()?;
+        let height = tokens.number()?;
+        let max = tokens.number()?;
+        if max == 0 || max > 65535 {
+            return Err(invalid("maximum value outside of 1 to 65535"));
+        }
+        let samples = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or_else(|| invalid("image too large"))?;
+
+        let values: Vec<usize> = if binary {
+            // A single whitespace byte separates the header from the data
+            let start = tokens.offset + 1;
+            let bytes = if max > 255 { 2 } else { 1 };
+            let end = samples
+                .checked_mul(bytes)
+                .and_then(|size| size.checked_add(start))
+                .ok_or_else(|| invalid("image too large"))?;
+            if data.len() < end {
+                return Err(invalid("unexpected end of data"));
+            }
+            data[start..end]
+                .chunks(bytes)
+                .map(|b| match b {
+                    [high, low] => (*high as usize) << 8 | *low as usize,
+                    _ => b[0] as usize,
+                })
+                .collect()
+        } else {
+            (0..samples)
+                .map(|_| tokens.number())
+                .collect::<Result<_, _>>()?
+        };
+        if values.iter().any(|v| *v > max) {
+            return Err(invalid("value above the maximum value"));
+        }
+        let scale = 1.0 / max as F;
+        let mut canvas = VCanvas::new(width, height);
+        for (i, rgb) in values.chunks(3).enumerate() {
+            canvas.write_pixel(
+                i % width,
+                i / width,
+                VColor::new(
+                    rgb[0] as F * scale,
+                    rgb[1] as F * scale,
+                    rgb[2] as F * scale,
+                ),
+            );
+        }
+        Ok(canvas)
+    }
+}
+fn invalid(message: &str) -> VImageError {
+    VImageError::Invalid(String::from(message))
+}
+
+// Whitespace separated tokens, skipping comments from # to the end of the
+// line.
+struct Tokens<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            match self.data.get(self.offset) {
+                Some(b'#') => {
+                    while self.data.get(self.offset).is_some_and(|b| *b != b'\n') {
+                        self.offset += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => self.offset += 1,
+                Some(_) => break,
+                None => return None,
+            }
+        }
+        let start = self.offset;
+        while self
+            .data
+            .get(self.offset)
+            .is_some_and(|b| !b.is_ascii_whitespace() && *b != b'#')
+        {
+            self.offset += 1;
+        }
+        Some(&self.data[start..self.offset])
+    }
+    fn number(&mut self) -> Result<usize, VImageError> {
+        let token = self
+            .next()
+            .ok_or_else(|| invalid("unexpected end of data"))?;
+        std::str::from_utf8(token)
+            .ok()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| {
+                invalid(&format!(
+                    "expected a number, got {:?}",
+                    String::from_utf8_lossy(token)
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::to_ppm::ToPPM;
+    use crate::zequality::*;
+
+    #[test]
+    fn reading_plain_ppm_with_comments() {
+        let data = b"P3\n# made by hand\n2 1 # width and height\n100\n100 50 0\n0 0 25 # blue\n";
+        let c = VCanvas::from_ppm(data).unwrap();
+
+        assert_eq!((c.width, c.height), (2, 1));
+        assert_zeq!(c.pixel_at(0, 0), VColor::new(1.0, 0.5, 0.0));
+        assert_zeq!(c.pixel_at(1, 0), VColor::new(0.0, 0.0, 0.25));
+    }
+
+    #[test]
+    fn plain_ppm_round_trips() {
+        let mut c = VCanvas::new(30, 2);
+        c.write_pixel(29, 1, VColor::new(0.2, 0.4, 1.0));
+        let read = VCanvas::from_ppm(&c.to_ppm()).unwrap();
+
+        assert_eq!((read.width, read.height), (30, 2));
+        assert_zeq!(
+            read.pixel_at(29, 1),
+            VColor::new(51.0, 102.0, 255.0) * (1.0 / 255.0)
+        );
+    }
+
+    #[test]
+    fn binary_ppm_round_trips() {
+        let mut c = VCanvas::new(3, 2);
+        c.write_pixel(2, 1, VColor::new(0.2, 0.4, 1.0));
+        let read = VCanvas::from_ppm(&c.to_binary_ppm()).unwrap();
+
+        assert_eq!((read.width, read.height), (3, 2));
+        assert_zeq!(read.pixel_at(2, 1), VColor::new(0.2, 0.4, 1.0));
+    }
+
+    #[test]
+    fn reading_binary_ppm() {
+        let mut data = b"P6 1 2 255\n".to_vec();
+        // Data bytes that look like whitespace or comments must not matter
+        data.extend([b'#', b' ', 255, b'\n', 0, 51]);
+        let c = VCanvas::from_ppm(&data).unwrap();
+        assert_zeq!(
+            c.pixel_at(0, 0),
+            VColor::new(35.0, 32.0, 255.0) * (1.0 / 255.0)
+        );
+        assert_zeq!(
+            c.pixel_at(0, 1),
+            VColor::new(10.0, 0.0, 51.0) * (1.0 / 255.0)
+        );
+
+        let mut deep = b"P6\n1 1\n1000\n".to_vec();
+        deep.extend([0x03, 0xe8, 0x01, 0xf4, 0x00, 0x00]);
+        let deep = VCanvas::from_ppm(&deep).unwrap();
+        assert_zeq!(deep.pixel_at(0, 0), VColor::new(1.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn rejecting_broken_ppm() {
+        let invalid = |data: &[u8]| {
+            let result = VCanvas::from_ppm(data);
+            matches!(result, Err(VImageError::Invalid(_)))
+        };
+
+        assert!(invalid(b"GIF89a"));
+        assert!(invalid(b"P3 2 1 255 0 0 0 0 0"));
+        assert!(invalid(b"P3 1 1 255 0 0 256"));
+        assert!(invalid(b"P3 1 x 255"));
+        assert!(invalid(b"P6 2 2 255\n\0\0\0"));
+        assert!(matches!(
+            VCanvas::from_ppm(b"P5 1 1 255\n\0"),
+            Err(VImageError::Unsupported(_))
+        ));
+    }
+}
@@ -1,5 +1,15 @@
 pub mod vcanvas;
 pub mod vcolor;
 pub mod to_rgba32;
+pub mod to_rgba64;
 pub mod to_ppm;
-pub mod to_png;
\ No newline at end of file
+pub mod to_png;
+pub mod to_hdr;
+pub mod to_gif;
+pub mod tone_map;
+pub mod from_png;
+pub mod from_ppm;
+pub mod from_hdr;
+pub mod compare;
+pub mod process;
+pub mod animated;
\ No newline at end of file
@@ -0,0 +1,404 @@
+use crate::canvas::vcanvas::{VCanvas, VImageError};
+use crate::canvas::vcolor::VColor;
+use crate::F;
+
+/// Reconstruction filter used when resizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VResampleFilter {
+    /// Picks the closest pixel, keeps hard edges.
+    Nearest,
+    /// Linear interpolation, a tent filter when shrinking.
+    Triangle,
+    /// Windowed sinc with three lobes, sharp with slight ringing.
+    Lanczos3,
+}
+impl VResampleFilter {
+    fn radius(&self) -> F {
+        match self {
+            VResampleFilter::Nearest => 0.5,
+            VResampleFilter::Triangle => 1.0,
+            VResampleFilter::Lanczos3 => 3.0,
+        }
+    }
+    fn weight(&self, x: F) -> F {
+        let x = x.abs();
+        match self {
+            VResampleFilter::Nearest => {
+                if x <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            VResampleFilter::Triangle => (1.0 - x).max(0.0),
+            VResampleFilter::Lanczos3 if x < F::EPSILON => 1.0,
+            VResampleFilter::Lanczos3 if x < 3.0 => {
+                let px = std::f64::consts::PI * x;
+                3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+            }
+            VResampleFilter::Lanczos3 => 0.0,
+        }
+    }
+}
+
+/// Post processing on the float pixels, every operation returns a new
+/// canvas. Pixels outside the canvas repeat the closest edge pixel.
+impl VCanvas {
+    /// The part starting at `x`, `y`, cut to the canvas.
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> VCanvas {
+        let width = width.min(self.width.saturating_sub(x));
+        let height = height.min(self.height.saturating_sub(y));
+        let mut canvas = VCanvas::new(width, height);
+        for cy in 0..height {
+            for cx in 0..width {
+                canvas.write_pixel(cx, cy, self.pixel_at(x + cx, y + cy));
+                canvas.write_alpha(cx, cy, self.alpha_at(x + cx, y + cy));
+            }
+        }
+        canvas
+    }
+    pub fn flipped_horizontally(&self) -> VCanvas {
+        self.remapped(|x, y| (self.width - 1 - x, y))
+    }
+    pub fn flipped_vertically(&self) -> VCanvas {
+        self.remapped(|x, y| (x, self.height - 1 - y))
+    }
+    /// Resamples to the new size, shrinking widens the filter so every
+    /// source pixel contributes.
+    pub fn resized(&self, width: usize, height: usize, filter: VResampleFilter) -> VCanvas {
+        self.filtered(|canvas| {
+            canvas.separable(
+                |row| resample(row, width, filter),
+                |column| resample(column, height, filter),
+            )
+        })
+    }
+    /// Puts this canvas over `background` with the Porter-Duff over
+    /// operator, the result covers what either of them covers. Both
+    /// canvases need the same size.
+    pub fn over(&self, background: &VCanvas) -> Result<VCanvas, VImageError> {
+        if (self.width, self.height) != (background.width, background.height) {
+            return Err(VImageError::Invalid(format!(
+                "can not composite a {}x{} over a {}x{} image",
+                self.width, self.height, background.width, background.height
+            )));
+        }
+        let mut canvas = VCanvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let a = self.alpha_at(x, y).clamp(0.0, 1.0);
+                let b = background.alpha_at(x, y).clamp(0.0, 1.0) * (1.0 - a);
+                let alpha = a + b;
+                if alpha > 0.0 {
+                    let color = self.pixel_at(x, y) * a + background.pixel_at(x, y) * b;
+                    canvas.write_pixel(x, y, color * (1.0 / alpha));
+                }
+                canvas.write_alpha(x, y, alpha);
+            }
+        }
+        Ok(canvas)
+    }
+    /// Gaussian blur, `sigma` is the standard deviation in pixels.
+    pub fn blurred(&self, sigma: F) -> VCanvas {
+        if sigma <= 0.0 {
+            return self.clone();
+        }
+        let radius = (3.0 * sigma).ceil() as isize;
+        let kernel: Vec<F> = (-radius..=radius)
+            .map(|i| (-(i * i) as F / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let sum: F = kernel.iter().sum();
+        let kernel: Vec<F> = kernel.iter().map(|w| w / sum).collect();
+        self.filtered(|canvas| {
+            canvas.separable(
+                |row| convolve(row, &kernel),
+                |column| convolve(column, &kernel),
+            )
+        })
+    }
+    /// Glow around highlights: everything brighter than `threshold` is
+    /// blurred with `sigma` and added back scaled by `strength`.
+    pub fn bloom(&self, threshold: F, sigma: F, strength: F) -> VCanvas {
+        let bright = self.remapped_colors(|_, _, c| {
+            VColor::new(
+                (c.r - threshold).max(0.0),
+                (c.g - threshold).max(0.0),
+                (c.b - threshold).max(0.0),
+            )
+        });
+        let glow = bright.blurred(sigma);
+        self.remapped_colors(|x, y, c| c + glow.pixel_at(x, y) * strength)
+    }
+
+    fn remapped<M: Fn(usize, usize) -> (usize, usize)>(&self, source: M) -> VCanvas {
+        let mut canvas = VCanvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (sx, sy) = source(x, y);
+                canvas.write_pixel(x, y, self.pixel_at(sx, sy));
+                canvas.write_alpha(x, y, self.alpha_at(sx, sy));
+            }
+        }
+        canvas
+    }
+    // Changes the colors, the alpha stays.
+    fn remapped_colors<M: Fn(usize, usize, VColor) -> VColor>(&self, color: M) -> VCanvas {
+        let mut canvas = self.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                canvas.write_pixel(x, y, color(x, y, self.pixel_at(x, y)));
+            }
+        }
+        canvas
+    }
+    // Runs a filter that mixes neighbouring pixels. Transparent pixels are
+    // filtered premultiplied, so their colors do not bleed into the covered
+    // ones, and the alpha is filtered alongside as a gray image.
+    fn filtered<O: Fn(&VCanvas) -> VCanvas>(&self, operation: O) -> VCanvas {
+        if self.is_opaque() {
+            return operation(self);
+        }
+        let mut premultiplied = VCanvas::new(self.width, self.height);
+        let mut alpha = VCanvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let a = self.alpha_at(x, y);
+                premultiplied.write_pixel(x, y, self.pixel_at(x, y) * a);
+                alpha.write_pixel(x, y, VColor::new(a, a, a));
+            }
+        }
+        let mut canvas = operation(&premultiplied);
+        let alpha = operation(&alpha);
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                // Lanczos can ring below 0 and above 1
+                let a = alpha.pixel_at(x, y).r.clamp(0.0, 1.0);
+                let color = if a > F::EPSILON {
+                    canvas.pixel_at(x, y) * (1.0 / a)
+                } else {
+                    VColor::black()
+                };
+                canvas.write_pixel(x, y, color);
+                canvas.write_alpha(x, y, a);
+            }
+        }
+        canvas
+    }
+    // Runs `rows` over every row, then `columns` over every column of that.
+    fn separable<R, C>(&self, rows: R, columns: C) -> VCanvas
+    where
+        R: Fn(&[VColor]) -> Vec<VColor>,
+        C: Fn(&[VColor]) -> Vec<VColor>,
+    {
+        let rows: Vec<Vec<VColor>> = self
+            .get_pixels()
+            .chunks(self.width.max(1))
+            .take(self.height)
+            .map(rows)
+            .collect();
+        let width = rows.first().map_or(0, |row| row.len());
+        let columns: Vec<Vec<VColor>> = (0..width)
+            .map(|x| columns(&rows.iter().map(|row| row[x]).collect::<Vec<_>>()))
+            .collect();
+        let height = columns.first().map_or(0, |column| column.len());
+        let mut canvas = VCanvas::new(width, height);
+        for (x, column) in columns.iter().enumerate() {
+            for (y, color) in column.iter().enumerate() {
+                canvas.write_pixel(x, y, *color);
+            }
+        }
+        canvas
+    }
+}
+
+fn resample(line: &[VColor], length: usize, filter: VResampleFilter) -> Vec<VColor> {
+    if line.is_empty() {
+        return vec![VColor::black(); length];
+    }
+    let scale = line.len() as F / length as F;
+    // Shrinking stretches the filter over the source pixels
+    let stretch = scale.max(1.0);
+    let support = filter.radius() * stretch;
+    (0..length)
+        .map(|i| {
+            let center = (i as F + 0.5) * scale - 0.5;
+            let first = (center - support).ceil() as isize;
+            let last = (center + support).floor() as isize;
+            let mut sum = VColor::black();
+            let mut total = 0.0;
+            for j in first..=last {
+                let w = filter.weight((j as F - center) / stretch);
+                sum = sum + clamped(line, j) * w;
+                total += w;
+            }
+            if total.abs() < F::EPSILON {
+                clamped(line, center.round() as isize)
+            } else {
+                sum * (1.0 / total)
+            }
+        })
+        .collect()
+}
+fn convolve(line: &[VColor], kernel: &[F]) -> Vec<VColor> {
+    let radius = (kernel.len() / 2) as isize;
+    (0..line.len() as isize)
+        .map(|i| {
+            kernel
+                .iter()
+                .enumerate()
+                .fold(VColor::black(), |sum, (k, w)| {
+                    sum + clamped(line, i + k as isize - radius) * *w
+                })
+        })
+        .collect()
+}
+fn clamped(line: &[VColor], i: isize) -> VColor {
+    line[i.clamp(0, line.len() as isize - 1) as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::*;
+
+    fn numbered(width: usize, height: usize) -> VCanvas {
+        let mut c = VCanvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                c.write_pixel(x, y, VColor::new(x as F, y as F, 1.0));
+            }
+        }
+        c
+    }
+    fn total(c: &VCanvas) -> VColor {
+        c.get_pixels()
+            .iter()
+            .fold(VColor::black(), |sum, color| sum + *color)
+    }
+
+    #[test]
+    fn cropping_a_canvas() {
+        let c = numbered(5, 4).crop(1, 2, 3, 5);
+
+        assert_eq!((c.width, c.height), (3, 2));
+        assert_zeq!(c.pixel_at(0, 0), VColor::new(1.0, 2.0, 1.0));
+        assert_zeq!(c.pixel_at(2, 1), VColor::new(3.0, 3.0, 1.0));
+        assert_eq!(numbered(5, 4).crop(6, 0, 2, 2).width, 0);
+    }
+
+    #[test]
+    fn flipping_a_canvas() {
+        let c = numbered(3, 2);
+
+        assert_zeq!(
+            c.flipped_horizontally().pixel_at(0, 1),
+            VColor::new(2.0, 1.0, 1.0)
+        );
+        assert_zeq!(
+            c.flipped_vertically().pixel_at(0, 1),
+            VColor::new(0.0, 0.0, 1.0)
+        );
+        assert_zeq!(c.flipped_vertically().flipped_vertically(), &c);
+    }
+
+    #[test]
+    fn resizing_a_canvas() {
+        let c = numbered(4, 2);
+
+        let nearest = c.resized(8, 4, VResampleFilter::Nearest);
+        assert_eq!((nearest.width, nearest.height), (8, 4));
+        assert_zeq!(nearest.pixel_at(3, 3), VColor::new(1.0, 1.0, 1.0));
+
+        // The tent reaches past the edge, which repeats the first pixel
+        let halved = c.resized(2, 1, VResampleFilter::Triangle);
+        assert_zeq!(halved.pixel_at(0, 0), VColor::new(0.625, 0.5, 1.0));
+        assert_zeq!(halved.pixel_at(1, 0), VColor::new(2.375, 0.5, 1.0));
+
+        // Constant areas stay constant with every filter
+        let mut flat = VCanvas::new(5, 5);
+        for y in 0..5 {
+            for x in 0..5 {
+                flat.write_pixel(x, y, VColor::new(0.2, 0.4, 0.6));
+            }
+        }
+        for filter in [VResampleFilter::Triangle, VResampleFilter::Lanczos3] {
+            let r = flat.resized(7, 3, filter);
+            assert_zeq!(r.pixel_at(6, 2), VColor::new(0.2, 0.4, 0.6));
+            assert_zeq!(r.pixel_at(3, 1), VColor::new(0.2, 0.4, 0.6));
+        }
+    }
+
+    #[test]
+    fn compositing_over_a_background() {
+        let mut front = VCanvas::new(3, 1);
+        let mut back = VCanvas::new(3, 1);
+        for x in 0..3 {
+            front.write_pixel(x, 0, VColor::red());
+            back.write_pixel(x, 0, VColor::blue());
+        }
+        front.write_alpha(1, 0, 0.25);
+        front.write_alpha(2, 0, 0.5);
+        back.write_alpha(2, 0, 0.5);
+        let c = front.over(&back).unwrap();
+
+        assert_zeq!(c.pixel_at(0, 0), VColor::red());
+        assert_zeq!(c.pixel_at(1, 0), VColor::new(0.25, 0.0, 0.75));
+        assert_zeq!(c.alpha_at(1, 0), 1.0);
+        assert_zeq!(c.alpha_at(2, 0), 0.75);
+        assert_zeq!(c.pixel_at(2, 0), VColor::new(2.0 / 3.0, 0.0, 1.0 / 3.0));
+    }
+
+    #[test]
+    fn compositing_canvases_of_different_sizes() {
+        let result = VCanvas::new(3, 1).over(&VCanvas::new(1, 3));
+
+        assert!(matches!(result, Err(VImageError::Invalid(_))));
+    }
+
+    #[test]
+    fn transparent_pixels_do_not_bleed_into_filters() {
+        let mut c = VCanvas::new(4, 1);
+        c.write_pixel(0, 0, VColor::green());
+        c.write_alpha(0, 0, 0.0);
+        for x in 1..4 {
+            c.write_pixel(x, 0, VColor::red());
+        }
+
+        let halved = c.resized(2, 1, VResampleFilter::Triangle);
+        assert_zeq!(halved.pixel_at(0, 0), VColor::red());
+        assert_zeq!(halved.alpha_at(0, 0), 0.5);
+        assert_zeq!(halved.alpha_at(1, 0), 1.0);
+        let blurred = c.blurred(1.0);
+        assert_zeq!(blurred.pixel_at(0, 0), VColor::red());
+        assert!(blurred.alpha_at(0, 0) < 1.0);
+        assert_zeq!(c.flipped_horizontally().alpha_at(3, 0), 0.0);
+        assert_zeq!(c.crop(0, 0, 1, 1).alpha_at(0, 0), 0.0);
+    }
+
+    #[test]
+    fn blurring_spreads_light_but_keeps_it() {
+        let mut c = VCanvas::new(9, 9);
+        c.write_pixel(4, 4, VColor::new(9.0, 0.0, 0.0));
+        let b = c.blurred(1.0);
+
+        assert_zeq!(total(&b), VColor::new(9.0, 0.0, 0.0));
+        assert!(b.pixel_at(4, 4).r < 9.0);
+        assert!(b.pixel_at(3, 4).r > 0.0);
+        assert_zeq!(b.pixel_at(3, 4), b.pixel_at(4, 5));
+        assert_zeq!(c.blurred(0.0), &c);
+    }
+
+    #[test]
+    fn bloom_only_spreads_highlights() {
+        let mut c = VCanvas::new(9, 9);
+        c.write_pixel(4, 4, VColor::new(5.0, 0.5, 0.5));
+        c.write_pixel(0, 0, VColor::new(0.8, 0.8, 0.8));
+        let b = c.bloom(1.0, 1.0, 0.5);
+
+        assert!(b.pixel_at(4, 4).r > 5.0);
+        assert!(b.pixel_at(5, 4).r > 0.0);
+        assert_zeq!(b.pixel_at(5, 4).g, 0.0);
+        assert_zeq!(b.pixel_at(0, 0), VColor::new(0.8, 0.8, 0.8));
+        assert_zeq!(total(&b) - total(&c), VColor::new(2.0, 0.0, 0.0));
+    }
+}
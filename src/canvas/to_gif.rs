@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+
+use crate::canvas::animated::VAnimatedImage;
+use crate::canvas::vcanvas::VImageError;
+
+// Pixels more transparent than this are left out of a GIF.
+const ALPHA_THRESHOLD: u8 = 128;
+// Bits kept per channel when counting colors for the palette.
+const HISTOGRAM_BITS: u32 = 5;
+
+impl VAnimatedImage {
+    /// GIF with a palette of up to 256 colors shared by all frames, picked by
+    /// median cut. GIF only knows fully transparent pixels, those get their
+    /// own palette entry.
+    pub fn to_gif(&self) -> Result<Vec<u8>, VImageError> {
+        if self.frames.is_empty() {
+            return Err(VImageError::Invalid(String::from(
+                "an animation needs at least one frame",
+            )));
+        }
+        if self.width > u16::MAX as usize || self.height > u16::MAX as usize {
+            return Err(VImageError::Unsupported(format!(
+                "GIF images can not be {}x{} pixels",
+                self.width, self.height
+            )));
+        }
+        let transparent = self
+            .frames
+            .values()
+            .any(|samples| samples.chunks(4).any(|p| p[3] < ALPHA_THRESHOLD));
+        let palette = median_cut(self.frames.values(), if transparent { 255 } else { 256 });
+        let transparent_index = 255;
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"GIF89a");
+        data.extend_from_slice(&(self.width as u16).to_le_bytes());
+        data.extend_from_slice(&(self.height as u16).to_le_bytes());
+        // Global color table of 256 entries with 8 bits per channel
+        data.extend_from_slice(&[0xf7, 0, 0]);
+        for index in 0..256 {
+            data.extend_from_slice(&palette.get(index).copied().unwrap_or([0, 0, 0]));
+        }
+        // GIFs count the repeats after the first play, without the block
+        // they are played once
+        if self.loops != 1 {
+            data.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01");
+            data.extend_from_slice(&self.loops.saturating_sub(1).to_le_bytes());
+            data.push(0);
+        }
+
+        let delay = (100.0 / self.frame_rate)
+            .round()
+            .clamp(1.0, u16::MAX as f64) as u16;
+        let mut nearest: HashMap<[u8; 3], u8> = HashMap::new();
+        for samples in self.frames.values() {
+            // Graphic control: clear transparent frames before the next one
+            let flags = if transparent { 2 << 2 | 1 } else { 1 << 2 };
+            data.extend_from_slice(&[0x21, 0xf9, 4, flags]);
+            data.extend_from_slice(&delay.to_le_bytes());
+            data.extend_from_slice(&[transparent_index, 0]);
+
+            data.push(0x2c);
+            data.extend_from_slice(&[0, 0, 0, 0]);
+            data.extend_from_slice(&(self.width as u16).to_le_bytes());
+            data.extend_from_slice(&(self.height as u16).to_le_bytes());
+            data.push(0);
+
+            let indices: Vec<u8> = samples
+                .chunks(4)
+                .map(|p| {
+                    if p[3] < ALPHA_THRESHOLD {
+                        return transparent_index;
+                    }
+                    let color = [p[0], p[1], p[2]];
+                    *nearest
+                        .entry(color)
+                        .or_insert_with(|| nearest_index(&palette, color))
+                })
+                .collect();
+            data.push(8);
+            for block in lzw_encode(&indices, 8).chunks(255) {
+                data.push(block.len() as u8);
+                data.extend_from_slice(block);
+            }
+            data.push(0);
+        }
+        data.push(0x3b);
+        Ok(data)
+    }
+}
+
+// Colors of one box of the median cut, with how often each appears.
+struct ColorBox {
+    colors: Vec<([u64; 3], u64)>,
+}
+impl ColorBox {
+    // Channel with the largest spread and its extent
+    fn widest_channel(&self) -> (usize, u64) {
+        (0..3)
+            .map(|channel| {
+                let values = self.colors.iter().map(|(sum, count)| sum[channel] / count);
+                let (min, max) =
+                    values.fold((u64::MAX, 0), |(min, max), v| (min.min(v), max.max(v)));
+                (channel, max.saturating_sub(min))
+            })
+            .max_by_key(|(_, extent)| *extent)
+            .unwrap_or((0, 0))
+    }
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.colors.sort_by_key(|(sum, count)| sum[channel] / count);
+        // Splits where half of the pixels are on either side
+        let total: u64 = self.colors.iter().map(|(_, count)| count).sum();
+        let mut seen = 0;
+        let mut at = self
+            .colors
+            .iter()
+            .position(|(_, count)| {
+                seen += count;
+                seen * 2 >= total
+            })
+            .unwrap_or(0)
+            + 1;
+        at = at.min(self.colors.len() - 1);
+        let rest = self.colors.split_off(at);
+        (self, ColorBox { colors: rest })
+    }
+    fn average(&self) -> [u8; 3] {
+        let mut sum = [0; 3];
+        let mut total = 0;
+        for (color, count) in self.colors.iter() {
+            for channel in 0..3 {
+                sum[channel] += color[channel];
+            }
+            total += count;
+        }
+        sum.map(|s| (s as f64 / total.max(1) as f64).round() as u8)
+    }
+}
+
+// Picks up to `size` colors representing the pixels of all frames. Colors
+// are counted in a coarse histogram, which keeps the boxes small, but each
+// bin remembers the exact colors that fell into it.
+fn median_cut<'a>(frames: impl Iterator<Item = &'a Vec<u8>>, size: usize) -> Vec<[u8; 3]> {
+    let shift = 8 - HISTOGRAM_BITS;
+    let mut bins: HashMap<u32, ([u64; 3], u64)> = HashMap::new();
+    for samples in frames {
+        for p in samples.chunks(4).filter(|p| p[3] >= ALPHA_THRESHOLD) {
+            let key = (p[0] as u32 >> shift) << (2 * HISTOGRAM_BITS)
+                | (p[1] as u32 >> shift) << HISTOGRAM_BITS
+                | p[2] as u32 >> shift;
+            let (sum, count) = bins.entry(key).or_insert(([0; 3], 0));
+            for channel in 0..3 {
+                sum[channel] += p[channel] as u64;
+            }
+            *count += 1;
+        }
+    }
+    if bins.is_empty() {
+        return vec![];
+    }
+    let mut boxes = vec![ColorBox {
+        colors: bins.into_values().collect(),
+    }];
+    while boxes.len() < size {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.colors.len() > 1)
+            .max_by_key(|(_, b)| b.widest_channel().1)
+            .map(|(index, _)| index);
+        match widest {
+            Some(index) => {
+                let (a, b) = boxes.swap_remove(index).split();
+                boxes.push(a);
+                boxes.push(b);
+            }
+            None => break,
+        }
+    }
+    boxes.iter().map(ColorBox::average).collect()
+}
+fn nearest_index(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |entry: &[u8; 3]| -> i32 {
+        (0..3)
+            .map(|c| (entry[c] as i32 - color[c] as i32).pow(2))
+            .sum()
+    };
+    (0..palette.len())
+        .min_by_key(|index| distance(&palette[*index]))
+        .unwrap_or(0) as u8
+}
+
+// Variable length LZW as used by GIF, codes are packed starting with the
+// least significant bit.
+fn lzw_encode(indices: &[u8], min_code_size: u32) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut output = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end + 1;
+
+    output.write(clear, code_size);
+    let mut current = match indices.first() {
+        Some(index) => *index as u16,
+        None => {
+            output.write(end, code_size);
+            return output.finish();
+        }
+    };
+    for index in indices[1..].iter() {
+        if let Some(code) = table.get(&(current, *index)) {
+            current = *code;
+            continue;
+        }
+        output.write(current, code_size);
+        // Decoders only learn of a code once it was used, so the code size
+        // grows one code late
+        if next_code >= 1 << code_size && code_size < 12 {
+            code_size += 1;
+        }
+        if next_code >= 4095 {
+            output.write(clear, code_size);
+            table.clear();
+            code_size = min_code_size + 1;
+            next_code = end + 1;
+        } else {
+            table.insert((current, *index), next_code);
+            next_code += 1;
+        }
+        current = *index as u16;
+    }
+    output.write(current, code_size);
+    if next_code >= 1 << code_size && code_size < 12 {
+        code_size += 1;
+    }
+    output.write(end, code_size);
+    output.finish()
+}
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u32) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animator::Frame;
+    use crate::canvas::vcanvas::VCanvas;
+    use crate::canvas::vcolor::VColor;
+    use crate::F;
+
+    // Reads the codes back the way GIF decoders do.
+    fn lzw_decode(data: &[u8], min_code_size: u32) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let mut table: Vec<Vec<u8>> = vec![];
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<usize> = None;
+        let mut output = vec![];
+        let (mut buffer, mut bits, mut bytes) = (0u32, 0, data.iter());
+        loop {
+            while bits < code_size {
+                buffer |= (*bytes.next().expect("missing end code") as u32) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << code_size) - 1)) as usize;
+            buffer >>= code_size;
+            bits -= code_size;
+            if code == clear {
+                table = (0..clear).map(|i| vec![i as u8]).collect();
+                table.extend([vec![], vec![]]);
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            } else if code == clear + 1 {
+                return output;
+            }
+            let entry = match (previous, table.get(code)) {
+                (_, Some(entry)) => entry.clone(),
+                (Some(previous), None) => {
+                    let mut entry = table[previous].clone();
+                    entry.push(entry[0]);
+                    entry
+                }
+                (None, None) => panic!("unknown first code {}", code),
+            };
+            if let Some(previous) = previous {
+                let mut added = table[previous].clone();
+                added.push(entry[0]);
+                table.push(added);
+                if table.len() == 1 << code_size && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+            output.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    // Image data of each frame, skipping the headers
+    fn frame_data(gif: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = vec![];
+        let mut at = 13 + 256 * 3 + 19;
+        while gif[at] == 0x21 {
+            at += 8;
+            assert_eq!(gif[at], 0x2c);
+            at += 10;
+            assert_eq!(gif[at], 8);
+            at += 1;
+            let mut data = vec![];
+            while gif[at] != 0 {
+                data.extend_from_slice(&gif[at + 1..at + 1 + gif[at] as usize]);
+                at += 1 + gif[at] as usize;
+            }
+            frames.push(lzw_decode(&data, 8));
+            at += 1;
+        }
+        assert_eq!(gif[at..], [0x3b]);
+        frames
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let noise: Vec<u8> = (0..20000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let runs: Vec<u8> = (0..20000).map(|i| (i / 700) as u8).collect();
+
+        for indices in [vec![], vec![7], noise, runs] {
+            assert_eq!(lzw_decode(&lzw_encode(&indices, 8), 8), indices);
+        }
+    }
+
+    #[test]
+    fn few_colors_are_kept_exactly() {
+        let colors = [[255, 0, 0], [0, 128, 255], [10, 10, 10], [255, 255, 255]];
+        let samples: Vec<u8> = colors
+            .iter()
+            .cycle()
+            .take(40)
+            .flat_map(|c| [c[0], c[1], c[2], 255])
+            .collect();
+        let mut palette = median_cut([samples].iter(), 256);
+        palette.sort();
+        let mut expected = colors.to_vec();
+        expected.sort();
+
+        assert_eq!(palette, expected);
+    }
+
+    #[test]
+    fn animations_are_written_as_gif() {
+        let mut animation = VAnimatedImage::new(16, 8).with_frame_rate(20.0);
+        for current in 0..3 {
+            let mut canvas = VCanvas::new(16, 8);
+            for y in 0..8 {
+                for x in 0..16 {
+                    let shade = (x + current) as F / 18.0;
+                    canvas.write_pixel(x, y, VColor::new(shade, y as F / 8.0, 0.5));
+                }
+            }
+            canvas.write_alpha(0, 0, 0.0);
+            animation
+                .add_frame(&Frame::new(3, current), &canvas)
+                .unwrap();
+        }
+        let gif = animation.clone().with_loops(3).to_gif().unwrap();
+
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(&gif[6..10], &[16, 0, 8, 0]);
+        assert_eq!(&gif[13 + 768 + 16..13 + 768 + 18], &[2, 0]);
+        // Delay of 5 hundredths, transparent index 255
+        assert_eq!(
+            &gif[13 + 768 + 19..][..8],
+            &[0x21, 0xf9, 4, 9, 5, 0, 255, 0]
+        );
+        let frames = frame_data(&gif);
+        assert_eq!(frames.len(), 3);
+        for indices in frames.iter() {
+            assert_eq!(indices.len(), 16 * 8);
+            assert_eq!(indices[0], 255);
+            assert!(indices[1..].iter().all(|index| *index < 255));
+        }
+        // 16 x 8 plus the shifted columns are 144 colors, all of them fit
+        let palette = |index: u8| &gif[13 + index as usize * 3..][..3];
+        assert_eq!(palette(frames[0][17]), &[14, 32, 128]);
+        assert_eq!(palette(frames[2][16]), &[28, 32, 128]);
+
+        // Repeating forever is 0, playing once needs no loop block at all
+        let forever = animation.clone().with_loops(0).to_gif().unwrap();
+        assert_eq!(&forever[13 + 768 + 16..13 + 768 + 18], &[0, 0]);
+        let once = animation.with_loops(1).to_gif().unwrap();
+        assert_eq!(&once[13 + 768..][..2], &[0x21, 0xf9]);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::canvas::vcanvas::VCanvas;
+use crate::canvas::vcolor::VColor;
+use crate::F;
+
+/// Radiance RGBE images keep the full range of the colors, nothing is
+/// clamped. Negative components are written as 0.
+pub trait ToHDR {
+    fn to_hdr(&self) -> Vec<u8>;
+}
+
+impl ToHDR for VCanvas {
+    fn to_hdr(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n");
+        data.extend(format!("-Y {} +X {}\n", self.height, self.width).into_bytes());
+        for row in self.get_pixels().chunks(self.width.max(1)) {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(|c| to_rgbe(*c)).collect();
+            // Run length encoding is only defined for these widths
+            if (8..0x8000).contains(&self.width) {
+                write_rle_scanline(&rgbe, &mut data);
+            } else {
+                data.extend(rgbe.iter().flatten());
+            }
+        }
+        data
+    }
+}
+
+/// Shared exponent encoding, the mantissas of all three channels are scaled
+/// by the power of two of the brightest one.
+pub fn to_rgbe(color: VColor) -> [u8; 4] {
+    let (r, g, b) = (color.r.max(0.0), color.g.max(0.0), color.b.max(0.0));
+    let brightest = r.max(g).max(b);
+    if brightest < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // brightest = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    if brightest / (2.0 as F).powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256.0 / (2.0 as F).powi(exponent);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+pub fn from_rgbe(rgbe: [u8; 4]) -> VColor {
+    if rgbe[3] == 0 {
+        return VColor::black();
+    }
+    let scale = (2.0 as F).powi(rgbe[3] as i32 - 136);
+    VColor::new(
+        rgbe[0] as F * scale,
+        rgbe[1] as F * scale,
+        rgbe[2] as F * scale,
+    )
+}
+
+// Each channel is stored on its own, as a mix of runs of one repeated byte
+// and stretches of literal bytes.
+fn write_rle_scanline(rgbe: &[[u8; 4]], data: &mut Vec<u8>) {
+    let width = rgbe.len();
+    data.extend([2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+    for channel in 0..4 {
+        let bytes: Vec<u8> = rgbe.iter().map(|p| p[channel]).collect();
+        let mut start = 0;
+        while start < bytes.len() {
+            let run = bytes[start..]
+                .iter()
+                .take(127)
+                .take_while(|b| **b == bytes[start])
+                .count();
+            if run >= 4 {
+                data.extend([128 + run as u8, bytes[start]]);
+                start += run;
+                continue;
+            }
+            // Literals up to the next run worth encoding
+            let mut end = start + 1;
+            while end < bytes.len() && end - start < 128 {
+                let ahead = bytes[end..]
+                    .iter()
+                    .take(4)
+                    .take_while(|b| **b == bytes[end]);
+                if ahead.count() == 4 {
+                    break;
+                }
+                end += 1;
+            }
+            data.push((end - start) as u8);
+            data.extend(&bytes[start..end]);
+            start = end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::*;
+
+    // Undoes write_rle_scanline for one scanline, returns the pixels and the
+    // rest of the data.
+    fn read_rle_scanline(data: &[u8], width: usize) -> (Vec<[u8; 4]>, &[u8]) {
+        assert_eq!(
+            &data[..4],
+            &[2, 2, (width >> 8) as u8, (width & 0xff) as u8]
+        );
+        let mut pixels = vec![[0u8; 4]; width];
+        let mut offset = 4;
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = data[offset] as usize;
+                if count > 128 {
+                    for pixel in pixels[x..x + count - 128].iter_mut() {
+                        pixel[channel] = data[offset + 1];
+                    }
+                    x += count - 128;
+                    offset += 2;
+                } else {
+                    for (i, pixel) in pixels[x..x + count].iter_mut().enumerate() {
+                        pixel[channel] = data[offset + 1 + i];
+                    }
+                    x += count;
+                    offset += 1 + count;
+                }
+            }
+        }
+        (pixels, &data[offset..])
+    }
+
+    #[test]
+    fn rgbe_keeps_colors_brighter_than_white() {
+        for color in [
+            VColor::new(1.0, 0.5, 0.25),
+            VColor::new(12.5, 3.0, 0.001),
+            VColor::new(0.002, 0.003, 0.004),
+        ] {
+            let decoded = from_rgbe(to_rgbe(color));
+            let brightest = color.r.max(color.g).max(color.b);
+            for (a, b) in [
+                (decoded.r, color.r),
+                (decoded.g, color.g),
+                (decoded.b, color.b),
+            ] {
+                assert!((a - b).abs() <= brightest / 128.0, "{:?}", color);
+            }
+        }
+        assert_eq!(to_rgbe(VColor::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(to_rgbe(VColor::new(-1.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(from_rgbe([0, 0, 0, 0]), VColor::black());
+    }
+
+    #[test]
+    fn hdr_header_describes_the_canvas() {
+        let data = VCanvas::new(5, 3).to_hdr();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 3 +X 5\n";
+
+        assert!(data.starts_with(header));
+        // Too narrow for run length encoding
+        assert_eq!(data.len(), header.len() + 5 * 3 * 4);
+    }
+
+    #[test]
+    fn hdr_scanlines_are_run_length_encoded() {
+        let mut c = VCanvas::new(300, 2);
+        for x in 0..300 {
+            let color = if x % 50 < 20 {
+                VColor::new(4.0, 2.0, 1.0)
+            } else {
+                VColor::new(x as F / 300.0, 0.25, 0.0)
+            };
+            c.write_pixel(x, 0, color);
+            c.write_pixel(299 - x, 1, color);
+        }
+        let data = c.to_hdr();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 300\n".len();
+
+        let (first, rest) = read_rle_scanline(&data[header..], 300);
+        let (second, rest) = read_rle_scanline(rest, 300);
+        assert!(rest.is_empty());
+        assert!(data.len() < header + 2 * 300 * 4);
+        for x in 0..300 {
+            assert_eq!(first[x], to_rgbe(c.pixel_at(x, 0)));
+            assert_eq!(second[x], to_rgbe(c.pixel_at(x, 1)));
+        }
+        assert_zeq!(from_rgbe(first[0]), VColor::new(4.0, 2.0, 1.0));
+    }
+}
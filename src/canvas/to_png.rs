@@ -1,26 +1,49 @@
 use crate::canvas::to_rgba32::ToRGBA32;
+use crate::canvas::to_rgba64::ToRGBA64;
+use crate::canvas::tone_map::VToneMap;
 use crate::canvas::vcanvas::Sized;
-use png::Encoder;
+use png::{BitDepth, Encoder};
 pub trait ToPNG {
-    fn to_png(&self) -> Vec<u8>;
+    fn to_png(&self) -> Vec<u8> {
+        self.to_png_with(&VToneMap::default())
+    }
+    fn to_png_with(&self, tone_map: &VToneMap) -> Vec<u8>;
+    /// PNG with 16 bits per channel, smooth gradients do not band.
+    fn to_png16(&self) -> Vec<u8> {
+        self.to_png16_with(&VToneMap::default())
+    }
+    fn to_png16_with(&self, tone_map: &VToneMap) -> Vec<u8>;
 }
 
 impl<T> ToPNG for T
 where
     T: ToRGBA32,
+    T: ToRGBA64,
     T: Sized,
 {
-    fn to_png(&self) -> Vec<u8> {
-        let mut data = Vec::new();
-        let mut encoder = Encoder::new(&mut data, self.width() as u32, self.height() as u32);
-        encoder.set_color(png::ColorType::Rgba);
-        encoder.set_depth(png::BitDepth::Eight);
-        //encoder.set_filter(png::FilterType::Paeth);
-        //encoder.set_compression(Compression::Best);
-        let mut writer = encoder.write_header().unwrap();
-        writer.write_image_data(&self.to_rgba32()).unwrap();
-        drop(writer);
-
-        data
+    fn to_png_with(&self, tone_map: &VToneMap) -> Vec<u8> {
+        encode_png(self, BitDepth::Eight, &self.to_rgba32_with(tone_map))
+    }
+    fn to_png16_with(&self, tone_map: &VToneMap) -> Vec<u8> {
+        // PNG stores 16 bit samples big endian
+        let samples: Vec<u8> = self
+            .to_rgba64_with(tone_map)
+            .into_iter()
+            .flat_map(|sample| sample.to_be_bytes())
+            .collect();
+        encode_png(self, BitDepth::Sixteen, &samples)
     }
 }
+fn encode_png<T: Sized>(image: &T, depth: BitDepth, samples: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut encoder = Encoder::new(&mut data, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(depth);
+    //encoder.set_filter(png::FilterType::Paeth);
+    //encoder.set_compression(Compression::Best);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(samples).unwrap();
+    drop(writer);
+
+    data
+}
@@ -1,4 +1,5 @@
 use crate::canvas::to_rgba32::ToRGBA32;
+use crate::canvas::tone_map::VToneMap;
 use crate::canvas::vcanvas::Sized;
 
 pub trait ToPPM {
@@ -12,7 +13,16 @@ pub trait ToPPM {
         header.extend(format!("{}\n", 255).into_bytes());
         return header;
     }
-    fn to_ppm(&self) -> Vec<u8>;
+    fn to_ppm(&self) -> Vec<u8> {
+        self.to_ppm_with(&VToneMap::default())
+    }
+    fn to_ppm_with(&self, tone_map: &VToneMap) -> Vec<u8>;
+    /// Binary P6 variant, a third of the size of the plain one at most and
+    /// much faster to write.
+    fn to_binary_ppm(&self) -> Vec<u8> {
+        self.to_binary_ppm_with(&VToneMap::default())
+    }
+    fn to_binary_ppm_with(&self, tone_map: &VToneMap) -> Vec<u8>;
 }
 
 impl<T> ToPPM for T
@@ -20,46 +30,52 @@ where
     T: ToRGBA32,
     T: Sized,
 {
-    fn to_ppm(&self) -> Vec<u8> {
-        let mut last_image_row: usize = 0;
-        let mut column_count: usize = 0;
-        let pixel_data = self
-            .to_rgba32()
-            .into_iter()
-            .map(|byte| format!("{}", byte))
-            .enumerate()
-            .filter(|(i, _)| (i + 1) % 4 != 0)
-            .enumerate()
-            .flat_map(|(i, (_, pixel_string))| {
-                let mut data: Vec<u8> = Vec::new();
-                let current_image_row = i / (self.width() * 3);
-                if current_image_row != last_image_row {
-                    last_image_row = current_image_row;
-                    data.extend(String::from("\n").into_bytes());
-                    column_count = 0;
-                }
-                let mut needed_space: usize = 0;
-                if column_count != 0 {
-                    needed_space += 1;
-                }
-                needed_space += pixel_string.len();
-                if column_count + needed_space > 70 {
-                    data.extend(String::from("\n").as_bytes());
+    fn to_ppm_with(&self, tone_map: &VToneMap) -> Vec<u8> {
+        let rgba = self.to_rgba32_with(tone_map);
+        let mut data = self.create_ppm_header();
+        data.reserve(rgba.len() * 3);
+        let mut digits = [0u8; 3];
+        for row in rgba.chunks(self.width().max(1) * 4) {
+            // Lines are wrapped to stay within 70 characters
+            let mut column_count: usize = 0;
+            for byte in row.iter().enumerate().filter(|(i, _)| (i + 1) % 4 != 0) {
+                let text = decimal(*byte.1, &mut digits);
+                if column_count != 0 && column_count + 1 + text.len() > 70 {
+                    data.push(b'\n');
                     column_count = 0;
                 }
                 if column_count != 0 {
-                    data.extend(String::from(" ").as_bytes());
+                    data.push(b' ');
                     column_count += 1;
                 }
-                data.extend(pixel_string.clone().into_bytes());
-                column_count += pixel_string.len();
-
-                data
-            });
-        self.create_ppm_header()
-            .into_iter()
-            .chain(pixel_data)
-            .chain(String::from("\n").into_bytes())
-            .collect()
+                data.extend(text);
+                column_count += text.len();
+            }
+            data.push(b'\n');
+        }
+        data
+    }
+    fn to_binary_ppm_with(&self, tone_map: &VToneMap) -> Vec<u8> {
+        let mut data = format!("P6\n{} {}\n255\n", self.width(), self.height()).into_bytes();
+        data.extend(
+            self.to_rgba32_with(tone_map)
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| (i + 1) % 4 != 0)
+                .map(|(_, byte)| byte),
+        );
+        data
+    }
+}
+fn decimal(byte: u8, digits: &mut [u8; 3]) -> &[u8] {
+    let mut start = 3;
+    let mut value = byte;
+    loop {
+        start -= 1;
+        digits[start] = b'0' + value % 10;
+        value /= 10;
+        if value == 0 {
+            return &digits[start..];
+        }
     }
 }
@@ -1,18 +1,22 @@
+use crate::canvas::tone_map::VToneMap;
 use crate::canvas::vcanvas::VCanvas;
 
 pub trait ToRGBA32 {
-    fn to_rgba32(&self) -> Vec<u8>;
+    fn to_rgba32(&self) -> Vec<u8> {
+        self.to_rgba32_with(&VToneMap::default())
+    }
+    fn to_rgba32_with(&self, tone_map: &VToneMap) -> Vec<u8>;
 }
 
 impl ToRGBA32 for VCanvas {
-    fn to_rgba32(&self) -> Vec<u8> {
+    fn to_rgba32_with(&self, tone_map: &VToneMap) -> Vec<u8> {
         let mut data: Vec<u8> = Vec::new();
-        for pixel in self.get_pixels().iter() {
-            let clamped_color = pixel.clamp(0.0, 1.0);
+        for (pixel, alpha) in self.get_pixels().iter().zip(self.get_alpha()) {
+            let (clamped_color, alpha) = tone_map.map_with_alpha(*pixel, *alpha);
             let r: u8 = (clamped_color.r * 255.0).round() as u8;
             let g: u8 = (clamped_color.g * 255.0).round() as u8;
             let b: u8 = (clamped_color.b * 255.0).round() as u8;
-            let a: u8 = 255;
+            let a: u8 = (alpha * 255.0).round() as u8;
 
             data.push(r);
             data.push(g);
@@ -0,0 +1,25 @@
+use crate::canvas::tone_map::VToneMap;
+use crate::canvas::vcanvas::VCanvas;
+
+/// Like [`ToRGBA32`](crate::canvas::to_rgba32::ToRGBA32), with 16 bits per
+/// channel.
+pub trait ToRGBA64 {
+    fn to_rgba64(&self) -> Vec<u16> {
+        self.to_rgba64_with(&VToneMap::default())
+    }
+    fn to_rgba64_with(&self, tone_map: &VToneMap) -> Vec<u16>;
+}
+
+impl ToRGBA64 for VCanvas {
+    fn to_rgba64_with(&self, tone_map: &VToneMap) -> Vec<u16> {
+        let mut data: Vec<u16> = Vec::with_capacity(self.get_pixels().len() * 4);
+        for (pixel, alpha) in self.get_pixels().iter().zip(self.get_alpha()) {
+            let (color, alpha) = tone_map.map_with_alpha(*pixel, *alpha);
+            data.push((color.r * 65535.0).round() as u16);
+            data.push((color.g * 65535.0).round() as u16);
+            data.push((color.b * 65535.0).round() as u16);
+            data.push((alpha * 65535.0).round() as u16);
+        }
+        data
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::vcolor::VColor;
+use crate::F;
+
+/// Curve compressing the unbounded colors of a render into [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VToneOperator {
+    /// Cuts everything above 1 off, bright highlights blow out.
+    Clamp,
+    /// `x / (1 + x)` per channel, never reaches white.
+    Reinhard,
+    /// Fit of the ACES filmic curve by Krzysztof Narkowicz.
+    Aces,
+}
+
+/// Turns linear render colors into displayable ones: exposure first, then the
+/// tone operator and finally the optional sRGB transfer function. The
+/// default changes nothing but clamping, which is what the exporters always
+/// did.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VToneMap {
+    /// Brightness change in stops, each one doubles the light.
+    pub exposure: F,
+    pub operator: VToneOperator,
+    /// Gamma encodes the result for sRGB displays.
+    pub srgb: bool,
+    /// Multiplies the exported colors with their alpha, for compositors
+    /// expecting premultiplied data. Formats without alpha ignore it.
+    #[serde(default)]
+    pub premultiplied: bool,
+}
+impl Default for VToneMap {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            operator: VToneOperator::Clamp,
+            srgb: false,
+            premultiplied: false,
+        }
+    }
+}
+impl VToneMap {
+    pub fn new(operator: VToneOperator) -> Self {
+        Self {
+            operator,
+            ..Self::default()
+        }
+    }
+    pub fn with_exposure(mut self, exposure: F) -> Self {
+        self.exposure = exposure;
+        self
+    }
+    pub fn with_srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+    pub fn with_premultiplied_alpha(mut self, premultiplied: bool) -> Self {
+        self.premultiplied = premultiplied;
+        self
+    }
+
+    pub fn map(&self, color: VColor) -> VColor {
+        let scale = (2.0 as F).powf(self.exposure);
+        let channel = |value: F| {
+            let value = (value * scale).max(0.0);
+            let value = match self.operator {
+                VToneOperator::Clamp => value,
+                VToneOperator::Reinhard => value / (1.0 + value),
+                VToneOperator::Aces => {
+                    (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+                }
+            };
+            let value = value.min(1.0);
+            if self.srgb {
+                srgb_encode(value)
+            } else {
+                value
+            }
+        };
+        VColor::new(channel(color.r), channel(color.g), channel(color.b))
+    }
+    /// Maps the color and clamps the alpha, premultiplying after the tone
+    /// curve so the stored values are what a display blends with.
+    pub fn map_with_alpha(&self, color: VColor, alpha: F) -> (VColor, F) {
+        let alpha = alpha.clamp(0.0, 1.0);
+        let color = self.map(color);
+        if self.premultiplied {
+            (color * alpha, alpha)
+        } else {
+            (color, alpha)
+        }
+    }
+}
+
+/// sRGB transfer function, from linear light to the encoded value.
+pub fn srgb_encode(linear: F) -> F {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+/// Inverse of [`srgb_encode`].
+pub fn srgb_decode(encoded: F) -> F {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::*;
+
+    #[test]
+    fn the_default_tone_map_only_clamps() {
+        let t = VToneMap::default();
+
+        assert_zeq!(
+            t.map(VColor::new(0.25, 1.5, -0.5)),
+            VColor::new(0.25, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn exposure_is_counted_in_stops() {
+        let c = VColor::new(0.1, 0.2, 0.4);
+
+        assert_zeq!(
+            VToneMap::default().with_exposure(1.0).map(c),
+            VColor::new(0.2, 0.4, 0.8)
+        );
+        assert_zeq!(
+            VToneMap::default().with_exposure(-2.0).map(c),
+            VColor::new(0.025, 0.05, 0.1)
+        );
+    }
+
+    #[test]
+    fn operators_compress_highlights() {
+        let reinhard = VToneMap::new(VToneOperator::Reinhard);
+        let aces = VToneMap::new(VToneOperator::Aces);
+
+        assert_zeq!(
+            reinhard.map(VColor::new(1.0, 3.0, 0.0)),
+            VColor::new(0.5, 0.75, 0.0)
+        );
+        let bright = aces.map(VColor::new(0.5, 1.0, 2.0));
+        assert!(bright.r < bright.g && bright.g < bright.b && bright.b < 1.0);
+        assert_zeq!(aces.map(VColor::new(100.0, 0.0, 0.0)).r, 1.0);
+        assert_zeq!(aces.map(VColor::black()), VColor::black());
+        assert_zeq!(aces.map(VColor::new(0.18, 0.18, 0.18)).r, 0.2669);
+    }
+
+    #[test]
+    fn srgb_brightens_the_midtones() {
+        assert_zeq!(srgb_encode(0.0), 0.0);
+        assert_zeq!(srgb_encode(1.0), 1.0);
+        assert_zeq!(srgb_encode(0.214), 0.5);
+        assert_zeq!(srgb_decode(srgb_encode(0.3)), 0.3);
+        assert_zeq!(srgb_decode(srgb_encode(0.002)), 0.002);
+
+        let t = VToneMap::default().with_srgb(true);
+        assert_zeq!(
+            t.map(VColor::new(0.214, 2.0, 0.0)),
+            VColor::new(0.5, 1.0, 0.0)
+        );
+    }
+}
@@ -1,5 +1,37 @@
 use crate::canvas::vcolor::*;
+use crate::F;
+use std::fmt;
+use std::path::Path;
 use std::vec::Vec;
+
+#[derive(Debug)]
+pub enum VImageError {
+    Io(std::io::Error),
+    Png(png::DecodingError),
+    Invalid(String),
+    Unsupported(String),
+}
+impl fmt::Display for VImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VImageError::Io(e) => write!(f, "could not access image: {}", e),
+            VImageError::Png(e) => write!(f, "invalid PNG image: {}", e),
+            VImageError::Invalid(message) => write!(f, "invalid image: {}", message),
+            VImageError::Unsupported(message) => write!(f, "unsupported image: {}", message),
+        }
+    }
+}
+impl std::error::Error for VImageError {}
+impl From<std::io::Error> for VImageError {
+    fn from(e: std::io::Error) -> Self {
+        VImageError::Io(e)
+    }
+}
+impl From<png::DecodingError> for VImageError {
+    fn from(e: png::DecodingError) -> Self {
+        VImageError::Png(e)
+    }
+}
 pub trait Sized {
     fn width(&self) -> usize;
     fn height(&self) -> usize;
@@ -12,11 +44,13 @@ impl Sized for VCanvas {
         self.height
     }
 }
+#[derive(Debug, Clone, PartialEq)]
 pub struct VCanvas {
     pub width: usize,
     pub height: usize,
 
     pixels: Vec<VColor>,
+    alpha: Vec<F>,
 }
 impl VCanvas {
     pub fn new(width: usize, height: usize) -> Self {
@@ -24,6 +58,7 @@ impl VCanvas {
             width,
             height,
             pixels: vec![VColor::black(); width * height],
+            alpha: vec![1.0; width * height],
         }
     }
     fn get_pixel_index(&self, x: usize, y: usize) -> usize {
@@ -39,12 +74,44 @@ impl VCanvas {
     pub fn get_pixels(&self) -> &Vec<VColor> {
         &self.pixels
     }
+    /// Sets the coverage of a pixel, 0 is fully transparent. Colors are kept
+    /// as they are, not multiplied with the alpha.
+    pub fn write_alpha(&mut self, x: usize, y: usize, alpha: F) {
+        let index = self.get_pixel_index(x, y);
+        self.alpha[index] = alpha;
+    }
+    pub fn alpha_at(&self, x: usize, y: usize) -> F {
+        self.alpha[self.get_pixel_index(x, y)]
+    }
+    pub fn get_alpha(&self) -> &Vec<F> {
+        &self.alpha
+    }
+    pub fn is_opaque(&self) -> bool {
+        self.alpha.iter().all(|a| *a >= 1.0)
+    }
+    /// Reads a PNG, PPM or HDR file, the format is told by the content.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VImageError> {
+        let data = std::fs::read(path)?;
+        if data.starts_with(b"\x89PNG") {
+            Self::from_png(&data)
+        } else if data.starts_with(b"P3") || data.starts_with(b"P6") {
+            Self::from_ppm(&data)
+        } else if data.starts_with(b"#?") {
+            Self::from_hdr(&data)
+        } else {
+            Err(VImageError::Unsupported(String::from(
+                "only PNG, PPM and HDR images can be read",
+            )))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::canvas::to_ppm::ToPPM;
+    use crate::canvas::to_rgba32::ToRGBA32;
+    use crate::canvas::tone_map::*;
 
     #[test]
     fn creating_a_canvas() {
@@ -95,4 +162,59 @@ mod tests {
 
         assert_eq!(result, expected_result)
     }
+    #[test]
+    fn tone_mapping_the_pixel_data() {
+        let mut cnv = VCanvas::new(2, 1);
+        cnv.write_pixel(0, 0, VColor::new(1.5, 0.214, 0.0));
+        cnv.write_pixel(1, 0, VColor::new(0.5, 1.0, 3.0));
+
+        let tone_map = VToneMap::new(VToneOperator::Reinhard).with_srgb(true);
+        let result = cnv.to_ppm_with(&tone_map);
+        let expected_result =
+            String::from("P3\n2 1\n255\n203 117 0 156 188 225\n").into_bytes();
+
+        assert_eq!(result, expected_result)
+    }
+    #[test]
+    fn splitting_long_lines_in_ppm_files() {
+        let mut cnv = VCanvas::new(10, 2);
+        for y in 0..2 {
+            for x in 0..10 {
+                cnv.write_pixel(x, y, VColor::new(1.0, 0.8, 0.6));
+            }
+        }
+
+        let result = String::from_utf8(cnv.to_ppm()).unwrap();
+        let line = "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204\n\
+                    153 255 204 153 255 204 153 255 204 153 255 204 153\n";
+
+        assert_eq!(result, format!("P3\n10 2\n255\n{}{}", line, line))
+    }
+    #[test]
+    fn constructing_binary_pixel_data() {
+        let mut cnv = VCanvas::new(2, 1);
+        cnv.write_pixel(0, 0, VColor::new(1.5, 0.0, 0.5));
+        cnv.write_pixel(1, 0, VColor::new(0.0, 0.2, 1.0));
+
+        let result = cnv.to_binary_ppm();
+        let mut expected_result = String::from("P6\n2 1\n255\n").into_bytes();
+        expected_result.extend([255, 0, 128, 0, 51, 255]);
+
+        assert_eq!(result, expected_result)
+    }
+    #[test]
+    fn constructing_rgba_pixel_data_with_alpha() {
+        let mut cnv = VCanvas::new(2, 1);
+        cnv.write_pixel(0, 0, VColor::new(1.0, 0.4, 0.0));
+        cnv.write_alpha(0, 0, 0.5);
+        cnv.write_alpha(1, 0, 0.0);
+
+        assert!(!cnv.is_opaque());
+        assert_eq!(cnv.to_rgba32(), vec![255, 102, 0, 128, 0, 0, 0, 0]);
+        let premultiplied = VToneMap::default().with_premultiplied_alpha(true);
+        assert_eq!(
+            cnv.to_rgba32_with(&premultiplied),
+            vec![128, 51, 0, 128, 0, 0, 0, 0]
+        );
+    }
 }
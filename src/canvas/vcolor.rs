@@ -1,12 +1,12 @@
 use std::ops;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::F;
 
 use crate::zequality::ZEq;
 
-#[derive(Debug, Clone, Copy,PartialEq,Deserialize)]
+#[derive(Debug, Clone, Copy,PartialEq, Serialize, Deserialize)]
 pub struct VColor
 {
     pub r: F,
@@ -0,0 +1,441 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::background::VBackground;
+use crate::camera::VCamera;
+use crate::canvas::vcolor::VColor;
+use crate::renderer::*;
+use crate::world::VWorld;
+
+const MAGIC: &[u8; 4] = b"VRCK";
+/// Bumped whenever the layout written by [`VCheckpoint::to_bytes`] changes.
+pub const CHECKPOINT_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum VCheckpointError {
+    Io(std::io::Error),
+    Invalid(String),
+    /// The file was written by an incompatible version of the format.
+    Version(u32),
+    /// The file belongs to a different world, camera or sampling settings.
+    SceneMismatch,
+}
+impl fmt::Display for VCheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VCheckpointError::Io(e) => write!(f, "could not access checkpoint: {}", e),
+            VCheckpointError::Invalid(message) => write!(f, "invalid checkpoint: {}", message),
+            VCheckpointError::Version(version) => write!(
+                f,
+                "checkpoint has version {}, expected {}",
+                version, CHECKPOINT_VERSION
+            ),
+            VCheckpointError::SceneMismatch => {
+                write!(f, "checkpoint was written for a different scene")
+            }
+        }
+    }
+}
+impl std::error::Error for VCheckpointError {}
+impl From<std::io::Error> for VCheckpointError {
+    fn from(e: std::io::Error) -> Self {
+        VCheckpointError::Io(e)
+    }
+}
+
+/// The state of a progressive render together with a hash of the scene it
+/// belongs to, so it is not resumed with a changed scene or with samples
+/// drawn differently.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VCheckpoint {
+    pub scene_hash: u64,
+    pub state: RenderState,
+}
+impl VCheckpoint {
+    pub fn new(
+        world: &VWorld,
+        camera: &VCamera,
+        settings: &RenderSettings,
+        state: RenderState,
+    ) -> Self {
+        Self {
+            scene_hash: scene_hash(world, camera, settings),
+            state,
+        }
+    }
+
+    /// Little endian layout: magic, version, scene hash, width, height, tile
+    /// size, completed passes, the completed tiles of the next pass and
+    /// finally the summed color, summed alpha and sample count of every
+    /// pixel.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let state = &self.state;
+        let mut data = Vec::with_capacity(64 + state.sums.len() * 40);
+        data.extend(MAGIC);
+        data.extend(CHECKPOINT_VERSION.to_le_bytes());
+        data.extend(self.scene_hash.to_le_bytes());
+        for value in [
+            state.width,
+            state.height,
+            state.tile_size,
+            state.passes_completed,
+            state.tiles_completed.len(),
+        ] {
+            data.extend((value as u64).to_le_bytes());
+        }
+        for tile in state.tiles_completed.iter() {
+            data.extend((*tile as u64).to_le_bytes());
+        }
+        for index in 0..state.sums.len() {
+            let sum = state.sums[index];
+            data.extend(sum.r.to_le_bytes());
+            data.extend(sum.g.to_le_bytes());
+            data.extend(sum.b.to_le_bytes());
+            data.extend(state.alphas[index].to_le_bytes());
+            data.extend((state.counts[index] as u64).to_le_bytes());
+        }
+        data
+    }
+    pub fn from_bytes(data: &[u8]) -> Result<Self, VCheckpointError> {
+        if !data.starts_with(MAGIC) {
+            return Err(invalid("not a checkpoint file"));
+        }
+        let mut reader = Reader { data: &data[4..] };
+        let version = u32::from_le_bytes(reader.take()?);
+        if version != CHECKPOINT_VERSION {
+            return Err(VCheckpointError::Version(version));
+        }
+        let scene_hash = u64::from_le_bytes(reader.take()?);
+        let width = reader.usize()?;
+        let height = reader.usize()?;
+        let tile_size = reader.usize()?;
+        if tile_size == 0 {
+            return Err(invalid("tile size of 0"));
+        }
+        let passes_completed = reader.usize()?;
+        let mut tiles_completed = vec![];
+        for _ in 0..reader.usize()? {
+            tiles_completed.push(reader.usize()?);
+        }
+        // Checked before the state is allocated, a corrupt header could ask
+        // for any amount of memory
+        let pixel_count = width.checked_mul(height);
+        if pixel_count.and_then(|count| count.checked_mul(40)) != Some(reader.data.len()) {
+            return Err(invalid("pixel data does not match the image size"));
+        }
+        let mut state = RenderState::new(width, height, tile_size);
+        state.passes_completed = passes_completed;
+        let tile_count = state.tiles().len();
+        if tiles_completed.iter().any(|tile| *tile >= tile_count) {
+            return Err(invalid("completed tile outside of the image"));
+        }
+        state.tiles_completed = tiles_completed;
+        for index in 0..width * height {
+            let r = f64::from_le_bytes(reader.take()?);
+            let g = f64::from_le_bytes(reader.take()?);
+            let b = f64::from_le_bytes(reader.take()?);
+            state.sums[index] = VColor::new(r, g, b);
+            state.alphas[index] = f64::from_le_bytes(reader.take()?);
+            state.counts[index] = reader.usize()?;
+        }
+        Ok(Self { scene_hash, state })
+    }
+
+    /// Writes to a temporary file next to `path` first, so an interrupted
+    /// save never destroys the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), VCheckpointError> {
+        let path = path.as_ref();
+        let mut temporary = PathBuf::from(path);
+        temporary.as_mut_os_string().push(".tmp");
+        fs::write(&temporary, self.to_bytes())?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VCheckpointError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+fn invalid(message: &str) -> VCheckpointError {
+    VCheckpointError::Invalid(String::from(message))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], VCheckpointError> {
+        if self.data.len() < N {
+            return Err(invalid("unexpected end of data"));
+        }
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(&self.data[..N]);
+        self.data = &self.data[N..];
+        Ok(bytes)
+    }
+    fn usize(&mut self) -> Result<usize, VCheckpointError> {
+        usize::try_from(u64::from_le_bytes(self.take()?)).map_err(|_| invalid("value out of range"))
+    }
+}
+
+/// FNV-1a hash over the serialized world and camera and the settings that
+/// decide which samples are drawn. Anything that changes the rendered image
+/// changes the hash, settings that only change how the work is done, like
+/// threads or budgets, do not. Environment maps are only serialized by their
+/// path, so their pixels are hashed as well.
+pub fn scene_hash(world: &VWorld, camera: &VCamera, settings: &RenderSettings) -> u64 {
+    let sampling = (
+        settings.samples,
+        settings.seed,
+        settings.jitter,
+        settings.transparent,
+    );
+    let json =
+        serde_json::to_string(&(world, camera, sampling)).expect("scenes are always serializable");
+    let hash = fnv1a(0xcbf29ce484222325, json.bytes());
+    match &world.background {
+        VBackground::Environment(map) => {
+            let image = map.image();
+            let colors = image.get_pixels().iter().flat_map(|c| [c.r, c.g, c.b]);
+            let values = colors.chain(image.get_alpha().iter().copied());
+            fnv1a(hash, values.flat_map(|value| value.to_le_bytes()))
+        }
+        _ => hash,
+    }
+}
+fn fnv1a<I: IntoIterator<Item = u8>>(hash: u64, bytes: I) -> u64 {
+    bytes.into_iter().fold(hash, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Like [`render_progressive`], but continues from the checkpoint at `path`
+/// if there is one and saves the progress there every
+/// `checkpoint_interval` and when the render ends. The checkpoint is kept
+/// after a complete render, so the caller decides when it is no longer
+/// needed.
+pub fn render_resumable<C, P>(
+    world: &VWorld,
+    camera: &VCamera,
+    settings: &RenderSettings,
+    path: P,
+    preview: C,
+) -> Result<RenderOutcome, VCheckpointError>
+where
+    C: FnMut(&RenderUpdate) -> bool,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let hash = scene_hash(world, camera, settings);
+    let state = if path.exists() {
+        let checkpoint = VCheckpoint::load(path)?;
+        if checkpoint.scene_hash != hash {
+            return Err(VCheckpointError::SceneMismatch);
+        }
+        // A matching hash does not make a damaged file fit the camera
+        let state = checkpoint.state;
+        if (state.width, state.height) != (camera.hsize, camera.vsize) || state.tile_size == 0 {
+            return Err(invalid("image size does not match the camera"));
+        }
+        state
+    } else {
+        RenderState::new(camera.hsize, camera.vsize, settings.tile_size)
+    };
+    // A failed save is reported once the render is over, the last save has
+    // to succeed for the render to be resumable.
+    let mut result = Ok(());
+    let outcome = resume_progressive(world, camera, settings, state, preview, |state| {
+        let checkpoint = VCheckpoint {
+            scene_hash: hash,
+            state: state.clone(),
+        };
+        result = checkpoint.save(path);
+    });
+    result.map(|_| outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::VEnvironmentMap;
+    use crate::canvas::vcanvas::VCanvas;
+    use crate::world::create_default_world;
+    use crate::F;
+    use std::time::Duration;
+
+    fn camera() -> VCamera {
+        VCamera::new(12, 8, std::f64::consts::PI / 3.0)
+    }
+    fn temporary_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("ray_tracer_{}_{}.ckpt", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn checkpoints_survive_a_round_trip() {
+        let mut state = RenderState::new(3, 2, 2);
+        state.sums[4] = VColor::new(0.25, 1.5, -0.125);
+        state.alphas[4] = 2.0;
+        state.counts[4] = 3;
+        state.passes_completed = 2;
+        state.tiles_completed = vec![1];
+        let checkpoint = VCheckpoint {
+            scene_hash: 42,
+            state,
+        };
+
+        assert_eq!(
+            VCheckpoint::from_bytes(&checkpoint.to_bytes()).unwrap(),
+            checkpoint
+        );
+    }
+
+    #[test]
+    fn rejecting_broken_checkpoints() {
+        let data = VCheckpoint {
+            scene_hash: 1,
+            state: RenderState::new(2, 2, 16),
+        }
+        .to_bytes();
+
+        assert!(matches!(
+            VCheckpoint::from_bytes(b"PNG"),
+            Err(VCheckpointError::Invalid(_))
+        ));
+        assert!(matches!(
+            VCheckpoint::from_bytes(&data[..data.len() - 1]),
+            Err(VCheckpointError::Invalid(_))
+        ));
+        let mut older = data.clone();
+        older[4] = 1;
+        assert!(matches!(
+            VCheckpoint::from_bytes(&older),
+            Err(VCheckpointError::Version(1))
+        ));
+    }
+
+    #[test]
+    fn rejecting_huge_images_before_allocating() {
+        let data = VCheckpoint {
+            scene_hash: 1,
+            state: RenderState::new(2, 2, 16),
+        }
+        .to_bytes();
+
+        // Too large to allocate and too large to multiply
+        for size in [1u64 << 20, 1 << 40] {
+            let mut huge = data.clone();
+            huge[16..24].copy_from_slice(&size.to_le_bytes());
+            huge[24..32].copy_from_slice(&size.to_le_bytes());
+            assert!(matches!(
+                VCheckpoint::from_bytes(&huge),
+                Err(VCheckpointError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn the_scene_hash_follows_world_camera_and_sampling() {
+        let w = create_default_world();
+        let c = camera();
+        let s = RenderSettings::default();
+        let mut moved = create_default_world();
+        moved.lights[0].pos.x += 1.0;
+        let hash = scene_hash(&w, &c, &s);
+
+        assert_eq!(hash, scene_hash(&w, &c, &s.clone().with_threads(3)));
+        assert_ne!(hash, scene_hash(&moved, &c, &s));
+        assert_ne!(hash, scene_hash(&w, &c.resized(24, 16), &s));
+        // Maps loaded from the same path differ by their pixels
+        let mut lit = create_default_world();
+        let mut sky = VCanvas::new(2, 1);
+        lit.background = VBackground::Environment(VEnvironmentMap::new(sky.clone()));
+        let before = scene_hash(&lit, &c, &s);
+        sky.write_pixel(1, 0, VColor::white());
+        lit.background = VBackground::Environment(VEnvironmentMap::new(sky));
+        assert_ne!(before, scene_hash(&lit, &c, &s));
+        for changed in [
+            s.clone().with_samples(4),
+            s.clone().with_seed(7),
+            s.clone().with_jitter(true),
+            s.clone().with_transparent_background(true),
+        ] {
+            assert_ne!(hash, scene_hash(&w, &c, &changed));
+        }
+    }
+
+    #[test]
+    fn resuming_a_stopped_render_matches_an_uninterrupted_one() {
+        let w = create_default_world();
+        let c = camera();
+        let path = temporary_path("resume");
+        let settings = RenderSettings::default()
+            .with_samples(3)
+            .with_threads(2)
+            .with_tile_size(4)
+            .with_preview_block(1)
+            .with_checkpoint_interval(Duration::ZERO);
+
+        let mut updates = 0;
+        let first = render_resumable(&w, &c, &settings, &path, |_| {
+            updates += 1;
+            updates < 8
+        })
+        .unwrap();
+        assert_eq!(first.stopped, Some(StopReason::Preview));
+        let saved = VCheckpoint::load(&path).unwrap();
+        assert_eq!(saved.state.counts, first.samples);
+
+        let resumed = render_resumable(&w, &c, &settings, &path, |_| true).unwrap();
+        let expected = render_progressive(&w, &c, &settings, |_| true);
+        fs::remove_file(&path).unwrap();
+
+        assert!(resumed.is_complete());
+        assert_eq!(resumed.samples, vec![3; 12 * 8]);
+        for y in 0..c.vsize {
+            for x in 0..c.hsize {
+                let (a, b) = (
+                    resumed.canvas.pixel_at(x, y),
+                    expected.canvas.pixel_at(x, y),
+                );
+                assert!((a.r - b.r).abs() + (a.g - b.g).abs() + (a.b - b.b).abs() < 1e-9 as F);
+            }
+        }
+    }
+
+    #[test]
+    fn refusing_to_resume_a_checkpoint_of_another_size() {
+        let w = create_default_world();
+        let c = camera();
+        let path = temporary_path("size");
+        let settings = RenderSettings::default();
+        VCheckpoint::new(&w, &c, &settings, RenderState::new(3, 3, 16))
+            .save(&path)
+            .unwrap();
+
+        let result = render_resumable(&w, &c, &settings, &path, |_| true);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(VCheckpointError::Invalid(_))));
+    }
+
+    #[test]
+    fn refusing_to_resume_a_different_scene() {
+        let c = camera();
+        let path = temporary_path("mismatch");
+        let settings = RenderSettings::default().with_threads(1);
+        render_resumable(&create_default_world(), &c, &settings, &path, |_| false).unwrap();
+
+        let mut changed = create_default_world();
+        changed.lights[0].pos.y -= 1.0;
+        let result = render_resumable(&changed, &c, &settings, &path, |_| true);
+        let reseeded = settings.clone().with_seed(1);
+        let resampled = render_resumable(&create_default_world(), &c, &reseeded, &path, |_| true);
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(VCheckpointError::SceneMismatch)));
+        assert!(matches!(resampled, Err(VCheckpointError::SceneMismatch)));
+    }
+}
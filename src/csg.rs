@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+
+use crate::body::VBody;
+use crate::body::VIntersectable;
+use crate::bounds::VBounds;
+use crate::intersections::VIntersection;
+use crate::material::*;
+use crate::matrix::*;
+use crate::ray::VRay;
+use crate::tuple::VTuple;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VCsgOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+impl VCsgOperation {
+    /// Decides whether a hit on one side is part of the combined surface.
+    /// `left_hit` tells which side was hit, `in_left` and `in_right` whether
+    /// the ray is currently inside the left or right body.
+    pub fn intersection_allowed(&self, left_hit: bool, in_left: bool, in_right: bool) -> bool {
+        match self {
+            VCsgOperation::Union => (left_hit && !in_right) || (!left_hit && !in_left),
+            VCsgOperation::Intersection => (left_hit && in_right) || (!left_hit && in_left),
+            VCsgOperation::Difference => (left_hit && !in_right) || (!left_hit && in_left),
+        }
+    }
+}
+
+/// Constructive solid geometry, combines two bodies into one. Like with
+/// groups, intersections report the child body that was hit, moved into the
+/// space of the parent.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VCsg {
+    pub operation: VCsgOperation,
+    pub left: Box<VBody>,
+    pub right: Box<VBody>,
+    pub transform: VMatrix<4>,
+}
+impl VCsg {
+    pub fn new(operation: VCsgOperation, left: VBody, right: VBody) -> Self {
+        Self {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+            transform: VMatrix::identity(),
+        }
+    }
+    pub fn union(left: VBody, right: VBody) -> Self {
+        Self::new(VCsgOperation::Union, left, right)
+    }
+    pub fn intersection(left: VBody, right: VBody) -> Self {
+        Self::new(VCsgOperation::Intersection, left, right)
+    }
+    pub fn difference(left: VBody, right: VBody) -> Self {
+        Self::new(VCsgOperation::Difference, left, right)
+    }
+
+    pub fn with_transform(mut self, transform: VMatrix<4>) -> Self {
+        self.transform = transform;
+        self
+    }
+    /// Assigns the material to every body on both sides.
+    pub fn with_material(self, material: VMaterial) -> Self {
+        VCsg {
+            left: Box::new(self.left.with_material(material)),
+            right: Box::new(self.right.with_material(material)),
+            ..self
+        }
+    }
+    /// Maps the material of every body on both sides.
+    pub fn map_material<M: Fn(VMaterial) -> VMaterial>(self, map: &M) -> Self {
+        VCsg {
+            left: Box::new(self.left.map_material(map)),
+            right: Box::new(self.right.map_material(map)),
+            ..self
+        }
+    }
+}
+impl VIntersectable for VCsg {
+    fn intersect_in_object_space(&self, ray: VRay) -> Vec<VIntersection> {
+        // Tag every hit with its side, children of nested groups or CSGs come
+        // back as copies and can not be matched against the operands later.
+        let mut xs: Vec<(bool, VIntersection)> = self
+            .left
+            .intersect(ray)
+            .into_iter()
+            .map(|i| (true, i))
+            .chain(self.right.intersect(ray).into_iter().map(|i| (false, i)))
+            .collect();
+        xs.sort_by(|a, b| a.1.t.total_cmp(&b.1.t));
+
+        let mut in_left = false;
+        let mut in_right = false;
+        let mut result = vec![];
+        for (left_hit, i) in xs {
+            if self
+                .operation
+                .intersection_allowed(left_hit, in_left, in_right)
+            {
+                result.push(VIntersection {
+                    body: i.body.with_parent_transform(self.transform),
+                    ..i
+                });
+            }
+            if left_hit {
+                in_left = !in_left;
+            } else {
+                in_right = !in_right;
+            }
+        }
+        result
+    }
+
+    fn transform(&self) -> VMatrix<4> {
+        self.transform
+    }
+    fn normal_at_in_object_space(&self, _: VTuple) -> VTuple {
+        // Intersections always refer to the child that was hit
+        panic!("CSG bodies do not have a surface normal of their own")
+    }
+    fn material(&self) -> VMaterial {
+        VMaterial::default()
+    }
+    fn bounds_in_object_space(&self) -> VBounds {
+        match self.operation {
+            VCsgOperation::Difference => self.left.bounds(),
+            _ => self.left.bounds().merged(&self.right.bounds()),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::VCube;
+    use crate::sphere::VSphere;
+    use crate::zequality::ZEq;
+    use crate::F;
+
+    #[test]
+    fn csg_is_created_with_an_operation_and_two_bodies() {
+        let s1 = VBody::from(VSphere::default());
+        let s2 = VBody::from(VCube::default());
+        let c = VCsg::union(s1.clone(), s2.clone());
+
+        assert_eq!(c.operation, VCsgOperation::Union);
+        assert_eq!(*c.left, s1);
+        assert_eq!(*c.right, s2);
+    }
+
+    #[test]
+    fn evaluating_the_rule_for_a_csg_operation() {
+        use VCsgOperation::*;
+        let cases = [
+            (Union, true, true, true, false),
+            (Union, true, true, false, true),
+            (Union, true, false, true, false),
+            (Union, true, false, false, true),
+            (Union, false, true, true, false),
+            (Union, false, true, false, false),
+            (Union, false, false, true, true),
+            (Union, false, false, false, true),
+            (Intersection, true, true, true, true),
+            (Intersection, true, true, false, false),
+            (Intersection, true, false, true, true),
+            (Intersection, true, false, false, false),
+            (Intersection, false, true, true, true),
+            (Intersection, false, true, false, true),
+            (Intersection, false, false, true, false),
+            (Intersection, false, false, false, false),
+            (Difference, true, true, true, false),
+            (Difference, true, true, false, true),
+            (Difference, true, false, true, false),
+            (Difference, true, false, false, true),
+            (Difference, false, true, true, true),
+            (Difference, false, true, false, true),
+            (Difference, false, false, true, false),
+            (Difference, false, false, false, false),
+        ];
+        for (op, lhit, inl, inr, expected) in cases {
+            assert_eq!(
+                op.intersection_allowed(lhit, inl, inr),
+                expected,
+                "{:?} {} {} {}",
+                op,
+                lhit,
+                inl,
+                inr
+            );
+        }
+    }
+
+    #[test]
+    fn filtering_intersections_reports_the_right_children() {
+        let s1 = VBody::from(VSphere::default());
+        let s2 =
+            VBody::from(VSphere::default().with_transform(VMatrix::translation(0.0, 0.0, 0.5)));
+        let r = VRay::new(VTuple::point(0.0, 0.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+
+        let union = VCsg::union(s1.clone(), s2.clone()).intersect(r);
+        assert_eq!(union.len(), 2);
+        assert_zeq!(union[0].t, 4.0);
+        assert_eq!(union[0].body, s1);
+        assert_zeq!(union[1].t, 6.5);
+        assert_eq!(union[1].body, s2);
+
+        let intersection = VCsg::intersection(s1.clone(), s2.clone()).intersect(r);
+        assert_eq!(intersection.len(), 2);
+        assert_zeq!(intersection[0].t, 4.5);
+        assert_eq!(intersection[0].body, s2);
+        assert_zeq!(intersection[1].t, 6.0);
+        assert_eq!(intersection[1].body, s1);
+
+        let difference = VCsg::difference(s1.clone(), s2.clone()).intersect(r);
+        assert_eq!(difference.len(), 2);
+        assert_zeq!(difference[0].t, 4.0);
+        assert_eq!(difference[0].body, s1);
+        assert_zeq!(difference[1].t, 4.5);
+        assert_eq!(difference[1].body, s2);
+    }
+
+    #[test]
+    fn a_ray_misses_a_csg_body() {
+        let c = VCsg::union(
+            VBody::from(VSphere::default()),
+            VBody::from(VCube::default()),
+        );
+        let r = VRay::new(VTuple::point(0.0, 2.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+
+        assert_eq!(c.intersect(r).len(), 0);
+    }
+
+    #[test]
+    fn drilling_a_hole_through_a_cube() {
+        let drill = VCube::default().with_transform(VMatrix::scaling(0.5, 0.5, 2.0));
+        let c = VCsg::difference(VBody::from(VCube::default()), VBody::from(drill))
+            .with_transform(VMatrix::translation(0.0, 0.0, 1.0));
+
+        let through_hole = VRay::new(VTuple::point(0.0, 0.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(c.intersect(through_hole).len(), 0);
+
+        let into_wall = VRay::new(VTuple::point(0.0, -5.0, 1.0), VTuple::vector(0.0, 1.0, 0.0));
+        let xs = c.intersect(into_wall);
+        assert_eq!(xs.len(), 4);
+        assert_zeq!(xs[0].t, 4.0);
+        assert_zeq!(xs[1].t, 4.5);
+
+        // The wall of the hole takes its normal from the drill
+        let n = xs[1].get_computed().normalv;
+        assert_zeq!(n, VTuple::vector(0.0, -1.0, 0.0));
+        assert_zeq!(
+            xs[1].body.transform(),
+            VMatrix::translation(0.0, 0.0, 1.0) * VMatrix::scaling(0.5, 0.5, 2.0)
+        );
+    }
+
+    #[test]
+    fn csg_bodies_are_bounded_by_their_children() {
+        let left = VBody::from(VCube::default());
+        let right =
+            VBody::from(VSphere::default().with_transform(VMatrix::translation(2.0, 0.0, 0.0)));
+
+        let union = VCsg::union(left.clone(), right.clone());
+        assert_zeq!(union.bounds().max, VTuple::point(3.0, 1.0, 1.0));
+
+        let difference = VCsg::difference(left, right);
+        assert_zeq!(difference.bounds().max, VTuple::point(1.0, 1.0, 1.0));
+        assert!(difference.bounds().min.x > -F::INFINITY);
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use crate::body::VBody;
+use crate::body::VIntersectable;
+use crate::bounds::VBounds;
+use crate::intersections::VIntersection;
+use crate::material::*;
+use crate::matrix::*;
+use crate::ray::VRay;
+use crate::tuple::VTuple;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VCube {
+    pub transform: VMatrix<4>,
+    pub material: VMaterial,
+}
+
+impl Default for VCube {
+    fn default() -> Self {
+        Self {
+            transform: VMatrix::identity(),
+            material: Default::default(),
+        }
+    }
+}
+impl VCube {
+    pub fn new(transform: Option<VMatrix<4>>, material: VMaterial) -> Self {
+        match transform {
+            Some(transform) => VCube {
+                transform,
+                material,
+            },
+            None => VCube::default(),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: VMatrix<4>) -> Self {
+        self.transform = transform;
+        self
+    }
+    pub fn with_material(mut self, material: VMaterial) -> Self {
+        self.material = material;
+        self
+    }
+}
+impl VIntersectable for VCube {
+    fn intersect_in_object_space(&self, ray: VRay) -> Vec<VIntersection> {
+        match VBounds::unit().intersect(ray) {
+            Some((tmin, tmax)) => vec![
+                VIntersection::new(tmin, ray, VBody::from(*self)),
+                VIntersection::new(tmax, ray, VBody::from(*self)),
+            ],
+            None => vec![],
+        }
+    }
+
+    fn transform(&self) -> VMatrix<4> {
+        self.transform
+    }
+    fn normal_at_in_object_space(&self, p: VTuple) -> VTuple {
+        let maxc = p.x.abs().max(p.y.abs()).max(p.z.abs());
+        if maxc == p.x.abs() {
+            VTuple::vector(p.x, 0.0, 0.0)
+        } else if maxc == p.y.abs() {
+            VTuple::vector(0.0, p.y, 0.0)
+        } else {
+            VTuple::vector(0.0, 0.0, p.z)
+        }
+    }
+    fn material(&self) -> VMaterial {
+        self.material
+    }
+    fn bounds_in_object_space(&self) -> VBounds {
+        VBounds::unit()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::ZEq;
+
+    #[test]
+    fn a_ray_intersects_a_cube() {
+        let cases = [
+            (VTuple::point(5.0, 0.5, 0.0), VTuple::vector(-1.0, 0.0, 0.0), 4.0, 6.0),
+            (VTuple::point(-5.0, 0.5, 0.0), VTuple::vector(1.0, 0.0, 0.0), 4.0, 6.0),
+            (VTuple::point(0.5, 5.0, 0.0), VTuple::vector(0.0, -1.0, 0.0), 4.0, 6.0),
+            (VTuple::point(0.5, -5.0, 0.0), VTuple::vector(0.0, 1.0, 0.0), 4.0, 6.0),
+            (VTuple::point(0.5, 0.0, 5.0), VTuple::vector(0.0, 0.0, -1.0), 4.0, 6.0),
+            (VTuple::point(0.5, 0.0, -5.0), VTuple::vector(0.0, 0.0, 1.0), 4.0, 6.0),
+            (VTuple::point(0.0, 0.5, 0.0), VTuple::vector(0.0, 0.0, 1.0), -1.0, 1.0),
+        ];
+        let c = VCube::default();
+        for (origin, direction, t1, t2) in cases {
+            let xs = c.intersect(VRay::new(origin, direction));
+
+            assert_eq!(2, xs.len());
+            assert_zeq!(t1, xs[0].t);
+            assert_zeq!(t2, xs[1].t);
+        }
+    }
+
+    #[test]
+    fn a_ray_misses_a_cube() {
+        let c = VCube::default();
+        let r = VRay::new(VTuple::point(-2.0, 0.0, 0.0), VTuple::vector(0.2673, 0.5345, 0.8018));
+        let xs = c.intersect(r);
+
+        assert_eq!(0, xs.len());
+    }
+
+    #[test]
+    fn the_normal_on_the_surface_of_a_cube() {
+        let c = VCube::default();
+
+        assert_zeq!(c.normal_at(VTuple::point(1.0, 0.5, -0.8)), VTuple::vector(1.0, 0.0, 0.0));
+        assert_zeq!(c.normal_at(VTuple::point(-0.4, 1.0, -0.1)), VTuple::vector(0.0, 1.0, 0.0));
+        assert_zeq!(c.normal_at(VTuple::point(0.4, 0.4, -1.0)), VTuple::vector(0.0, 0.0, -1.0));
+        assert_zeq!(c.normal_at(VTuple::point(1.0, 1.0, 1.0)), VTuple::vector(1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+
+use crate::body::VBody;
+use crate::body::VIntersectable;
+use crate::bounds::VBounds;
+use crate::intersections::VIntersection;
+use crate::material::*;
+use crate::matrix::*;
+use crate::ray::VRay;
+use crate::tuple::VTuple;
+use crate::F;
+
+/// A collection of bodies sharing a common transform. Intersections are
+/// reported with the child body, already moved into the space of the group's
+/// parent, so materials and normals resolve to the child.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "VGroupConfig", into = "VGroupConfig")]
+pub struct VGroup {
+    pub transform: VMatrix<4>,
+    children: Vec<VBody>,
+    bounds: VBounds,
+}
+// The bounds are a cache over the children and are rebuilt when loading.
+#[derive(Serialize, Deserialize)]
+struct VGroupConfig {
+    transform: VMatrix<4>,
+    children: Vec<VBody>,
+}
+impl From<VGroupConfig> for VGroup {
+    fn from(cfg: VGroupConfig) -> Self {
+        VGroup::new(cfg.children).with_transform(cfg.transform)
+    }
+}
+impl From<VGroup> for VGroupConfig {
+    fn from(group: VGroup) -> Self {
+        VGroupConfig {
+            transform: group.transform,
+            children: group.children,
+        }
+    }
+}
+
+impl Default for VGroup {
+    fn default() -> Self {
+        Self {
+            transform: VMatrix::identity(),
+            children: vec![],
+            bounds: VBounds::empty(),
+        }
+    }
+}
+impl VGroup {
+    pub fn new(children: Vec<VBody>) -> Self {
+        let mut group = VGroup::default();
+        for child in children {
+            group.add_child(child);
+        }
+        group
+    }
+    pub fn with_transform(mut self, transform: VMatrix<4>) -> Self {
+        self.transform = transform;
+        self
+    }
+    pub fn with_material(self, material: VMaterial) -> Self {
+        let transform = self.transform;
+        VGroup::new(
+            self.children
+                .into_iter()
+                .map(|child| child.with_material(material))
+                .collect(),
+        )
+        .with_transform(transform)
+    }
+    /// Maps the material of every body in the group.
+    pub fn map_material<M: Fn(VMaterial) -> VMaterial>(self, map: &M) -> Self {
+        let transform = self.transform;
+        VGroup::new(
+            self.children
+                .into_iter()
+                .map(|child| child.map_material(map))
+                .collect(),
+        )
+        .with_transform(transform)
+    }
+    pub fn with_child(mut self, child: VBody) -> Self {
+        self.add_child(child);
+        self
+    }
+    pub fn add_child(&mut self, child: VBody) {
+        self.bounds = self.bounds.merged(&child.bounds());
+        self.children.push(child);
+    }
+    /// Splits the children into a hierarchy of nested groups holding at most
+    /// `threshold` bodies each, so rays can skip most of a large mesh by
+    /// testing bounding boxes only.
+    pub fn divided(self, threshold: usize) -> VGroup {
+        let transform = self.transform;
+        VGroup::new(partition(self.children, threshold.max(2))).with_transform(transform)
+    }
+    pub fn children(&self) -> &Vec<VBody> {
+        &self.children
+    }
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+// Sorts the bodies along the axis their centroids are most spread out on
+// and splits them at the median.
+fn partition(mut children: Vec<VBody>, threshold: usize) -> Vec<VBody> {
+    if children.len() <= threshold {
+        return children;
+    }
+    let centroids: Vec<VTuple> = children.iter().map(|c| c.bounds().centroid()).collect();
+    let mut spread = VBounds::empty();
+    for centroid in centroids.iter() {
+        spread.add_point(*centroid);
+    }
+    let extent = spread.max - spread.min;
+    let axis: fn(&VTuple) -> F = if extent.x >= extent.y && extent.x >= extent.z {
+        |t| t.x
+    } else if extent.y >= extent.z {
+        |t| t.y
+    } else {
+        |t| t.z
+    };
+    let mut keyed: Vec<(F, VBody)> = centroids.iter().map(axis).zip(children.drain(..)).collect();
+    keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut left: Vec<VBody> = keyed.into_iter().map(|(_, body)| body).collect();
+    let right = left.split_off(left.len() / 2);
+    vec![
+        VBody::from(VGroup::new(partition(left, threshold))),
+        VBody::from(VGroup::new(partition(right, threshold))),
+    ]
+}
+impl VIntersectable for VGroup {
+    fn intersect_in_object_space(&self, ray: VRay) -> Vec<VIntersection> {
+        if !self.bounds.intersects(ray) {
+            return vec![];
+        }
+        self.children
+            .iter()
+            .flat_map(|child| child.intersect(ray))
+            .map(|i| VIntersection {
+                body: i.body.with_parent_transform(self.transform),
+                ..i
+            })
+            .collect()
+    }
+
+    fn transform(&self) -> VMatrix<4> {
+        self.transform
+    }
+    fn normal_at_in_object_space(&self, _: VTuple) -> VTuple {
+        // Intersections always refer to the child that was hit
+        panic!("Groups do not have a surface normal of their own")
+    }
+    fn material(&self) -> VMaterial {
+        VMaterial::default()
+    }
+    fn bounds_in_object_space(&self) -> VBounds {
+        self.bounds
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::VSphere;
+    use crate::zequality::ZEq;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn intersecting_a_ray_with_an_empty_group() {
+        let g = VGroup::default();
+        let r = VRay::new(VTuple::point(0.0, 0.0, 0.0), VTuple::vector(0.0, 0.0, 1.0));
+
+        assert_eq!(0, g.intersect(r).len());
+    }
+
+    #[test]
+    fn intersecting_a_ray_with_a_nonempty_group() {
+        let s1 = VSphere::default();
+        let s2 = VSphere::default().with_transform(VMatrix::translation(0.0, 0.0, -3.0));
+        let s3 = VSphere::default().with_transform(VMatrix::translation(5.0, 0.0, 0.0));
+        let g = VGroup::new(vec![VBody::from(s1), VBody::from(s2), VBody::from(s3)]);
+        let r = VRay::new(VTuple::point(0.0, 0.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+        let xs = g.intersect(r);
+
+        assert_eq!(4, xs.len());
+        assert_eq!(xs[0].body, VBody::from(s2));
+        assert_eq!(xs[1].body, VBody::from(s2));
+        assert_eq!(xs[2].body, VBody::from(s1));
+        assert_eq!(xs[3].body, VBody::from(s1));
+    }
+
+    #[test]
+    fn intersecting_a_transformed_group() {
+        let s = VSphere::default().with_transform(VMatrix::translation(5.0, 0.0, 0.0));
+        let g = VGroup::new(vec![VBody::from(s)]).with_transform(VMatrix::scaling(2.0, 2.0, 2.0));
+        let r = VRay::new(VTuple::point(10.0, 0.0, -10.0), VTuple::vector(0.0, 0.0, 1.0));
+
+        assert_eq!(2, g.intersect(r).len());
+    }
+
+    #[test]
+    fn normals_of_children_include_the_group_transform() {
+        let s = VSphere::default().with_transform(VMatrix::translation(5.0, 0.0, 0.0));
+        let g2 = VGroup::new(vec![VBody::from(s)]).with_transform(VMatrix::scaling(1.0, 2.0, 3.0));
+        let g1 = VGroup::new(vec![VBody::from(g2)]).with_transform(VMatrix::rotation_y(PI / 2.0));
+        let r = VRay::new(VTuple::point(0.0, 0.0, -15.0), VTuple::vector(0.0, 0.0, 1.0));
+        let xs = g1.intersect(r);
+
+        let world_space_child = VBody::from(s)
+            .with_parent_transform(VMatrix::scaling(1.0, 2.0, 3.0))
+            .with_parent_transform(VMatrix::rotation_y(PI / 2.0));
+        assert_eq!(xs[0].body, world_space_child);
+
+        let p = VTuple::point(1.7321, 1.1547, -5.5774);
+        assert_zeq!(
+            world_space_child.normal_at(p),
+            VTuple::vector(0.2857, 0.4286, -0.8571)
+        );
+    }
+
+    #[test]
+    fn dividing_a_group_keeps_all_children_reachable() {
+        let spheres: Vec<VBody> = (0..20)
+            .map(|i| VBody::from(VSphere::default().with_transform(VMatrix::translation(3.0 * i as F, 0.0, 0.0))))
+            .collect();
+        let g = VGroup::new(spheres).divided(4);
+
+        assert_eq!(2, g.len());
+        for i in 0..20 {
+            let r = VRay::new(VTuple::point(3.0 * i as F, 0.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+            let xs = g.intersect(r);
+
+            assert_eq!(2, xs.len());
+            assert_zeq!(xs[0].body.transform(), VMatrix::translation(3.0 * i as F, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn a_group_is_bounded_by_its_children() {
+        let s = VSphere::default()
+            .with_transform(VMatrix::translation(2.0, 5.0, -3.0) * VMatrix::scaling(2.0, 2.0, 2.0));
+        let g = VGroup::new(vec![VBody::from(s)]);
+        let b = g.bounds_in_object_space();
+
+        assert_zeq!(b.min, VTuple::point(0.0, 3.0, -5.0));
+        assert_zeq!(b.max, VTuple::point(4.0, 7.0, -1.0));
+        assert!(b.max.x < F::INFINITY);
+    }
+}
@@ -4,21 +4,35 @@ use crate::body::*;
 use crate::computed_intersection::*;
 use crate::{ray::VRay, F};
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct VIntersection {
     pub t: F,
     pub ray: VRay,
     pub body: VBody,
+    // Barycentric coordinates of the hit, only set for triangles
+    pub u: F,
+    pub v: F,
 }
 
 impl VIntersection {
     pub fn new(t: F, ray: VRay, body: VBody) -> VIntersection {
-        VIntersection { t, ray, body }
+        VIntersection {
+            t,
+            ray,
+            body,
+            u: 0.0,
+            v: 0.0,
+        }
+    }
+    pub fn with_uv(mut self, u: F, v: F) -> VIntersection {
+        self.u = u;
+        self.v = v;
+        self
     }
 
     pub fn get_computed(&self) -> VComputedIntersection {
         let position = self.ray.position(self.t);
-        let mut normalv = self.body.normal_at(position);
+        let mut normalv = self.body.normal_at_with_uv(position, self.u, self.v);
         let eyev = -self.ray.direction;
         let inside = normalv.dot(&eyev) < 0.0;
 
@@ -92,7 +106,7 @@ mod tests {
         let r = VRay::new(VTuple::point(1.0, 1.0, 1.0), VTuple::vector(0.0, 0.0, 1.0));
         let i1 = VIntersection::new(1.0, r, VBody::from(s));
         let i2 = VIntersection::new(2.0, r, VBody::from(s));
-        let xs = VIntersections::new(vec![i2, i1]);
+        let xs = VIntersections::new(vec![i2, i1.clone()]);
 
         assert_eq!(xs.hit(), Some(&i1));
     }
@@ -103,7 +117,7 @@ mod tests {
         let r = VRay::new(VTuple::point(1.0, 1.0, 1.0), VTuple::vector(0.0, 0.0, 1.0));
         let i1 = VIntersection::new(-1.0, r, VBody::from(s));
         let i2 = VIntersection::new(1.0, r, VBody::from(s));
-        let xs = VIntersections::new(vec![i2, i1]);
+        let xs = VIntersections::new(vec![i2.clone(), i1]);
 
         assert_eq!(xs.hit(), Some(&i2));
     }
@@ -1,20 +1,34 @@
 #[macro_use]
 pub mod zequality;
+pub mod animation;
 pub mod animator;
+pub mod aov;
+pub mod background;
 pub mod body;
+pub mod bounds;
 pub mod camera;
 pub mod canvas;
+pub mod checkpoint;
 pub mod computed_intersection;
+pub mod csg;
+pub mod cube;
+pub mod group;
 pub mod intersections;
 pub mod light;
 pub mod material;
 pub mod matrix;
+pub mod mesh;
 pub mod plane;
 pub mod ray;
+pub mod renderer;
+pub mod rng;
+pub mod scene;
+pub mod smooth_triangle;
 pub mod sphere;
+pub mod triangle;
 pub mod tuple;
 pub mod world;
-//pub mod world_loader;
+pub mod world_loader;
 
 pub type F = f64;
 const EPSILON:F = 0.001;
@@ -1,7 +1,7 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{canvas::vcolor::VColor, tuple::VTuple};
-#[derive(Debug, Clone, Copy,PartialEq,Deserialize)]
+#[derive(Debug, Clone, Copy,PartialEq, Serialize, Deserialize)]
 pub struct VPointLight {
     pub pos: VTuple,
     pub col: VColor,
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::light::VPointLight;
 use crate::tuple::VTuple;
@@ -6,9 +6,12 @@ use crate::zequality::ZEq;
 use crate::{canvas::vcolor::VColor, F};
 pub trait Illuminated {
     fn lighting(&self, light: VPointLight, pos: VTuple, cam: VTuple, normal: VTuple,shadowed:bool) -> VColor;
+    /// Diffuse and specular response to light of the given color arriving
+    /// from the normalized direction `lightv`, without ambient light.
+    fn lighting_from(&self, lightv: VTuple, light_col: VColor, cam: VTuple, normal: VTuple) -> VColor;
 }
 
-#[derive(Copy, Clone, Debug, PartialEq,Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 
 pub enum VMaterial {
     VPhong(VPhong),
@@ -19,8 +22,21 @@ impl Illuminated for VMaterial {
             VMaterial::VPhong(ref m) => m.lighting(light, pos, cam, normal,shadowed),
         }
     }
+    fn lighting_from(&self, lightv: VTuple, light_col: VColor, cam: VTuple, normal: VTuple) -> VColor {
+        match *self {
+            VMaterial::VPhong(ref m) => m.lighting_from(lightv, light_col, cam, normal),
+        }
+    }
 }
 
+impl VMaterial {
+    /// Base color of the surface, without any lighting.
+    pub fn albedo(&self) -> VColor {
+        match *self {
+            VMaterial::VPhong(ref m) => m.col,
+        }
+    }
+}
 impl Default for VMaterial {
     fn default() -> Self {
         VMaterial::from(VPhong::default())
@@ -39,7 +55,7 @@ impl ZEq<VMaterial> for VMaterial {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq,Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 
 pub struct VPhong {
     pub col: VColor,
@@ -93,17 +109,19 @@ impl ZEq<VPhong> for VPhong {
 }
 impl Illuminated for VPhong {
     fn lighting(&self, light: VPointLight, pos: VTuple, cam: VTuple, normal: VTuple,shadowed:bool) -> VColor {
-        let light_amb: VColor;
-        let light_dif: VColor;
-        let light_spc: VColor;
-
-        let eff_col = self.col * light.col;
-        light_amb = eff_col * self.amb;
+        let light_amb = self.col * light.col * self.amb;
         
         if shadowed{return light_amb}
 
 
         let lightv = (light.pos - pos).normalized();
+        light_amb + self.lighting_from(lightv, light.col, cam, normal)
+    }
+    fn lighting_from(&self, lightv: VTuple, light_col: VColor, cam: VTuple, normal: VTuple) -> VColor {
+        let light_dif: VColor;
+        let light_spc: VColor;
+
+        let eff_col = self.col * light_col;
         let light_dot_normal = lightv.dot(&normal);
         if light_dot_normal < 0.0 {
             light_dif = VColor::black();
@@ -116,10 +134,10 @@ impl Illuminated for VPhong {
                 light_spc = VColor::black();
             } else {
                 let fac = reflect_dot_cam.powf(self.shi);
-                light_spc = light.col * self.spc * fac;
+                light_spc = light_col * self.spc * fac;
             }
         }
-        light_amb + light_dif + light_spc
+        light_dif + light_spc
     }
 }
 
@@ -0,0 +1,269 @@
+use std::fs;
+use std::path::Path;
+
+use crate::body::VBody;
+use crate::group::VGroup;
+use crate::smooth_triangle::VSmoothTriangle;
+use crate::triangle::VTriangle;
+use crate::tuple::VTuple;
+use crate::F;
+
+/// Result of parsing a Wavefront OBJ file. Faces before the first `g`
+/// statement end up in `default_group`, all others in their named group.
+pub struct VObjFile {
+    pub vertices: Vec<VTuple>,
+    pub normals: Vec<VTuple>,
+    pub default_group: VGroup,
+    pub groups: Vec<(String, VGroup)>,
+    /// Line number (starting at 1) and content of every line that was not
+    /// understood or referenced missing data.
+    pub ignored: Vec<(usize, String)>,
+    current_group: Option<usize>,
+}
+impl VObjFile {
+    pub fn parse(data: &str) -> Self {
+        let mut obj = VObjFile {
+            vertices: vec![],
+            normals: vec![],
+            default_group: VGroup::default(),
+            groups: vec![],
+            ignored: vec![],
+            current_group: None,
+        };
+        for (i, line) in data.lines().enumerate() {
+            if !obj.parse_line(line) {
+                obj.ignored.push((i + 1, String::from(line)));
+            }
+        }
+        obj
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+    /// All faces of the file, every named group becomes a child group.
+    pub fn to_group(&self) -> VGroup {
+        let mut group = self.default_group.clone();
+        for (_, named) in self.groups.iter() {
+            group.add_child(VBody::from(named.clone()));
+        }
+        group
+    }
+    pub fn group(&self, name: &str) -> Option<&VGroup> {
+        self.groups
+            .iter()
+            .find(|(group_name, _)| group_name == name)
+            .map(|(_, group)| group)
+    }
+
+    // Returns false if the line has to be reported as ignored.
+    fn parse_line(&mut self, line: &str) -> bool {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => return true,
+        };
+        let args: Vec<&str> = tokens.collect();
+        match keyword {
+            "v" => match parse_numbers(&args) {
+                Some(n) if n.len() >= 3 => self.vertices.push(VTuple::point(n[0], n[1], n[2])),
+                _ => return false,
+            },
+            "vn" => match parse_numbers(&args) {
+                Some(n) if n.len() == 3 => self.normals.push(VTuple::vector(n[0], n[1], n[2])),
+                _ => return false,
+            },
+            "f" => return self.parse_face(&args),
+            "g" if !args.is_empty() => {
+                let name = args.join(" ");
+                let index = match self.groups.iter().position(|(n, _)| *n == name) {
+                    Some(index) => index,
+                    None => {
+                        self.groups.push((name, VGroup::default()));
+                        self.groups.len() - 1
+                    }
+                };
+                self.current_group = Some(index);
+            }
+            _ => return false,
+        }
+        true
+    }
+    fn parse_face(&mut self, args: &[&str]) -> bool {
+        let mut corners = vec![];
+        for arg in args {
+            match self.parse_face_vertex(arg) {
+                Some(corner) => corners.push(corner),
+                None => return false,
+            }
+        }
+        if corners.len() < 3 {
+            return false;
+        }
+        let smooth = corners.iter().all(|(_, normal)| normal.is_some());
+        // Fan triangulation, works for the convex polygons OBJ files contain
+        let mut triangles = vec![];
+        for i in 1..corners.len() - 1 {
+            let (p1, n1) = corners[0];
+            let (p2, n2) = corners[i];
+            let (p3, n3) = corners[i + 1];
+            let triangle = match (smooth, n1, n2, n3) {
+                (true, Some(n1), Some(n2), Some(n3)) => {
+                    VBody::from(VSmoothTriangle::new(p1, p2, p3, n1, n2, n3))
+                }
+                _ => VBody::from(VTriangle::new(p1, p2, p3)),
+            };
+            triangles.push(triangle);
+        }
+        let group = match self.current_group {
+            Some(index) => &mut self.groups[index].1,
+            None => &mut self.default_group,
+        };
+        for triangle in triangles {
+            group.add_child(triangle);
+        }
+        true
+    }
+    // Parses `v`, `v/vt`, `v//vn` and `v/vt/vn`, texture coordinates are not used.
+    fn parse_face_vertex(&self, arg: &str) -> Option<(VTuple, Option<VTuple>)> {
+        let mut indices = arg.split('/');
+        let vertex = *resolve_index(indices.next()?, &self.vertices)?;
+        let _texture = indices.next();
+        let normal = match indices.next() {
+            Some(index) if !index.is_empty() => Some(*resolve_index(index, &self.normals)?),
+            _ => None,
+        };
+        Some((vertex, normal))
+    }
+}
+// OBJ indices start at 1, negative indices count back from the last element.
+fn resolve_index<'a>(index: &str, items: &'a [VTuple]) -> Option<&'a VTuple> {
+    let index: i64 = index.parse().ok()?;
+    let resolved = if index < 0 {
+        items.len() as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 {
+        return None;
+    }
+    items.get(resolved as usize)
+}
+fn parse_numbers(args: &[&str]) -> Option<Vec<F>> {
+    args.iter().map(|arg| arg.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::*;
+
+    fn triangle_at(group: &VGroup, index: usize) -> VTriangle {
+        match group.children()[index] {
+            VBody::VTriangle(triangle) => triangle,
+            ref body => panic!("expected a triangle, got {:?}", body),
+        }
+    }
+
+    #[test]
+    fn ignoring_unrecognized_lines() {
+        let obj = VObjFile::parse(
+            "There was a young lady named Bright\nwho traveled much faster than light.\n\n# just a comment\n",
+        );
+
+        assert_eq!(obj.ignored.len(), 2);
+        assert_eq!(obj.ignored[0].0, 1);
+    }
+
+    #[test]
+    fn vertex_records() {
+        let obj = VObjFile::parse("v -1 1 0\nv -1.0000 0.5000 0.0000\nv 1 0 0\nv 1 1 0\n");
+
+        assert_zeq!(obj.vertices[0], VTuple::point(-1.0, 1.0, 0.0));
+        assert_zeq!(obj.vertices[1], VTuple::point(-1.0, 0.5, 0.0));
+        assert_zeq!(obj.vertices[2], VTuple::point(1.0, 0.0, 0.0));
+        assert_zeq!(obj.vertices[3], VTuple::point(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn parsing_triangle_faces() {
+        let obj = VObjFile::parse("v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\n\nf 1 2 3\nf 1 3 4\n");
+        let t1 = triangle_at(&obj.default_group, 0);
+        let t2 = triangle_at(&obj.default_group, 1);
+
+        assert_zeq!(t1.p1, obj.vertices[0]);
+        assert_zeq!(t1.p2, obj.vertices[1]);
+        assert_zeq!(t1.p3, obj.vertices[2]);
+        assert_zeq!(t2.p1, obj.vertices[0]);
+        assert_zeq!(t2.p2, obj.vertices[2]);
+        assert_zeq!(t2.p3, obj.vertices[3]);
+    }
+
+    #[test]
+    fn triangulating_polygons() {
+        let obj = VObjFile::parse("v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\nv 0 2 0\n\nf 1 2 3 4 5\n");
+        let g = &obj.default_group;
+
+        assert_eq!(g.len(), 3);
+        assert_zeq!(triangle_at(g, 2).p1, obj.vertices[0]);
+        assert_zeq!(triangle_at(g, 2).p2, obj.vertices[3]);
+        assert_zeq!(triangle_at(g, 2).p3, obj.vertices[4]);
+    }
+
+    #[test]
+    fn triangles_in_groups() {
+        let obj = VObjFile::parse(
+            "v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\ng FirstGroup\nf 1 2 3\ng SecondGroup\nf 1 3 4\n",
+        );
+
+        assert_eq!(obj.default_group.len(), 0);
+        assert_eq!(obj.group("FirstGroup").unwrap().len(), 1);
+        assert_eq!(obj.group("SecondGroup").unwrap().len(), 1);
+        assert_eq!(obj.to_group().len(), 2);
+    }
+
+    #[test]
+    fn reopening_a_group_adds_to_it() {
+        let obj = VObjFile::parse(
+            "v -1 1 0\nv -1 0 0\nv 1 0 0\ng a\nf 1 2 3\ng b\nf 1 2 3\ng a\nf 1 2 3\n",
+        );
+
+        assert_eq!(obj.groups.len(), 2);
+        assert_eq!(obj.group("a").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn vertex_normal_records() {
+        let obj = VObjFile::parse("vn 0 0 1\nvn 0.707 0 -0.707\nvn 1 2 3\n");
+
+        assert_zeq!(obj.normals[0], VTuple::vector(0.0, 0.0, 1.0));
+        assert_zeq!(obj.normals[1], VTuple::vector(0.707, 0.0, -0.707));
+        assert_zeq!(obj.normals[2], VTuple::vector(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn faces_with_normals() {
+        let obj = VObjFile::parse(
+            "v 0 1 0\nv -1 0 0\nv 1 0 0\nvn -1 0 0\nvn 1 0 0\nvn 0 1 0\nf 1//3 2//1 3//2\nf 1/0/3 2/102/1 3/14/2\n",
+        );
+        let expected = VBody::from(VSmoothTriangle::new(
+            obj.vertices[0],
+            obj.vertices[1],
+            obj.vertices[2],
+            obj.normals[2],
+            obj.normals[0],
+            obj.normals[1],
+        ));
+
+        assert_eq!(obj.default_group.children()[0], expected);
+        assert_eq!(obj.default_group.children()[1], expected);
+    }
+
+    #[test]
+    fn faces_with_missing_vertices_are_ignored() {
+        let obj = VObjFile::parse("v 0 1 0\nv -1 0 0\nv 1 0 0\nf 1 2 4\nf -3 -2 -1\n");
+
+        assert_eq!(obj.ignored, vec![(4, String::from("f 1 2 4"))]);
+        assert_eq!(obj.default_group.len(), 1);
+    }
+}
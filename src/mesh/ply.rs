@@ -0,0 +1,417 @@
+use crate::mesh::vmesh::{VMesh, VMeshError, WELD_TOLERANCE};
+use crate::tuple::VTuple;
+use crate::F;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, VMeshError> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid(&format!("unknown property type {:?}", name))),
+        })
+    }
+    fn size(&self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Parses an ASCII or binary PLY file into a welded mesh. Only the `x`, `y`,
+/// `z` vertex properties and the face vertex indices are read, polygons are
+/// fan triangulated and all other elements are skipped.
+pub fn parse_ply(data: &[u8]) -> Result<VMesh, VMeshError> {
+    let (format, elements, body) = parse_header(data)?;
+    let mut reader: Box<dyn ValueReader> = match format {
+        Format::Ascii => Box::new(AsciiReader::new(body)?),
+        Format::BinaryLittleEndian => Box::new(BinaryReader {
+            data: body,
+            little_endian: true,
+        }),
+        Format::BinaryBigEndian => Box::new(BinaryReader {
+            data: body,
+            little_endian: false,
+        }),
+    };
+    let mut mesh = VMesh::default();
+    for element in elements.iter() {
+        match element.name.as_str() {
+            "vertex" => read_vertices(element, reader.as_mut(), &mut mesh)?,
+            "face" => read_faces(element, reader.as_mut(), &mut mesh)?,
+            _ => skip_element(element, reader.as_mut())?,
+        }
+    }
+    if let Some(face) = mesh
+        .faces
+        .iter()
+        .find(|f| f.iter().any(|i| *i >= mesh.vertices.len()))
+    {
+        return Err(invalid(&format!(
+            "face {:?} references a missing vertex",
+            face
+        )));
+    }
+    Ok(mesh.welded(WELD_TOLERANCE))
+}
+fn invalid(message: &str) -> VMeshError {
+    VMeshError::Invalid(String::from(message))
+}
+fn parse_header(data: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), VMeshError> {
+    if !data.starts_with(b"ply") {
+        return Err(invalid("not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut offset = 0;
+    loop {
+        let end = data[offset..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| invalid("header without end_header"))?;
+        let line = String::from_utf8_lossy(&data[offset..offset + end]);
+        offset += end + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["end_header"] => break,
+            ["format", name, _] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid(&format!("unknown format {:?}", name))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: String::from(*name),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid("malformed element count"))?,
+                properties: vec![],
+            }),
+            ["property", "list", count_type, item_type, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid("property before element"))?
+                .properties
+                .push(Property::List(
+                    String::from(*name),
+                    Scalar::parse(count_type)?,
+                    Scalar::parse(item_type)?,
+                )),
+            ["property", scalar, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid("property before element"))?
+                .properties
+                .push(Property::Scalar(
+                    String::from(*name),
+                    Scalar::parse(scalar)?,
+                )),
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| invalid("missing format"))?;
+    Ok((format, elements, &data[offset..]))
+}
+
+trait ValueReader {
+    fn read(&mut self, scalar: Scalar) -> Result<F, VMeshError>;
+    /// Reads a list length or vertex index, which has to be a whole number
+    /// that is not negative.
+    fn read_index(&mut self, scalar: Scalar) -> Result<usize, VMeshError> {
+        let value = self.read(scalar)?;
+        if value < 0.0 || value.fract() != 0.0 {
+            return Err(invalid(&format!("invalid index {}", value)));
+        }
+        Ok(value as usize)
+    }
+}
+struct AsciiReader<'a> {
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+impl<'a> AsciiReader<'a> {
+    // Comments are part of the header, so the body has to be plain text
+    fn new(data: &'a [u8]) -> Result<Self, VMeshError> {
+        let text = std::str::from_utf8(data).map_err(|_| invalid("body is not ASCII text"))?;
+        Ok(Self {
+            tokens: text.split_ascii_whitespace(),
+        })
+    }
+}
+impl ValueReader for AsciiReader<'_> {
+    fn read(&mut self, _: Scalar) -> Result<F, VMeshError> {
+        self.tokens
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(|| invalid("malformed or missing value"))
+    }
+}
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+impl ValueReader for BinaryReader<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<F, VMeshError> {
+        let size = scalar.size();
+        if self.data.len() < size {
+            return Err(invalid("unexpected end of data"));
+        }
+        let mut bytes = [0u8; 8];
+        bytes[..size].copy_from_slice(&self.data[..size]);
+        if !self.little_endian {
+            bytes[..size].reverse();
+        }
+        self.data = &self.data[size..];
+        let b = bytes;
+        Ok(match scalar {
+            Scalar::I8 => b[0] as i8 as F,
+            Scalar::U8 => b[0] as F,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as F,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as F,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as F,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as F,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as F,
+            Scalar::F64 => f64::from_le_bytes(b) as F,
+        })
+    }
+}
+
+fn read_vertices(
+    element: &Element,
+    reader: &mut dyn ValueReader,
+    mesh: &mut VMesh,
+) -> Result<(), VMeshError> {
+    for _ in 0..element.count {
+        let mut position = [0.0; 3];
+        for property in element.properties.iter() {
+            match property {
+                Property::Scalar(name, scalar) => {
+                    let value = reader.read(*scalar)?;
+                    match name.as_str() {
+                        "x" => position[0] = value,
+                        "y" => position[1] = value,
+                        "z" => position[2] = value,
+                        _ => {}
+                    }
+                }
+                Property::List(_, count, item) => skip_list(reader, *count, *item)?,
+            }
+        }
+        mesh.vertices
+            .push(VTuple::point(position[0], position[1], position[2]));
+    }
+    Ok(())
+}
+fn read_faces(
+    element: &Element,
+    reader: &mut dyn ValueReader,
+    mesh: &mut VMesh,
+) -> Result<(), VMeshError> {
+    for _ in 0..element.count {
+        for property in element.properties.iter() {
+            match property {
+                Property::List(name, count, item)
+                    if name == "vertex_indices" || name == "vertex_index" =>
+                {
+                    // Counts are not trusted for allocating, the data may
+                    // end long before
+                    let n = reader.read_index(*count)?;
+                    let mut indices = vec![];
+                    for _ in 0..n {
+                        indices.push(reader.read_index(*item)?);
+                    }
+                    // Fan triangulation, like the OBJ loader
+                    for i in 1..n.saturating_sub(1) {
+                        mesh.faces.push([indices[0], indices[i], indices[i + 1]]);
+                    }
+                }
+                Property::List(_, count, item) => skip_list(reader, *count, *item)?,
+                Property::Scalar(_, scalar) => {
+                    reader.read(*scalar)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+fn skip_element(element: &Element, reader: &mut dyn ValueReader) -> Result<(), VMeshError> {
+    for _ in 0..element.count {
+        for property in element.properties.iter() {
+            match property {
+                Property::Scalar(_, scalar) => {
+                    reader.read(*scalar)?;
+                }
+                Property::List(_, count, item) => skip_list(reader, *count, *item)?,
+            }
+        }
+    }
+    Ok(())
+}
+fn skip_list(reader: &mut dyn ValueReader, count: Scalar, item: Scalar) -> Result<(), VMeshError> {
+    let n = reader.read_index(count)?;
+    for _ in 0..n {
+        reader.read(item)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::*;
+
+    const ASCII_PLY: &str = "ply
+format ascii 1.0
+comment a unit quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 255
+1 0 0 255
+1 1 0 255
+0 1 0 255
+4 0 1 2 3
+0 2
+";
+
+    fn binary_header(format: &str) -> Vec<u8> {
+        format!(
+            "ply\nformat {} 1.0\nelement vertex 3\nproperty double x\nproperty double y\nproperty double z\nelement face 1\nproperty list uchar uint vertex_index\nend_header\n",
+            format
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn parsing_ascii_ply() {
+        let mesh = parse_ply(ASCII_PLY.as_bytes()).unwrap();
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_zeq!(mesh.vertices[2], VTuple::point(1.0, 1.0, 0.0));
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn parsing_binary_ply() {
+        let points: [F; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let mut little = binary_header("binary_little_endian");
+        let mut big = binary_header("binary_big_endian");
+        for p in points {
+            little.extend(p.to_le_bytes());
+            big.extend(p.to_be_bytes());
+        }
+        little.push(3);
+        big.push(3);
+        for i in 0..3u32 {
+            little.extend(i.to_le_bytes());
+            big.extend(i.to_be_bytes());
+        }
+        let little = parse_ply(&little).unwrap();
+        let big = parse_ply(&big).unwrap();
+
+        assert_eq!(little, big);
+        assert_zeq!(little.vertices[1], VTuple::point(1.0, 0.0, 0.0));
+        assert_eq!(little.faces, vec![[0, 1, 2]]);
+    }
+
+    #[test]
+    fn rejecting_faces_with_missing_vertices() {
+        let data = ASCII_PLY.replace("4 0 1 2 3", "3 0 1 7");
+
+        assert!(matches!(
+            parse_ply(data.as_bytes()),
+            Err(VMeshError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn rejecting_ascii_ply_that_is_not_text() {
+        // Without any elements to read this used to pass as an empty mesh
+        let end = ASCII_PLY.find("end_header\n").unwrap() + 11;
+        let header = ASCII_PLY[..end]
+            .replace(" 4\n", " 0\n")
+            .replace(" 1\n", " 0\n");
+        let mut data = header.into_bytes();
+        data.extend([0xff, 0xfe]);
+
+        assert!(matches!(parse_ply(&data), Err(VMeshError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejecting_truncated_binary_ply() {
+        let data = binary_header("binary_little_endian");
+
+        assert!(matches!(parse_ply(&data), Err(VMeshError::Invalid(_))));
+        // A huge count fails on the missing data instead of allocating
+        let huge = String::from_utf8(data)
+            .unwrap()
+            .replace("element vertex 3", "element vertex 1152921504606846976");
+        assert!(matches!(
+            parse_ply(huge.as_bytes()),
+            Err(VMeshError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn rejecting_negative_indices() {
+        let mut data = String::from_utf8(binary_header("binary_little_endian"))
+            .unwrap()
+            .replace("uint vertex_index", "int vertex_index")
+            .into_bytes();
+        data.extend([0u8; 9 * 8]);
+        data.push(3);
+        for i in [0i32, -1, 2] {
+            data.extend(i.to_le_bytes());
+        }
+
+        assert!(matches!(parse_ply(&data), Err(VMeshError::Invalid(_))));
+        let ascii = ASCII_PLY.replace("4 0 1 2 3", "3 0 -1 2");
+        assert!(matches!(
+            parse_ply(ascii.as_bytes()),
+            Err(VMeshError::Invalid(_))
+        ));
+    }
+}
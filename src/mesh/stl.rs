@@ -0,0 +1,147 @@
+use crate::mesh::vmesh::{VMesh, VMeshError, WELD_TOLERANCE};
+use crate::tuple::VTuple;
+use crate::F;
+
+const HEADER_SIZE: usize = 84;
+const TRIANGLE_SIZE: usize = 50;
+
+/// Parses a binary or ASCII STL file into a welded mesh. The stored facet
+/// normals are ignored, they are recomputed from the vertex order.
+pub fn parse_stl(data: &[u8]) -> Result<VMesh, VMeshError> {
+    // Plenty of binary files start their header with "solid" as well, so the
+    // size check has to come first.
+    let mesh = if is_binary(data) {
+        parse_binary(data)
+    } else if data.starts_with(b"solid") {
+        parse_ascii(data)?
+    } else {
+        return Err(VMeshError::Invalid(String::from("not an STL file")));
+    };
+    Ok(mesh.welded(WELD_TOLERANCE))
+}
+fn is_binary(data: &[u8]) -> bool {
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    data.len() == HEADER_SIZE + count * TRIANGLE_SIZE
+}
+fn parse_binary(data: &[u8]) -> VMesh {
+    let read_f32 = |offset: usize| -> F {
+        f32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]) as F
+    };
+    let mut mesh = VMesh::default();
+    for triangle in data[HEADER_SIZE..].chunks_exact(TRIANGLE_SIZE).enumerate() {
+        // Skip the 12 byte normal, the trailing u16 is an unused attribute
+        let offset = HEADER_SIZE + triangle.0 * TRIANGLE_SIZE + 12;
+        for corner in 0..3 {
+            let o = offset + corner * 12;
+            mesh.vertices
+                .push(VTuple::point(read_f32(o), read_f32(o + 4), read_f32(o + 8)));
+        }
+        let base = mesh.vertices.len() - 3;
+        mesh.faces.push([base, base + 1, base + 2]);
+    }
+    mesh
+}
+fn parse_ascii(data: &[u8]) -> Result<VMesh, VMeshError> {
+    let text = String::from_utf8_lossy(data);
+    let mut mesh = VMesh::default();
+    let mut tokens = text.split_whitespace();
+    while let Some(token) = tokens.next() {
+        if token != "vertex" {
+            continue;
+        }
+        let mut coordinate = || -> Result<F, VMeshError> {
+            tokens
+                .next()
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(|| VMeshError::Invalid(String::from("malformed vertex")))
+        };
+        let (x, y, z) = (coordinate()?, coordinate()?, coordinate()?);
+        mesh.vertices.push(VTuple::point(x, y, z));
+        if mesh.vertices.len() % 3 == 0 {
+            let base = mesh.vertices.len() - 3;
+            mesh.faces.push([base, base + 1, base + 2]);
+        }
+    }
+    if mesh.vertices.len() % 3 != 0 {
+        return Err(VMeshError::Invalid(String::from(
+            "facet with less than 3 vertices",
+        )));
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::*;
+
+    const ASCII_STL: &str = "solid quad
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 1 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid quad
+";
+
+    fn binary_stl(triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        let mut data = b"solid but actually binary".to_vec();
+        data.resize(80, 0);
+        data.extend((triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            data.extend([0u8; 12]);
+            for corner in triangle {
+                for c in corner {
+                    data.extend(c.to_le_bytes());
+                }
+            }
+            data.extend([0u8; 2]);
+        }
+        data
+    }
+
+    #[test]
+    fn parsing_ascii_stl() {
+        let mesh = parse_stl(ASCII_STL.as_bytes()).unwrap();
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.faces.len(), 2);
+        assert_zeq!(mesh.vertices[3], VTuple::point(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn parsing_binary_stl() {
+        let data = binary_stl(&[
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            [[1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+        ]);
+        let mesh = parse_stl(&data).unwrap();
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [1, 3, 2]]);
+    }
+
+    #[test]
+    fn rejecting_truncated_ascii_stl() {
+        let result = parse_stl(b"solid x\nfacet\nouter loop\nvertex 0 0 0\nvertex 1 0");
+
+        assert!(matches!(result, Err(VMeshError::Invalid(_))));
+    }
+}
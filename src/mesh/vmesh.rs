@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::body::VBody;
+use crate::group::VGroup;
+use crate::mesh::{ply, stl};
+use crate::smooth_triangle::VSmoothTriangle;
+use crate::triangle::VTriangle;
+use crate::tuple::VTuple;
+use crate::F;
+
+/// Groups built from meshes are split until no group holds more children.
+pub const MESH_GROUP_SIZE: usize = 8;
+/// Vertices closer than this on every axis are merged when welding.
+pub const WELD_TOLERANCE: F = 1e-6;
+
+#[derive(Debug)]
+pub enum VMeshError {
+    Io(std::io::Error),
+    Invalid(String),
+    UnknownFormat(String),
+}
+impl fmt::Display for VMeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VMeshError::Io(e) => write!(f, "could not read mesh: {}", e),
+            VMeshError::Invalid(message) => write!(f, "invalid mesh: {}", message),
+            VMeshError::UnknownFormat(ext) => write!(f, "unknown mesh format {:?}", ext),
+        }
+    }
+}
+impl std::error::Error for VMeshError {}
+impl From<std::io::Error> for VMeshError {
+    fn from(e: std::io::Error) -> Self {
+        VMeshError::Io(e)
+    }
+}
+
+/// Indexed triangle mesh, the common representation STL and PLY files are
+/// read into before they are turned into bodies.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VMesh {
+    pub vertices: Vec<VTuple>,
+    pub faces: Vec<[usize; 3]>,
+}
+impl VMesh {
+    pub fn new(vertices: Vec<VTuple>, faces: Vec<[usize; 3]>) -> Self {
+        Self { vertices, faces }
+    }
+    /// Loads an STL or PLY file, picking the format from the file extension.
+    /// The returned mesh is already welded.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VMeshError> {
+        let ext = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .unwrap_or_default();
+        let data = fs::read(&path)?;
+        match ext.as_str() {
+            "stl" => stl::parse_stl(&data),
+            "ply" => ply::parse_ply(&data),
+            _ => Err(VMeshError::UnknownFormat(ext)),
+        }
+    }
+    /// Merges vertices closer than `tolerance` on every axis and drops faces
+    /// that collapse in the process. Vertices are hashed into cells of the
+    /// tolerance's size and only compared with the neighbouring cells, so it
+    /// runs in linear time.
+    pub fn welded(&self, tolerance: F) -> VMesh {
+        let cell = |v: &VTuple| {
+            (
+                (v.x / tolerance).floor() as i64,
+                (v.y / tolerance).floor() as i64,
+                (v.z / tolerance).floor() as i64,
+            )
+        };
+        // Cells are as large as the tolerance, so each keeps one vertex
+        let mut lookup: HashMap<(i64, i64, i64), usize> = HashMap::new();
+        let mut vertices: Vec<VTuple> = vec![];
+        let remap: Vec<usize> = self
+            .vertices
+            .iter()
+            .map(|v| {
+                let (x, y, z) = cell(v);
+                let close = |w: &VTuple| {
+                    (v.x - w.x).abs() < tolerance
+                        && (v.y - w.y).abs() < tolerance
+                        && (v.z - w.z).abs() < tolerance
+                };
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        for dz in -1..=1 {
+                            match lookup.get(&(x + dx, y + dy, z + dz)) {
+                                Some(index) if close(&vertices[*index]) => return *index,
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                vertices.push(*v);
+                lookup.insert((x, y, z), vertices.len() - 1);
+                vertices.len() - 1
+            })
+            .collect();
+        let faces = self
+            .faces
+            .iter()
+            .map(|face| [remap[face[0]], remap[face[1]], remap[face[2]]])
+            .filter(|face| face[0] != face[1] && face[1] != face[2] && face[0] != face[2])
+            .collect();
+        VMesh::new(vertices, faces)
+    }
+    /// Per vertex normals, averaged from the adjacent faces weighted by area.
+    pub fn vertex_normals(&self) -> Vec<VTuple> {
+        let mut normals = vec![VTuple::vector(0.0, 0.0, 0.0); self.vertices.len()];
+        for face in self.faces.iter() {
+            // Not normalized, so larger faces contribute more
+            let normal = self.face_normal(face);
+            for index in face.iter() {
+                normals[*index] = normals[*index] + normal;
+            }
+        }
+        normals
+            .into_iter()
+            .map(|n| {
+                if n.magnitude() > 0.0 {
+                    n.normalized()
+                } else {
+                    n
+                }
+            })
+            .collect()
+    }
+    /// Turns the mesh into a group of triangles, organized as a bounding
+    /// volume hierarchy. With `smooth` set the normals are interpolated
+    /// across faces.
+    pub fn to_group(&self, smooth: bool) -> VGroup {
+        let normals = if smooth {
+            self.vertex_normals()
+        } else {
+            vec![]
+        };
+        let triangles = self
+            .faces
+            .iter()
+            .map(|face| {
+                let (p1, p2, p3) = (
+                    self.vertices[face[0]],
+                    self.vertices[face[1]],
+                    self.vertices[face[2]],
+                );
+                if smooth {
+                    VBody::from(VSmoothTriangle::new(
+                        p1,
+                        p2,
+                        p3,
+                        normals[face[0]],
+                        normals[face[1]],
+                        normals[face[2]],
+                    ))
+                } else {
+                    VBody::from(VTriangle::new(p1, p2, p3))
+                }
+            })
+            .collect();
+        VGroup::new(triangles).divided(MESH_GROUP_SIZE)
+    }
+    fn face_normal(&self, face: &[usize; 3]) -> VTuple {
+        let e1 = self.vertices[face[1]] - self.vertices[face[0]];
+        let e2 = self.vertices[face[2]] - self.vertices[face[0]];
+        e2.crossed(&e1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::*;
+
+    // Two triangles sharing an edge, stored without shared vertices
+    fn create_quad() -> VMesh {
+        VMesh::new(
+            vec![
+                VTuple::point(0.0, 0.0, 0.0),
+                VTuple::point(1.0, 0.0, 0.0),
+                VTuple::point(0.0, 1.0, 0.0),
+                VTuple::point(1.0, 0.0, 0.0),
+                VTuple::point(1.0, 1.0, 0.0),
+                VTuple::point(0.0, 1.0, 0.0000001),
+            ],
+            vec![[0, 1, 2], [3, 4, 5]],
+        )
+    }
+
+    #[test]
+    fn welding_merges_close_vertices() {
+        let mesh = create_quad().welded(WELD_TOLERANCE);
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.faces, vec![[0, 1, 2], [1, 3, 2]]);
+    }
+
+    #[test]
+    fn welding_merges_vertices_across_cells() {
+        // Pairs on both sides of a rounded and of a truncated cell boundary
+        let mesh = VMesh::new(
+            vec![
+                VTuple::point(0.449, 0.0, 0.0),
+                VTuple::point(0.451, 0.0, 0.0),
+                VTuple::point(0.999, 0.0, 0.0),
+                VTuple::point(1.001, 0.0, 0.0),
+            ],
+            vec![],
+        );
+        let welded = mesh.welded(0.1);
+
+        assert_eq!(welded.vertices.len(), 2);
+        assert_zeq!(welded.vertices[1], VTuple::point(0.999, 0.0, 0.0));
+        assert_eq!(mesh.welded(0.001).vertices.len(), 4);
+    }
+
+    #[test]
+    fn welding_drops_collapsed_faces() {
+        let mut mesh = create_quad();
+        mesh.faces.push([2, 5, 0]);
+
+        assert_eq!(mesh.welded(WELD_TOLERANCE).faces.len(), 2);
+    }
+
+    #[test]
+    fn welding_scales_to_large_meshes() {
+        let n = 200;
+        let mut mesh = VMesh::default();
+        for x in 0..n {
+            for y in 0..n {
+                let base = mesh.vertices.len();
+                let (x, y) = (x as F, y as F);
+                mesh.vertices.push(VTuple::point(x, y, 0.0));
+                mesh.vertices.push(VTuple::point(x + 1.0, y, 0.0));
+                mesh.vertices.push(VTuple::point(x, y + 1.0, 0.0));
+                mesh.faces.push([base, base + 1, base + 2]);
+            }
+        }
+        let welded = mesh.welded(WELD_TOLERANCE);
+
+        assert_eq!(welded.faces.len(), n * n);
+        assert_eq!(welded.vertices.len(), (n + 1) * (n + 1) - 1);
+    }
+
+    #[test]
+    fn vertex_normals_are_averaged() {
+        let mesh = VMesh::new(
+            vec![
+                VTuple::point(0.0, 0.0, 0.0),
+                VTuple::point(1.0, 0.0, 0.0),
+                VTuple::point(0.0, 1.0, 0.0),
+                VTuple::point(0.0, 0.0, 1.0),
+            ],
+            vec![[0, 1, 2], [0, 3, 1]],
+        );
+        let normals = mesh.vertex_normals();
+        let diagonal = (2.0 as F).sqrt() / 2.0;
+
+        assert_zeq!(normals[0], VTuple::vector(0.0, -diagonal, -diagonal));
+        assert_zeq!(normals[2], VTuple::vector(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn meshes_become_groups_of_triangles() {
+        let mesh = create_quad().welded(WELD_TOLERANCE);
+
+        match mesh.to_group(true).children()[0] {
+            VBody::VSmoothTriangle(_) => {}
+            ref body => panic!("expected a smooth triangle, got {:?}", body),
+        }
+        match mesh.to_group(false).children()[1] {
+            VBody::VTriangle(_) => {}
+            ref body => panic!("expected a triangle, got {:?}", body),
+        }
+    }
+}
@@ -1,14 +1,16 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::EPSILON;
+use crate::{EPSILON, F};
 use crate::body::VBody;
 use crate::body::VIntersectable;
+use crate::bounds::VBounds;
+use crate::intersections::VIntersection;
 use crate::material::*;
 use crate::matrix::*;
 use crate::ray::VRay;
 use crate::tuple::VTuple;
 
-#[derive(Copy, Clone, Debug, PartialEq,Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct VPlane {
     pub transform: VMatrix<4>,
     pub material: VMaterial,
@@ -43,12 +45,12 @@ impl VPlane {
     }
 }
 impl VIntersectable for VPlane {
-    fn intersect_in_object_space(&self, ray: VRay) -> Vec<(f64, VBody)> {
+    fn intersect_in_object_space(&self, ray: VRay) -> Vec<VIntersection> {
         if ray.direction.y.abs()<=EPSILON{
             return vec![];
         }
         let t = -ray.origin.y / ray.direction.y;
-        return vec![(t,VBody::from(*self))]
+        return vec![VIntersection::new(t, ray, VBody::from(*self))]
     }
 
     fn transform(&self) -> VMatrix<4> {
@@ -60,6 +62,12 @@ impl VIntersectable for VPlane {
     fn material(&self) -> VMaterial {
         self.material
     }
+    fn bounds_in_object_space(&self) -> VBounds {
+        VBounds::new(
+            VTuple::point(F::NEG_INFINITY, 0.0, F::NEG_INFINITY),
+            VTuple::point(F::INFINITY, 0.0, F::INFINITY),
+        )
+    }
 }
 #[cfg(test)]
 mod tests {
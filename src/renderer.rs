@@ -0,0 +1,1168 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+
+use crate::aov::VAovBuffers;
+use crate::camera::VCamera;
+use crate::canvas::vcanvas::VCanvas;
+use crate::canvas::vcolor::VColor;
+use crate::ray::VRay;
+use crate::rng::VRng;
+use crate::world::VWorld;
+use crate::F;
+
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    /// Rays per pixel, spread over the pixel area and averaged.
+    pub samples: usize,
+    /// Worker threads, `None` uses the global rayon pool. Renders started on
+    /// a rayon worker always use that worker's pool.
+    pub threads: Option<usize>,
+    /// Edge length of the square tiles the image is split into.
+    pub tile_size: usize,
+    /// Edge length of the pixel blocks sharing one ray in the first pass of
+    /// a progressive render, 1 skips that pass.
+    pub preview_block: usize,
+    /// Lets another thread abort a render.
+    pub cancel: Option<CancelToken>,
+    /// Wall clock time after which a render stops.
+    pub time_budget: Option<Duration>,
+    /// Number of camera rays after which a render stops, rays of the coarse
+    /// preview pass of a progressive render are not counted.
+    pub sample_budget: Option<usize>,
+    /// Minimum time between two checkpoints of a resumable render.
+    pub checkpoint_interval: Duration,
+    /// Seed of the random numbers of every sample, the same seed gives the
+    /// same image no matter how the work is spread over threads.
+    pub seed: u64,
+    /// Places the samples randomly inside the pixel instead of on a fixed
+    /// pattern.
+    pub jitter: bool,
+    /// Makes pixels where the camera rays miss everything transparent
+    /// instead of black, partly covered pixels get the fraction of samples
+    /// that hit as alpha.
+    pub transparent: bool,
+}
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples: 1,
+            threads: None,
+            tile_size: 16,
+            preview_block: 8,
+            cancel: None,
+            time_budget: None,
+            sample_budget: None,
+            checkpoint_interval: Duration::from_secs(60),
+            seed: 0,
+            jitter: false,
+            transparent: false,
+        }
+    }
+}
+impl RenderSettings {
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+    pub fn with_tile_size(mut self, tile_size: usize) -> Self {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+    pub fn with_preview_block(mut self, preview_block: usize) -> Self {
+        self.preview_block = preview_block.max(1);
+        self
+    }
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+    pub fn with_time_budget(mut self, time_budget: Duration) -> Self {
+        self.time_budget = Some(time_budget);
+        self
+    }
+    pub fn with_sample_budget(mut self, sample_budget: usize) -> Self {
+        self.sample_budget = Some(sample_budget);
+        self
+    }
+    pub fn with_checkpoint_interval(mut self, checkpoint_interval: Duration) -> Self {
+        self.checkpoint_interval = checkpoint_interval;
+        self
+    }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+    pub fn with_transparent_background(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+    /// Starts the worker threads asked for, `None` if the global pool is
+    /// used. Renders start their own pool and fall back to the current one
+    /// if that fails, call this first to report the error instead.
+    pub fn thread_pool(&self) -> Result<Option<rayon::ThreadPool>, rayon::ThreadPoolBuildError> {
+        self.threads
+            .map(|threads| rayon::ThreadPoolBuilder::new().num_threads(threads).build())
+            .transpose()
+    }
+}
+
+/// Shared flag to abort a render from another thread, clones refer to the
+/// same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Cancelled,
+    TimeBudget,
+    SampleBudget,
+    /// The preview callback asked to stop.
+    Preview,
+}
+/// Result of a render, which may have stopped early.
+pub struct RenderOutcome {
+    pub canvas: VCanvas,
+    /// Samples traced per pixel, row by row. Pixels only showing the coarse
+    /// preview or not rendered at all count 0.
+    pub samples: Vec<usize>,
+    /// Samples per pixel the settings asked for.
+    pub requested_samples: usize,
+    /// Passes that ran to completion, including the coarse pass. A one shot
+    /// render has a single pass.
+    pub passes_completed: usize,
+    /// Why the render stopped early, `None` if it finished.
+    pub stopped: Option<StopReason>,
+}
+impl RenderOutcome {
+    pub fn is_complete(&self) -> bool {
+        self.stopped.is_none()
+    }
+    pub fn samples_at(&self, x: usize, y: usize) -> usize {
+        self.samples[y * self.canvas.width + x]
+    }
+    /// Fraction of the requested samples that were traced, from 0 to 1.
+    pub fn coverage(&self) -> F {
+        let requested = self.samples.len() * self.requested_samples;
+        if requested == 0 {
+            return 1.0;
+        }
+        self.samples.iter().sum::<usize>() as F / requested as F
+    }
+}
+
+/// Rectangular part of the image that is rendered as one unit of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+impl Tile {
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Pixel coordinates covered by the tile, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let tile = *self;
+        (tile.y..tile.y + tile.height)
+            .flat_map(move |y| (tile.x..tile.x + tile.width).map(move |x| (x, y)))
+    }
+}
+/// Splits an image into tiles of at most `size` pixels square, row by row.
+pub fn tiles(width: usize, height: usize, size: usize) -> Vec<Tile> {
+    let size = size.max(1);
+    let mut tiles = vec![];
+    for y in (0..height).step_by(size) {
+        for x in (0..width).step_by(size) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+pub fn render(world: &VWorld, camera: &VCamera, settings: &RenderSettings) -> RenderOutcome {
+    render_with_progress(world, camera, settings, |_, _| {})
+}
+/// Renders the world as seen by the camera. `progress` is called on the
+/// calling thread with the number of finished and total pixels whenever a
+/// tile is done. Renders running on a rayon worker, like the frames of
+/// [`VAnimator::animate_parallel`], report their tiles in rounds of one per
+/// worker of the pool.
+///
+/// [`VAnimator::animate_parallel`]: crate::animator::VAnimator::animate_parallel
+///
+/// Like [`render_progressive`] the render stops once it is cancelled or runs
+/// out of its time or sample budget. Tiles not started by then stay black
+/// and count no samples in the outcome, so the last progress reported is
+/// below the total.
+pub fn render_with_progress<R: FnMut(usize, usize)>(
+    world: &VWorld,
+    camera: &VCamera,
+    settings: &RenderSettings,
+    progress: R,
+) -> RenderOutcome {
+    let samples = settings.samples.max(1);
+    render_canvas(
+        camera.hsize,
+        camera.vsize,
+        settings,
+        samples,
+        |x, y| {
+            let mut color = VColor::black();
+            let mut alpha = 0.0;
+            for i in 0..samples {
+                let (sample, coverage) = trace_sample(world, camera, settings, x, y, i);
+                color = color + sample;
+                alpha += coverage;
+            }
+            average(color, alpha, samples)
+        },
+        progress,
+    )
+}
+/// Fills a canvas by calling `pixel` for every coordinate, tiles are
+/// rendered in parallel and handed back to the calling thread, which is the
+/// only one writing to the canvas. Every call counts as one sample.
+pub fn render_pixels<P, R>(
+    width: usize,
+    height: usize,
+    settings: &RenderSettings,
+    pixel: P,
+    progress: R,
+) -> RenderOutcome
+where
+    P: Fn(usize, usize) -> VColor + Sync,
+    R: FnMut(usize, usize),
+{
+    render_canvas(
+        width,
+        height,
+        settings,
+        1,
+        |x, y| (pixel(x, y), 1.0),
+        progress,
+    )
+}
+// Like `render_pixels`, with the alpha of every pixel next to its color and
+// the rays traced per pixel counted against the sample budget.
+fn render_canvas<P, R>(
+    width: usize,
+    height: usize,
+    settings: &RenderSettings,
+    rays_per_pixel: usize,
+    pixel: P,
+    mut progress: R,
+) -> RenderOutcome
+where
+    P: Fn(usize, usize) -> (VColor, F) + Sync,
+    R: FnMut(usize, usize),
+{
+    let mut canvas = VCanvas::new(width, height);
+    let mut samples = vec![0; width * height];
+    let tiles = tiles(width, height, settings.tile_size);
+    let pool = render_pool(settings);
+    let total = width * height;
+    let mut done = 0;
+    let mut stopped = None;
+    let deadline = settings.time_budget.map(|budget| Instant::now() + budget);
+    let halted = AtomicBool::new(false);
+    let should_stop =
+        || halted.load(Ordering::Relaxed) || stop_reason(settings, deadline).is_some();
+    for_each_tile(
+        &tiles,
+        pool.as_ref(),
+        &should_stop,
+        pixel,
+        |tile, colors| {
+            for ((x, y), (color, alpha)) in tile.pixels().zip(colors) {
+                canvas.write_pixel(x, y, color);
+                canvas.write_alpha(x, y, alpha);
+                samples[y * width + x] = rays_per_pixel;
+            }
+            done += tile.len();
+            progress(done, total);
+            if settings
+                .sample_budget
+                .is_some_and(|budget| done * rays_per_pixel >= budget)
+            {
+                stopped = Some(StopReason::SampleBudget);
+                halted.store(true, Ordering::Relaxed);
+            }
+        },
+    );
+    if done < total {
+        // Workers skipped the tiles left after a cancel or the deadline
+        stopped = stopped.or_else(|| stop_reason(settings, deadline));
+    } else {
+        stopped = None;
+    }
+    RenderOutcome {
+        canvas,
+        samples,
+        requested_samples: rays_per_pixel,
+        passes_completed: usize::from(stopped.is_none()),
+        stopped,
+    }
+}
+
+/// Traces a single ray through the center of every pixel and keeps the
+/// surface it hits, for compositing and debugging next to the image.
+pub fn render_aovs(world: &VWorld, camera: &VCamera, settings: &RenderSettings) -> VAovBuffers {
+    let (width, height) = (camera.hsize, camera.vsize);
+    let mut surfaces = vec![None; width * height];
+    let tiles = tiles(width, height, settings.tile_size);
+    let pool = render_pool(settings);
+    for_each_tile(
+        &tiles,
+        pool.as_ref(),
+        &|| false,
+        |x, y| world.surface_at(camera.ray_for_pixel(x, y)),
+        |tile, pixels| {
+            for ((x, y), surface) in tile.pixels().zip(pixels) {
+                surfaces[y * width + x] = surface;
+            }
+        },
+    );
+    VAovBuffers {
+        width,
+        height,
+        surfaces,
+    }
+}
+
+/// State of a progressive render, handed to the preview callback.
+pub struct RenderUpdate<'a> {
+    /// Image as far as it is rendered, pixels not reached by the current
+    /// pass still show the result of the previous one.
+    pub canvas: &'a VCanvas,
+    /// Pass currently running, starting at 0.
+    pub pass: usize,
+    pub passes: usize,
+    /// Set on the last update of a pass.
+    pub pass_finished: bool,
+    /// Finished tiles of the current pass.
+    pub tiles_done: usize,
+    pub tiles: usize,
+}
+/// Renders in passes that refine the whole image: a coarse pass tracing one
+/// ray per `preview_block` square of pixels (skipped if that is 1), then one
+/// pass per sample. `preview` is called on the calling thread after every
+/// tile, returning false stops the render. On a rayon worker tiles are
+/// reported in rounds, see [`render_with_progress`].
+///
+/// The render also stops once it is cancelled through the settings' token
+/// or runs out of its time or sample budget. Tiles that were already started
+/// are finished, so a stopped render returns an image where every pixel has
+/// either all or none of the samples of a pass.
+pub fn render_progressive<C>(
+    world: &VWorld,
+    camera: &VCamera,
+    settings: &RenderSettings,
+    preview: C,
+) -> RenderOutcome
+where
+    C: FnMut(&RenderUpdate) -> bool,
+{
+    let state = RenderState::new(camera.hsize, camera.vsize, settings.tile_size);
+    resume_progressive(world, camera, settings, state, preview, |_| {})
+}
+/// Continues a progressive render from the state an earlier one stopped
+/// at, the coarse pass is skipped for renders that already have samples.
+/// `checkpoint` is called with the current state every
+/// `checkpoint_interval` and once more when the render ends.
+///
+/// The state has to belong to the same world and camera, a state of another
+/// image size panics. See [`VCheckpoint`](crate::checkpoint::VCheckpoint)
+/// for a way to persist and validate it.
+pub fn resume_progressive<C, S>(
+    world: &VWorld,
+    camera: &VCamera,
+    settings: &RenderSettings,
+    mut state: RenderState,
+    mut preview: C,
+    mut checkpoint: S,
+) -> RenderOutcome
+where
+    C: FnMut(&RenderUpdate) -> bool,
+    S: FnMut(&RenderState),
+{
+    let (width, height) = (camera.hsize, camera.vsize);
+    assert_eq!(
+        (state.width, state.height),
+        (width, height),
+        "Render state does not match the camera"
+    );
+    let block = settings.preview_block.max(1);
+    let samples = settings.samples.max(1);
+    let coarse = usize::from(block > 1 && !state.is_started());
+    let passes = coarse + samples;
+
+    let mut canvas = state.canvas();
+    let mut traced = 0;
+    let mut passes_completed = state.passes_completed.min(samples);
+    let mut stopped = None;
+    let mut last_checkpoint = Instant::now();
+    let pool = render_pool(settings);
+    let deadline = settings.time_budget.map(|budget| Instant::now() + budget);
+    // Set by the calling thread, the workers only look at the flag
+    let halted = AtomicBool::new(false);
+    let external_stop = || stop_reason(settings, deadline);
+    let should_stop = || halted.load(Ordering::Relaxed) || external_stop().is_some();
+    for pass in 0..passes {
+        let is_coarse = pass < coarse;
+        let sample = pass.saturating_sub(coarse);
+        if !is_coarse && sample < state.passes_completed {
+            continue;
+        }
+        stopped = stopped.or_else(external_stop);
+        if stopped.is_some() {
+            break;
+        }
+        let all_tiles = if is_coarse {
+            // Tiles over the grid of blocks, not over pixels
+            tiles(
+                width.div_ceil(block),
+                height.div_ceil(block),
+                state.tile_size,
+            )
+        } else {
+            state.tiles()
+        };
+        let pending: Vec<Tile> = if is_coarse {
+            all_tiles.clone()
+        } else {
+            all_tiles
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !state.tiles_completed.contains(index))
+                .map(|(_, tile)| *tile)
+                .collect()
+        };
+        let pixel = |x: usize, y: usize| {
+            if is_coarse {
+                let x = (x * block + block / 2).min(width - 1);
+                let y = (y * block + block / 2).min(height - 1);
+                let mut rng = VRng::for_sample(settings.seed, x, y, 0);
+                shade(world, settings, camera.ray_for_pixel(x, y), &mut rng)
+            } else {
+                trace_sample(world, camera, settings, x, y, sample)
+            }
+        };
+        let mut tiles_done = all_tiles.len() - pending.len();
+        for_each_tile(
+            &pending,
+            pool.as_ref(),
+            &should_stop,
+            pixel,
+            |tile, colors| {
+                for ((x, y), (color, alpha)) in tile.pixels().zip(colors) {
+                    if is_coarse {
+                        for py in y * block..((y + 1) * block).min(height) {
+                            for px in x * block..((x + 1) * block).min(width) {
+                                canvas.write_pixel(px, py, color);
+                                canvas.write_alpha(px, py, alpha);
+                            }
+                        }
+                    } else {
+                        let index = y * width + x;
+                        state.sums[index] = state.sums[index] + color;
+                        state.alphas[index] += alpha;
+                        state.counts[index] += 1;
+                        let (color, alpha) = state.average_at(index);
+                        canvas.write_pixel(x, y, color);
+                        canvas.write_alpha(x, y, alpha);
+                    }
+                }
+                tiles_done += 1;
+                if !is_coarse {
+                    traced += tile.len();
+                    state.tiles_completed.push(state.tile_index(&tile));
+                    if tiles_done == all_tiles.len() {
+                        state.passes_completed += 1;
+                        state.tiles_completed.clear();
+                    }
+                    if last_checkpoint.elapsed() >= settings.checkpoint_interval {
+                        checkpoint(&state);
+                        last_checkpoint = Instant::now();
+                    }
+                }
+                // Tiles finishing after a stop are kept, but not reported
+                if stopped.is_some() {
+                    return;
+                }
+                let update = RenderUpdate {
+                    canvas: &canvas,
+                    pass,
+                    passes,
+                    pass_finished: tiles_done == all_tiles.len(),
+                    tiles_done,
+                    tiles: all_tiles.len(),
+                };
+                stopped = external_stop();
+                if stopped.is_none() && settings.sample_budget.is_some_and(|b| traced >= b) {
+                    stopped = Some(StopReason::SampleBudget);
+                }
+                if stopped.is_none() && !preview(&update) {
+                    stopped = Some(StopReason::Preview);
+                }
+                if stopped.is_some() {
+                    halted.store(true, Ordering::Relaxed);
+                }
+            },
+        );
+        if tiles_done == all_tiles.len() {
+            passes_completed += 1;
+        } else {
+            // Workers noticed the cancel or deadline before this thread did
+            stopped = stopped.or_else(external_stop);
+        }
+    }
+    checkpoint(&state);
+    RenderOutcome {
+        canvas,
+        samples: state.counts,
+        requested_samples: samples,
+        passes_completed,
+        stopped,
+    }
+}
+
+/// Accumulated samples of a progressive render, everything needed to
+/// continue it later.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderState {
+    pub width: usize,
+    pub height: usize,
+    pub tile_size: usize,
+    /// Sum of all samples per pixel, row by row.
+    pub sums: Vec<VColor>,
+    /// Sum of the alpha of all samples per pixel, row by row.
+    pub alphas: Vec<F>,
+    /// Number of samples per pixel, row by row.
+    pub counts: Vec<usize>,
+    /// Sample passes that ran to completion.
+    pub passes_completed: usize,
+    /// Tiles finished of the pass after those, as indices into
+    /// [`tiles`] of the state's size.
+    pub tiles_completed: Vec<usize>,
+}
+impl RenderState {
+    pub fn new(width: usize, height: usize, tile_size: usize) -> Self {
+        Self {
+            width,
+            height,
+            tile_size: tile_size.max(1),
+            sums: vec![VColor::black(); width * height],
+            alphas: vec![0.0; width * height],
+            counts: vec![0; width * height],
+            passes_completed: 0,
+            tiles_completed: vec![],
+        }
+    }
+    pub fn is_started(&self) -> bool {
+        self.passes_completed > 0 || !self.tiles_completed.is_empty()
+    }
+    /// Averages the samples, pixels without samples are opaque black.
+    pub fn canvas(&self) -> VCanvas {
+        let mut canvas = VCanvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (color, alpha) = self.average_at(y * self.width + x);
+                canvas.write_pixel(x, y, color);
+                canvas.write_alpha(x, y, alpha);
+            }
+        }
+        canvas
+    }
+    pub fn tiles(&self) -> Vec<Tile> {
+        tiles(self.width, self.height, self.tile_size)
+    }
+    fn tile_index(&self, tile: &Tile) -> usize {
+        let per_row = self.width.div_ceil(self.tile_size);
+        (tile.y / self.tile_size) * per_row + tile.x / self.tile_size
+    }
+    fn average_at(&self, index: usize) -> (VColor, F) {
+        match self.counts[index] {
+            0 => (VColor::black(), 1.0),
+            count => average(self.sums[index], self.alphas[index], count),
+        }
+    }
+}
+
+// Pool to render on, none for the global pool and for renders already
+// running on a rayon worker, which share that worker's pool instead of
+// starting threads for every frame of a parallel animation.
+fn render_pool(settings: &RenderSettings) -> Option<rayon::ThreadPool> {
+    if rayon::current_thread_index().is_some() {
+        return None;
+    }
+    settings.thread_pool().ok().flatten()
+}
+// Why a render has to stop, apart from its sample budget and preview.
+fn stop_reason(settings: &RenderSettings, deadline: Option<Instant>) -> Option<StopReason> {
+    if settings.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+        Some(StopReason::Cancelled)
+    } else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        Some(StopReason::TimeBudget)
+    } else {
+        None
+    }
+}
+// Renders the tiles in parallel and calls `done` on the calling thread for
+// each of them. Tiles not started yet are skipped once `stop` returns true.
+// On a rayon worker, e.g. inside a parallel animation, blocking on finished
+// tiles could starve the pool, so they are rendered in rounds of one tile per
+// worker and reported after each round instead of one by one.
+fn for_each_tile<T, P, R>(
+    tiles: &[Tile],
+    pool: Option<&rayon::ThreadPool>,
+    stop: &(dyn Fn() -> bool + Sync),
+    pixel: P,
+    mut done: R,
+) where
+    T: Send,
+    P: Fn(usize, usize) -> T + Sync,
+    R: FnMut(Tile, Vec<T>),
+{
+    let render_tile = |tile: &Tile| {
+        if stop() {
+            return None;
+        }
+        let colors: Vec<T> = tile.pixels().map(|(x, y)| pixel(x, y)).collect();
+        Some((*tile, colors))
+    };
+    if rayon::current_thread_index().is_some() {
+        let workers = pool.map_or_else(rayon::current_num_threads, |p| p.current_num_threads());
+        for round in tiles.chunks(workers.max(1)) {
+            if stop() {
+                break;
+            }
+            let work = || round.par_iter().filter_map(render_tile).collect::<Vec<_>>();
+            let rendered = match pool {
+                Some(pool) => pool.install(work),
+                None => work(),
+            };
+            for (tile, colors) in rendered {
+                done(tile, colors);
+            }
+        }
+        return;
+    }
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let work = || {
+                tiles.par_iter().for_each_with(sender, |sender, tile| {
+                    if let Some(rendered) = render_tile(tile) {
+                        // Only fails if the receiving thread is gone already
+                        let _ = sender.send(rendered);
+                    }
+                })
+            };
+            match pool {
+                Some(pool) => pool.install(work),
+                None => work(),
+            }
+        });
+        for (tile, colors) in receiver {
+            done(tile, colors);
+        }
+    });
+}
+// Traces one camera ray. Each sample draws from its own random stream, so
+// progressive and one shot renders on any number of threads agree.
+fn trace_sample(
+    world: &VWorld,
+    camera: &VCamera,
+    settings: &RenderSettings,
+    x: usize,
+    y: usize,
+    sample: usize,
+) -> (VColor, F) {
+    let mut rng = VRng::for_sample(settings.seed, x, y, sample);
+    let (dx, dy) = if settings.jitter {
+        (rng.next_f(), rng.next_f())
+    } else {
+        sample_offset(sample)
+    };
+    let ray = camera.ray_for_subpixel(x, y, dx, dy);
+    shade(world, settings, ray, &mut rng)
+}
+// Color and alpha seen along a camera ray, transparent misses are black.
+fn shade(world: &VWorld, settings: &RenderSettings, ray: VRay, rng: &mut VRng) -> (VColor, F) {
+    match world.hit_color_with(ray, rng) {
+        Some(color) => (color, 1.0),
+        None if settings.transparent => (VColor::black(), 0.0),
+        None => (world.background.color_at(ray.direction), 1.0),
+    }
+}
+// Turns sums over `count` samples into a color and alpha. Misses add black,
+// so dividing by the alpha instead of the count leaves the color of the
+// covered part, which is what the canvas stores.
+fn average(sum: VColor, alpha: F, count: usize) -> (VColor, F) {
+    if alpha > 0.0 {
+        (sum * (1.0 / alpha), alpha / count as F)
+    } else {
+        (VColor::black(), 0.0)
+    }
+}
+// Points of the R2 low discrepancy sequence, starting in the pixel center
+// so a single sample matches the unsampled render.
+fn sample_offset(i: usize) -> (F, F) {
+    const A1: F = 0.754_877_666_246_692_7;
+    const A2: F = 0.569_840_290_998_053_3;
+    ((0.5 + A1 * i as F).fract(), (0.5 + A2 * i as F).fract())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::VBackground;
+    use crate::tuple::VTuple;
+    use crate::world::create_default_world;
+    use crate::zequality::*;
+    use std::f64::consts::PI;
+
+    fn create_camera() -> VCamera {
+        VCamera::new(11, 11, PI / 2.0).positioned_and_pointed(
+            VTuple::point(0.0, 0.0, -5.0),
+            VTuple::point(0.0, 0.0, 0.0),
+            VTuple::vector(0.0, 1.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn tiles_cover_the_image_once() {
+        let tiles = tiles(10, 7, 4);
+
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(
+            tiles[2],
+            Tile {
+                x: 8,
+                y: 0,
+                width: 2,
+                height: 4
+            }
+        );
+        assert_eq!(tiles.iter().map(|t| t.len()).sum::<usize>(), 70);
+        let mut pixels: Vec<(usize, usize)> = tiles.iter().flat_map(|t| t.pixels()).collect();
+        pixels.sort();
+        pixels.dedup();
+        assert_eq!(pixels.len(), 70);
+    }
+
+    #[test]
+    fn rendering_a_world_with_a_camera() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let image = render(&world, &camera, &RenderSettings::default()).canvas;
+
+        assert_zeq!(image.pixel_at(5, 5), VColor::new(0.34066, 0.42583, 0.25550));
+        for (x, y) in [(0, 0), (3, 9), (10, 10)] {
+            assert_zeq!(
+                image.pixel_at(x, y),
+                world.color_at(camera.ray_for_pixel(x, y))
+            );
+        }
+    }
+
+    #[test]
+    fn rendering_aovs_keeps_the_surfaces() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let aovs = render_aovs(
+            &world,
+            &camera,
+            &RenderSettings::default().with_tile_size(4),
+        );
+
+        let center = aovs.surface_at(5, 5).unwrap();
+        assert_zeq!(center.t, 4.0);
+        assert_eq!(center.body, 0);
+        assert_zeq!(center.normal, VTuple::vector(0.0, 0.0, -1.0));
+        assert_eq!(aovs.surface_at(0, 0), None);
+        for (x, y) in [(3, 4), (7, 2)] {
+            assert_eq!(
+                aovs.surface_at(x, y),
+                world.surface_at(camera.ray_for_pixel(x, y))
+            );
+        }
+    }
+
+    #[test]
+    fn rendering_with_own_threads_and_small_tiles() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let settings = RenderSettings::default().with_threads(2).with_tile_size(3);
+        let mut calls = vec![];
+        let image = render_with_progress(&world, &camera, &settings, |done, total| {
+            calls.push((done, total))
+        })
+        .canvas;
+
+        assert_zeq!(image.pixel_at(5, 5), VColor::new(0.34066, 0.42583, 0.25550));
+        assert_eq!(calls.len(), 16);
+        assert_eq!(calls.last(), Some(&(121, 121)));
+        assert!(calls.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn thread_pools_are_only_started_when_asked_for() {
+        let settings = RenderSettings::default();
+        assert!(settings.thread_pool().unwrap().is_none());
+
+        let pool = settings.with_threads(2).thread_pool().unwrap().unwrap();
+        assert_eq!(pool.current_num_threads(), 2);
+    }
+
+    #[test]
+    fn supersampling_averages_the_samples() {
+        let image = render_pixels(
+            2,
+            2,
+            &RenderSettings::default(),
+            |x, y| VColor::new(x as F, y as F, 1.0),
+            |_, _| {},
+        )
+        .canvas;
+
+        assert_zeq!(image.pixel_at(1, 0), VColor::new(1.0, 0.0, 1.0));
+        assert_eq!(sample_offset(0), (0.5, 0.5));
+
+        let world = create_default_world();
+        let camera = create_camera();
+        let image = render(&world, &camera, &RenderSettings::default().with_samples(4)).canvas;
+        let mut expected = VColor::black();
+        for i in 0..4 {
+            let (dx, dy) = sample_offset(i);
+            expected = expected + world.color_at(camera.ray_for_subpixel(2, 7, dx, dy)) * 0.25;
+        }
+        assert_zeq!(image.pixel_at(2, 7), expected);
+    }
+
+    #[test]
+    fn missed_pixels_can_be_transparent() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let settings = RenderSettings::default().with_samples(4);
+        let opaque = render(&world, &camera, &settings).canvas;
+        let settings = settings.with_transparent_background(true);
+        let image = render(&world, &camera, &settings).canvas;
+
+        assert!(opaque.is_opaque());
+        assert_zeq!(image.alpha_at(0, 0), 0.0);
+        assert_zeq!(image.pixel_at(0, 0), VColor::black());
+        assert_zeq!(image.alpha_at(5, 5), 1.0);
+        assert_zeq!(image.pixel_at(5, 5), opaque.pixel_at(5, 5));
+        // Edge pixels keep the color of the covered part
+        let (x, y) = (0..11)
+            .flat_map(|y| (0..11).map(move |x| (x, y)))
+            .find(|(x, y)| image.alpha_at(*x, *y) > 0.0 && image.alpha_at(*x, *y) < 1.0)
+            .unwrap();
+        assert_zeq!(
+            image.pixel_at(x, y) * image.alpha_at(x, y),
+            opaque.pixel_at(x, y)
+        );
+
+        let progressive = render_progressive(&world, &camera, &settings, |_| true).canvas;
+        assert_zeq!(progressive, &image);
+    }
+
+    #[test]
+    fn missed_pixels_show_the_background() {
+        let world = create_default_world().with_background(VBackground::Solid(VColor::blue()));
+        let camera = create_camera();
+        let image = render(&world, &camera, &RenderSettings::default()).canvas;
+
+        assert_zeq!(image.pixel_at(0, 0), VColor::blue());
+        let settings = RenderSettings::default().with_transparent_background(true);
+        assert_zeq!(
+            render(&world, &camera, &settings).canvas.alpha_at(0, 0),
+            0.0
+        );
+    }
+
+    #[test]
+    fn progressive_rendering_converges_to_the_full_render() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let settings = RenderSettings::default().with_samples(3).with_tile_size(4);
+        let mut passes = vec![];
+        let outcome = render_progressive(&world, &camera, &settings, |update| {
+            if update.pass_finished {
+                passes.push((update.pass, update.passes, update.tiles));
+            }
+            true
+        });
+        let image = outcome.canvas;
+        let expected = render(&world, &camera, &settings).canvas;
+
+        assert_eq!(passes, vec![(0, 4, 1), (1, 4, 9), (2, 4, 9), (3, 4, 9)]);
+        for (x, y) in [(0, 0), (5, 5), (2, 7), (10, 10)] {
+            assert_zeq!(image.pixel_at(x, y), expected.pixel_at(x, y));
+        }
+    }
+
+    #[test]
+    fn the_coarse_pass_fills_whole_blocks() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let settings = RenderSettings::default().with_preview_block(4);
+        let mut preview = None;
+        render_progressive(&world, &camera, &settings, |update| {
+            if update.pass_finished {
+                preview = Some(
+                    (0..11)
+                        .map(|x| update.canvas.pixel_at(x, 5))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            !update.pass_finished
+        });
+        let row = preview.unwrap();
+
+        assert_zeq!(row[4], row[7]);
+        assert_zeq!(row[4], world.color_at(camera.ray_for_pixel(6, 6)));
+        assert_zeq!(row[8], row[10]);
+    }
+
+    #[test]
+    fn a_preview_can_stop_the_render() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let settings = RenderSettings::default().with_samples(4).with_tile_size(2);
+        let mut updates = 0;
+        let outcome = render_progressive(&world, &camera, &settings, |_| {
+            updates += 1;
+            false
+        });
+
+        assert_eq!(updates, 1);
+        assert_eq!(outcome.stopped, Some(StopReason::Preview));
+        assert_eq!(outcome.passes_completed, 1);
+        assert_zeq!(outcome.coverage(), 0.0);
+    }
+
+    #[test]
+    fn a_cancelled_render_reports_its_coverage() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let cancel = CancelToken::new();
+        let settings = RenderSettings::default()
+            .with_samples(4)
+            .with_preview_block(1)
+            .with_tile_size(4)
+            .with_cancel_token(cancel.clone());
+        let outcome = render_progressive(&world, &camera, &settings, |update| {
+            if update.pass_finished {
+                cancel.cancel();
+            }
+            true
+        });
+
+        assert_eq!(outcome.stopped, Some(StopReason::Cancelled));
+        assert!(!outcome.is_complete());
+        assert_eq!(outcome.passes_completed, 1);
+        assert_zeq!(outcome.coverage(), 0.25);
+        assert_eq!(outcome.samples_at(10, 10), 1);
+        assert_zeq!(
+            outcome.canvas.pixel_at(5, 5),
+            world.color_at(camera.ray_for_pixel(5, 5))
+        );
+    }
+
+    #[test]
+    fn a_render_cancelled_up_front_traces_nothing() {
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let settings = RenderSettings::default().with_cancel_token(cancel);
+        let outcome =
+            render_progressive(&create_default_world(), &create_camera(), &settings, |_| {
+                true
+            });
+
+        assert_eq!(outcome.stopped, Some(StopReason::Cancelled));
+        assert_zeq!(outcome.coverage(), 0.0);
+        assert_eq!(outcome.canvas.pixel_at(5, 5), VColor::black());
+    }
+
+    #[test]
+    fn one_shot_renders_stop_like_progressive_ones() {
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let world = create_default_world();
+        let camera = create_camera();
+        for (settings, reason) in [
+            (
+                RenderSettings::default().with_cancel_token(cancel),
+                StopReason::Cancelled,
+            ),
+            (
+                RenderSettings::default().with_time_budget(Duration::ZERO),
+                StopReason::TimeBudget,
+            ),
+        ] {
+            let mut calls = 0;
+            let outcome = render_with_progress(&world, &camera, &settings, |_, _| calls += 1);
+            assert_eq!(calls, 0);
+            assert_eq!(outcome.stopped, Some(reason));
+            assert_zeq!(outcome.coverage(), 0.0);
+            assert_eq!(outcome.canvas.pixel_at(5, 5), VColor::black());
+        }
+
+        let outcome = render(&world, &camera, &RenderSettings::default().with_samples(2));
+        assert!(outcome.is_complete());
+        assert_eq!(outcome.passes_completed, 1);
+        assert_zeq!(outcome.coverage(), 1.0);
+    }
+
+    #[test]
+    fn a_cancelled_one_shot_render_reports_its_coverage() {
+        // Slow pixels, so the render is cancelled long before it is done
+        let slow = |_, _| {
+            thread::sleep(Duration::from_millis(1));
+            VColor::white()
+        };
+        let cancel = CancelToken::new();
+        let settings = RenderSettings::default()
+            .with_threads(1)
+            .with_tile_size(4)
+            .with_cancel_token(cancel.clone());
+        let outcome = render_pixels(32, 32, &settings, slow, |_, _| cancel.cancel());
+
+        assert_eq!(outcome.stopped, Some(StopReason::Cancelled));
+        assert!(outcome.coverage() > 0.0 && outcome.coverage() < 1.0);
+        assert_eq!(outcome.samples_at(0, 0), 1);
+        assert_eq!(outcome.samples_at(31, 31), 0);
+        assert_eq!(outcome.canvas.pixel_at(0, 0), VColor::white());
+
+        // Tiles in flight when the budget runs out are still finished
+        let settings = RenderSettings::default()
+            .with_threads(1)
+            .with_tile_size(4)
+            .with_sample_budget(16);
+        let mut last = (0, 0);
+        let outcome = render_pixels(32, 32, &settings, slow, |done, total| last = (done, total));
+        assert!(last.0 >= 16 && last.0 < last.1);
+        assert_eq!(outcome.stopped, Some(StopReason::SampleBudget));
+        assert_zeq!(outcome.coverage(), last.0 as F / last.1 as F);
+    }
+
+    #[test]
+    fn renders_on_a_rayon_worker_report_rounds_and_stop() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        // The worker's pool is used, not a new one of 3 threads
+        let settings = RenderSettings::default()
+            .with_threads(3)
+            .with_tile_size(4)
+            .with_sample_budget(16);
+        let mut calls = vec![];
+        let outcome = pool.install(|| {
+            render_pixels(
+                32,
+                32,
+                &settings,
+                |_, _| VColor::new(rayon::current_num_threads() as F, 0.0, 0.0),
+                |done, _| calls.push(done),
+            )
+        });
+
+        assert_eq!(calls, vec![16, 32]);
+        assert_eq!(outcome.stopped, Some(StopReason::SampleBudget));
+        assert_zeq!(outcome.coverage(), 32.0 / 1024.0);
+        assert_zeq!(outcome.canvas.pixel_at(0, 0), VColor::new(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn renders_stop_when_out_of_budget() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let settings = RenderSettings::default()
+            .with_samples(4)
+            .with_preview_block(1)
+            .with_threads(1)
+            .with_sample_budget(2 * 121);
+        let outcome = render_progressive(&world, &camera, &settings, |_| true);
+
+        assert_eq!(outcome.stopped, Some(StopReason::SampleBudget));
+        assert_eq!(outcome.passes_completed, 2);
+        assert_zeq!(outcome.coverage(), 0.5);
+
+        let settings = RenderSettings::default().with_time_budget(Duration::ZERO);
+        let outcome = render_progressive(&world, &camera, &settings, |_| true);
+        assert_eq!(outcome.stopped, Some(StopReason::TimeBudget));
+
+        let settings = RenderSettings::default().with_time_budget(Duration::from_secs(60));
+        let outcome = render_progressive(&world, &camera, &settings, |_| true);
+        assert!(outcome.is_complete());
+        assert_zeq!(outcome.coverage(), 1.0);
+    }
+
+    #[test]
+    fn seeded_renders_do_not_depend_on_scheduling() {
+        let world = create_default_world();
+        let camera = create_camera();
+        let settings = RenderSettings::default()
+            .with_samples(4)
+            .with_jitter(true)
+            .with_seed(42);
+        let pixels = |canvas: &VCanvas| -> Vec<VColor> {
+            (0..camera.vsize)
+                .flat_map(|y| (0..camera.hsize).map(move |x| (x, y)))
+                .map(|(x, y)| canvas.pixel_at(x, y))
+                .collect()
+        };
+
+        let single = pixels(&render(&world, &camera, &settings.clone().with_threads(1)).canvas);
+        let parallel = pixels(
+            &render(
+                &world,
+                &camera,
+                &settings.clone().with_threads(4).with_tile_size(3),
+            )
+            .canvas,
+        );
+        let progressive = render_progressive(&world, &camera, &settings, |_| true);
+        let reseeded = pixels(&render(&world, &camera, &settings.clone().with_seed(43)).canvas);
+
+        assert_eq!(single, parallel);
+        assert_eq!(single, pixels(&progressive.canvas));
+        assert_ne!(single, reseeded);
+    }
+}
@@ -0,0 +1,92 @@
+use crate::F;
+
+/// Small and fast pseudo random generator (SplitMix64). Not suitable for
+/// anything but sampling, but the same seed always gives the same numbers on
+/// every platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VRng {
+    state: u64,
+}
+impl VRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+    /// Generator for one sample of one pixel. Every sample gets its own
+    /// stream, so the result does not depend on the order or the thread the
+    /// samples are traced in.
+    pub fn for_sample(seed: u64, x: usize, y: usize, sample: usize) -> Self {
+        let state = [x, y, sample]
+            .iter()
+            .fold(mix(seed), |state, value| mix(state ^ *value as u64));
+        Self { state }
+    }
+    /// Generator seeded from the exact bits of some numbers, for callers
+    /// without a stream of their own.
+    pub fn for_values(values: &[F]) -> Self {
+        let state = values
+            .iter()
+            .fold(mix(0), |state, value| mix(state ^ value.to_bits()));
+        Self { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+    /// Uniformly distributed in [0, 1).
+    pub fn next_f(&mut self) -> F {
+        // The upper 53 bits fill the whole mantissa
+        (self.next_u64() >> 11) as F * (1.0 / (1u64 << 53) as F)
+    }
+    /// Uniformly distributed in [min, max).
+    pub fn next_in(&mut self, min: F, max: F) -> F {
+        min + (max - min) * self.next_f()
+    }
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_gives_the_same_numbers() {
+        let mut a = VRng::new(7);
+        let mut b = VRng::new(7);
+        let mut c = VRng::new(8);
+        let a: Vec<u64> = (0..4).map(|_| a.next_u64()).collect();
+        let b: Vec<u64> = (0..4).map(|_| b.next_u64()).collect();
+        let c: Vec<u64> = (0..4).map(|_| c.next_u64()).collect();
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn floats_are_spread_over_the_unit_interval() {
+        let mut rng = VRng::new(1);
+        let values: Vec<F> = (0..10_000).map(|_| rng.next_f()).collect();
+        let mean = values.iter().sum::<F>() / values.len() as F;
+
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        assert!((mean - 0.5).abs() < 0.02);
+        assert!((2.0..3.0).contains(&VRng::new(2).next_in(2.0, 3.0)));
+    }
+
+    #[test]
+    fn neighbouring_samples_get_different_streams() {
+        let first = VRng::for_sample(0, 3, 4, 0).next_u64();
+
+        assert_eq!(first, VRng::for_sample(0, 3, 4, 0).next_u64());
+        assert_ne!(first, VRng::for_sample(0, 4, 3, 0).next_u64());
+        assert_ne!(first, VRng::for_sample(0, 3, 4, 1).next_u64());
+        assert_ne!(first, VRng::for_sample(1, 3, 4, 0).next_u64());
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::animation::VAnimation;
+use crate::camera::VCamera;
+use crate::world::VWorld;
+use crate::world_loader::scene_from_yaml;
+use crate::F;
+
+#[derive(Debug)]
+pub enum VSceneError {
+    Io(std::io::Error),
+    Ron(ron::Error),
+    Json(serde_json::Error),
+    Yaml(serde_yaml::Error),
+    Invalid(String),
+    UnknownFormat(String),
+}
+impl fmt::Display for VSceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VSceneError::Io(e) => write!(f, "could not access scene file: {}", e),
+            VSceneError::Ron(e) => write!(f, "invalid RON scene: {}", e),
+            VSceneError::Json(e) => write!(f, "invalid JSON scene: {}", e),
+            VSceneError::Yaml(e) => write!(f, "invalid YAML scene: {}", e),
+            VSceneError::Invalid(message) => write!(f, "invalid scene: {}", message),
+            VSceneError::UnknownFormat(ext) => write!(f, "unknown scene format {:?}", ext),
+        }
+    }
+}
+impl std::error::Error for VSceneError {}
+impl From<std::io::Error> for VSceneError {
+    fn from(e: std::io::Error) -> Self {
+        VSceneError::Io(e)
+    }
+}
+impl From<ron::Error> for VSceneError {
+    fn from(e: ron::Error) -> Self {
+        VSceneError::Ron(e)
+    }
+}
+impl From<serde_json::Error> for VSceneError {
+    fn from(e: serde_json::Error) -> Self {
+        VSceneError::Json(e)
+    }
+}
+impl From<serde_yaml::Error> for VSceneError {
+    fn from(e: serde_yaml::Error) -> Self {
+        VSceneError::Yaml(e)
+    }
+}
+
+/// A world together with the named cameras looking at it. This is the unit
+/// that gets written to and read from scene files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VScene {
+    pub world: VWorld,
+    pub cameras: BTreeMap<String, VCamera>,
+    /// Keyframes changing the scene over time, see [`VScene::at_frame`].
+    #[serde(default, skip_serializing_if = "VAnimation::is_empty")]
+    pub animation: VAnimation,
+}
+impl VScene {
+    pub fn new(world: VWorld) -> Self {
+        Self {
+            world,
+            cameras: BTreeMap::new(),
+            animation: VAnimation::default(),
+        }
+    }
+    pub fn with_camera(mut self, name: &str, camera: VCamera) -> Self {
+        self.cameras.insert(String::from(name), camera);
+        self
+    }
+    /// Looks up a camera by name. Without a name the scene has to contain a
+    /// single camera, or one called `camera`.
+    pub fn camera(&self, name: Option<&str>) -> Result<&VCamera, VSceneError> {
+        let camera = match name {
+            Some(name) => self.cameras.get(name),
+            None if self.cameras.len() == 1 => self.cameras.values().next(),
+            None => self.cameras.get("camera"),
+        };
+        camera.ok_or_else(|| {
+            let names: Vec<&String> = self.cameras.keys().collect();
+            VSceneError::Invalid(match name {
+                Some(name) => format!("no camera named {:?}, available: {:?}", name, names),
+                None => format!("no default camera, pick one of {:?}", names),
+            })
+        })
+    }
+    pub fn with_animation(mut self, animation: VAnimation) -> Self {
+        self.animation = animation;
+        self
+    }
+    /// The scene as it looks at `frame`, with every keyframed value
+    /// interpolated. Frames may be fractional.
+    pub fn at_frame(&self, frame: F) -> VScene {
+        let mut scene = self.clone();
+        self.animation.apply(&mut scene, frame);
+        scene
+    }
+    pub fn world_at(&self, frame: F) -> VWorld {
+        self.at_frame(frame).world
+    }
+    pub fn from_ron(data: &str) -> Result<Self, VSceneError> {
+        Ok(ron::de::from_str(data)?)
+    }
+    pub fn to_ron(&self) -> Result<String, VSceneError> {
+        Ok(ron::ser::to_string_pretty(self, PrettyConfig::new())?)
+    }
+    pub fn from_json(data: &str) -> Result<Self, VSceneError> {
+        Ok(serde_json::from_str(data)?)
+    }
+    pub fn to_json(&self) -> Result<String, VSceneError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    /// Reads the YAML scene format of "The Ray Tracer Challenge", see
+    /// [`scene_from_yaml`](crate::world_loader::scene_from_yaml).
+    pub fn from_yaml(data: &str) -> Result<Self, VSceneError> {
+        scene_from_yaml(data)
+    }
+    /// Loads a scene, picking the format from the file extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, VSceneError> {
+        let data = fs::read_to_string(&path)?;
+        match extension_of(path.as_ref()).as_str() {
+            "ron" => Self::from_ron(&data),
+            "json" => Self::from_json(&data),
+            "yml" | "yaml" => Self::from_yaml(&data),
+            ext => Err(VSceneError::UnknownFormat(String::from(ext))),
+        }
+    }
+    /// Saves a scene, picking the format from the file extension. Only RON
+    /// and JSON can be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), VSceneError> {
+        let data = match extension_of(path.as_ref()).as_str() {
+            "ron" => self.to_ron()?,
+            "json" => self.to_json()?,
+            ext => return Err(VSceneError::UnknownFormat(String::from(ext))),
+        };
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::body::VBody;
+    use crate::canvas::vcolor::VColor;
+    use crate::cube::VCube;
+    use crate::group::VGroup;
+    use crate::light::VPointLight;
+    use crate::material::{VMaterial, VPhong};
+    use crate::matrix::VMatrix;
+    use crate::plane::VPlane;
+    use crate::sphere::VSphere;
+    use crate::tuple::VTuple;
+    use std::f64::consts::PI;
+
+    fn create_scene() -> VScene {
+        let sphere = VSphere::default()
+            .with_transform(VMatrix::translation(-3.0, 1.0, -2.0) * VMatrix::rotation_y(PI / 7.0))
+            .with_material(VMaterial::from(VPhong::default().with_color(VColor::red())));
+        let floor = VPlane::default().with_material(VMaterial::from(VPhong {
+            col: VColor::new(0.2, 0.2, 0.2),
+            spc: 0.0,
+            ..VPhong::default()
+        }));
+        let group = VGroup::new(vec![VBody::from(
+            VCube::default().with_transform(VMatrix::scaling(0.5, 2.0, 0.5)),
+        )])
+        .with_transform(VMatrix::translation(2.0, 0.0, 0.0));
+        let world = VWorld::new(
+            vec![VBody::from(sphere), VBody::from(floor), VBody::from(group)],
+            vec![VPointLight::new(
+                VTuple::point(-5.0, 15.0, -10.0),
+                VColor::new(0.9, 0.9, 0.9),
+            )],
+        );
+        VScene::new(world).with_camera(
+            "main",
+            VCamera::new(320, 200, PI / 3.0).positioned_and_pointed(
+                VTuple::point(-10.0, 10.0, -10.0),
+                VTuple::point(0.0, 0.0, 0.0),
+                VTuple::vector(0.0, 1.0, 0.0),
+            ),
+        )
+    }
+
+    #[test]
+    fn scene_round_trips_through_ron() {
+        let scene = create_scene();
+        let data = scene.to_ron().unwrap();
+        let loaded = VScene::from_ron(&data).unwrap();
+
+        assert_eq!(loaded, scene);
+        assert_eq!(loaded.to_ron().unwrap(), data);
+    }
+
+    #[test]
+    fn scene_round_trips_through_json() {
+        let scene = create_scene();
+        let data = scene.to_json().unwrap();
+        let loaded = VScene::from_json(&data).unwrap();
+
+        assert_eq!(loaded, scene);
+        assert_eq!(loaded.to_json().unwrap(), data);
+    }
+
+    #[test]
+    fn loaded_camera_recomputes_pixel_size() {
+        let scene = create_scene();
+        let loaded = VScene::from_json(&scene.to_json().unwrap()).unwrap();
+
+        assert_eq!(
+            loaded.cameras["main"].ray_for_pixel(10, 20),
+            scene.cameras["main"].ray_for_pixel(10, 20)
+        );
+    }
+
+    #[test]
+    fn looking_up_cameras() {
+        let scene = create_scene();
+        let other = VCamera::new(10, 10, PI / 2.0);
+
+        assert_eq!(scene.camera(None).unwrap(), &scene.cameras["main"]);
+        assert_eq!(scene.camera(Some("main")).unwrap(), &scene.cameras["main"]);
+        assert!(matches!(scene.camera(Some("top")), Err(VSceneError::Invalid(_))));
+
+        let scene = scene.with_camera("top", other);
+        assert!(matches!(scene.camera(None), Err(VSceneError::Invalid(_))));
+        assert_eq!(scene.with_camera("camera", other).camera(None).unwrap(), &other);
+    }
+
+    #[test]
+    fn unknown_extensions_are_rejected() {
+        let result = create_scene().save("scene.txt");
+
+        assert!(matches!(result, Err(VSceneError::UnknownFormat(_))));
+    }
+}
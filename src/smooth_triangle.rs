@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+
+use crate::body::VBody;
+use crate::body::VIntersectable;
+use crate::bounds::VBounds;
+use crate::intersections::VIntersection;
+use crate::material::*;
+use crate::matrix::*;
+use crate::ray::VRay;
+use crate::triangle::{intersect_triangle, triangle_bounds};
+use crate::tuple::VTuple;
+use crate::F;
+
+/// Triangle with a normal per vertex, the normal at a hit is interpolated
+/// from the barycentric `u`/`v` stored in the intersection.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "VSmoothTriangleConfig", into = "VSmoothTriangleConfig")]
+pub struct VSmoothTriangle {
+    pub p1: VTuple,
+    pub p2: VTuple,
+    pub p3: VTuple,
+    pub n1: VTuple,
+    pub n2: VTuple,
+    pub n3: VTuple,
+    pub transform: VMatrix<4>,
+    pub material: VMaterial,
+    e1: VTuple,
+    e2: VTuple,
+}
+#[derive(Serialize, Deserialize)]
+struct VSmoothTriangleConfig {
+    p1: VTuple,
+    p2: VTuple,
+    p3: VTuple,
+    n1: VTuple,
+    n2: VTuple,
+    n3: VTuple,
+    transform: VMatrix<4>,
+    material: VMaterial,
+}
+impl From<VSmoothTriangleConfig> for VSmoothTriangle {
+    fn from(cfg: VSmoothTriangleConfig) -> Self {
+        VSmoothTriangle::new(cfg.p1, cfg.p2, cfg.p3, cfg.n1, cfg.n2, cfg.n3)
+            .with_transform(cfg.transform)
+            .with_material(cfg.material)
+    }
+}
+impl From<VSmoothTriangle> for VSmoothTriangleConfig {
+    fn from(triangle: VSmoothTriangle) -> Self {
+        VSmoothTriangleConfig {
+            p1: triangle.p1,
+            p2: triangle.p2,
+            p3: triangle.p3,
+            n1: triangle.n1,
+            n2: triangle.n2,
+            n3: triangle.n3,
+            transform: triangle.transform,
+            material: triangle.material,
+        }
+    }
+}
+
+impl VSmoothTriangle {
+    pub fn new(p1: VTuple, p2: VTuple, p3: VTuple, n1: VTuple, n2: VTuple, n3: VTuple) -> Self {
+        Self {
+            p1,
+            p2,
+            p3,
+            n1,
+            n2,
+            n3,
+            transform: VMatrix::identity(),
+            material: Default::default(),
+            e1: p2 - p1,
+            e2: p3 - p1,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: VMatrix<4>) -> Self {
+        self.transform = transform;
+        self
+    }
+    pub fn with_material(mut self, material: VMaterial) -> Self {
+        self.material = material;
+        self
+    }
+}
+impl VIntersectable for VSmoothTriangle {
+    fn intersect_in_object_space(&self, ray: VRay) -> Vec<VIntersection> {
+        match intersect_triangle(ray, self.p1, self.e1, self.e2) {
+            Some((t, u, v)) => vec![VIntersection::new(t, ray, VBody::from(*self)).with_uv(u, v)],
+            None => vec![],
+        }
+    }
+
+    fn transform(&self) -> VMatrix<4> {
+        self.transform
+    }
+    fn normal_at_in_object_space(&self, p: VTuple) -> VTuple {
+        self.normal_at_in_object_space_with_uv(p, 0.0, 0.0)
+    }
+    fn normal_at_in_object_space_with_uv(&self, _: VTuple, u: F, v: F) -> VTuple {
+        self.n2 * u + self.n3 * v + self.n1 * (1.0 - u - v)
+    }
+    fn material(&self) -> VMaterial {
+        self.material
+    }
+    fn bounds_in_object_space(&self) -> VBounds {
+        triangle_bounds(self.p1, self.p2, self.p3)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::ZEq;
+
+    fn create_triangle() -> VSmoothTriangle {
+        VSmoothTriangle::new(
+            VTuple::point(0.0, 1.0, 0.0),
+            VTuple::point(-1.0, 0.0, 0.0),
+            VTuple::point(1.0, 0.0, 0.0),
+            VTuple::vector(0.0, 1.0, 0.0),
+            VTuple::vector(-1.0, 0.0, 0.0),
+            VTuple::vector(1.0, 0.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn an_intersection_with_a_smooth_triangle_stores_u_v() {
+        let r = VRay::new(VTuple::point(-0.2, 0.3, -2.0), VTuple::vector(0.0, 0.0, 1.0));
+        let xs = create_triangle().intersect(r);
+
+        assert_zeq!(xs[0].u, 0.45);
+        assert_zeq!(xs[0].v, 0.25);
+    }
+
+    #[test]
+    fn a_smooth_triangle_uses_u_v_to_interpolate_the_normal() {
+        let t = create_triangle();
+        let n = t.normal_at_with_uv(VTuple::point(0.0, 0.0, 0.0), 0.45, 0.25);
+
+        assert_zeq!(n, VTuple::vector(-0.5547, 0.83205, 0.0));
+    }
+
+    #[test]
+    fn preparing_the_normal_on_a_smooth_triangle() {
+        let r = VRay::new(VTuple::point(-0.2, 0.3, -2.0), VTuple::vector(0.0, 0.0, 1.0));
+        let xs = create_triangle().intersect(r);
+        let c = xs[0].get_computed();
+
+        assert_zeq!(c.normalv, VTuple::vector(-0.5547, 0.83205, 0.0));
+    }
+}
@@ -1,13 +1,15 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::body::VBody;
 use crate::body::VIntersectable;
+use crate::bounds::VBounds;
+use crate::intersections::VIntersection;
 use crate::material::*;
 use crate::matrix::*;
 use crate::ray::VRay;
 use crate::tuple::VTuple;
 
-#[derive(Copy, Clone, Debug, PartialEq,Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct VSphere {
     pub transform: VMatrix<4>,
     pub material: VMaterial,
@@ -42,7 +44,7 @@ impl VSphere {
     }
 }
 impl VIntersectable for VSphere {
-    fn intersect_in_object_space(&self, ray: VRay) -> Vec<(f64, VBody)> {
+    fn intersect_in_object_space(&self, ray: VRay) -> Vec<VIntersection> {
         let sphere_to_ray = ray.origin - VTuple::point(0.0, 0.0, 0.0);
 
         let a = ray.direction.dot(&ray.direction);
@@ -54,8 +56,8 @@ impl VIntersectable for VSphere {
             vec![]
         } else {
             vec![
-                ((-b - discriminant.sqrt()) / (2.0 * a), VBody::from(*self)),
-                ((-b + discriminant.sqrt()) / (2.0 * a), VBody::from(*self)),
+                VIntersection::new((-b - discriminant.sqrt()) / (2.0 * a), ray, VBody::from(*self)),
+                VIntersection::new((-b + discriminant.sqrt()) / (2.0 * a), ray, VBody::from(*self)),
             ]
         }
     }
@@ -69,6 +71,9 @@ impl VIntersectable for VSphere {
     fn material(&self) -> VMaterial {
         self.material
     }
+    fn bounds_in_object_space(&self) -> VBounds {
+        VBounds::unit()
+    }
 }
 #[cfg(test)]
 mod tests {
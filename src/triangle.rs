@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+
+use crate::body::VBody;
+use crate::body::VIntersectable;
+use crate::bounds::VBounds;
+use crate::intersections::VIntersection;
+use crate::material::*;
+use crate::matrix::*;
+use crate::ray::VRay;
+use crate::tuple::VTuple;
+use crate::F;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "VTriangleConfig", into = "VTriangleConfig")]
+pub struct VTriangle {
+    pub p1: VTuple,
+    pub p2: VTuple,
+    pub p3: VTuple,
+    pub transform: VMatrix<4>,
+    pub material: VMaterial,
+    e1: VTuple,
+    e2: VTuple,
+    normal: VTuple,
+}
+// Edges and normal are derived from the points and recomputed on load.
+#[derive(Serialize, Deserialize)]
+struct VTriangleConfig {
+    p1: VTuple,
+    p2: VTuple,
+    p3: VTuple,
+    transform: VMatrix<4>,
+    material: VMaterial,
+}
+impl From<VTriangleConfig> for VTriangle {
+    fn from(cfg: VTriangleConfig) -> Self {
+        VTriangle::new(cfg.p1, cfg.p2, cfg.p3)
+            .with_transform(cfg.transform)
+            .with_material(cfg.material)
+    }
+}
+impl From<VTriangle> for VTriangleConfig {
+    fn from(triangle: VTriangle) -> Self {
+        VTriangleConfig {
+            p1: triangle.p1,
+            p2: triangle.p2,
+            p3: triangle.p3,
+            transform: triangle.transform,
+            material: triangle.material,
+        }
+    }
+}
+
+impl VTriangle {
+    pub fn new(p1: VTuple, p2: VTuple, p3: VTuple) -> Self {
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+        Self {
+            p1,
+            p2,
+            p3,
+            transform: VMatrix::identity(),
+            material: Default::default(),
+            e1,
+            e2,
+            normal: e2.crossed(&e1).normalized(),
+        }
+    }
+
+    pub fn with_transform(mut self, transform: VMatrix<4>) -> Self {
+        self.transform = transform;
+        self
+    }
+    pub fn with_material(mut self, material: VMaterial) -> Self {
+        self.material = material;
+        self
+    }
+}
+/// Möller–Trumbore intersection shared by flat and smooth triangles, returns
+/// `t`, `u` and `v` of the hit.
+pub(crate) fn intersect_triangle(
+    ray: VRay,
+    p1: VTuple,
+    e1: VTuple,
+    e2: VTuple,
+) -> Option<(F, F, F)> {
+    let dir_cross_e2 = ray.direction.crossed(&e2);
+    let det = e1.dot(&dir_cross_e2);
+    if det.abs() < F::EPSILON {
+        return None;
+    }
+    let f = 1.0 / det;
+    let p1_to_origin = ray.origin - p1;
+    let u = f * p1_to_origin.dot(&dir_cross_e2);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let origin_cross_e1 = p1_to_origin.crossed(&e1);
+    let v = f * ray.direction.dot(&origin_cross_e1);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some((f * e2.dot(&origin_cross_e1), u, v))
+}
+pub(crate) fn triangle_bounds(p1: VTuple, p2: VTuple, p3: VTuple) -> VBounds {
+    let mut bounds = VBounds::empty();
+    bounds.add_point(p1);
+    bounds.add_point(p2);
+    bounds.add_point(p3);
+    bounds
+}
+impl VIntersectable for VTriangle {
+    fn intersect_in_object_space(&self, ray: VRay) -> Vec<VIntersection> {
+        match intersect_triangle(ray, self.p1, self.e1, self.e2) {
+            Some((t, u, v)) => vec![VIntersection::new(t, ray, VBody::from(*self)).with_uv(u, v)],
+            None => vec![],
+        }
+    }
+
+    fn transform(&self) -> VMatrix<4> {
+        self.transform
+    }
+    fn normal_at_in_object_space(&self, _: VTuple) -> VTuple {
+        self.normal
+    }
+    fn material(&self) -> VMaterial {
+        self.material
+    }
+    fn bounds_in_object_space(&self) -> VBounds {
+        triangle_bounds(self.p1, self.p2, self.p3)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zequality::ZEq;
+
+    fn create_triangle() -> VTriangle {
+        VTriangle::new(
+            VTuple::point(0.0, 1.0, 0.0),
+            VTuple::point(-1.0, 0.0, 0.0),
+            VTuple::point(1.0, 0.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn constructing_a_triangle() {
+        let t = create_triangle();
+
+        assert_zeq!(t.e1, VTuple::vector(-1.0, -1.0, 0.0));
+        assert_zeq!(t.e2, VTuple::vector(1.0, -1.0, 0.0));
+        assert_zeq!(t.normal, VTuple::vector(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn the_normal_is_constant_across_the_triangle() {
+        let t = create_triangle();
+
+        assert_zeq!(t.normal_at(VTuple::point(0.0, 0.5, 0.0)), t.normal);
+        assert_zeq!(t.normal_at(VTuple::point(-0.5, 0.75, 0.0)), t.normal);
+        assert_zeq!(t.normal_at(VTuple::point(0.5, 0.25, 0.0)), t.normal);
+    }
+
+    #[test]
+    fn a_ray_parallel_to_the_triangle_misses() {
+        let r = VRay::new(VTuple::point(0.0, -1.0, -2.0), VTuple::vector(0.0, 1.0, 0.0));
+
+        assert_eq!(0, create_triangle().intersect(r).len());
+    }
+
+    #[test]
+    fn a_ray_misses_the_edges() {
+        let t = create_triangle();
+        let d = VTuple::vector(0.0, 0.0, 1.0);
+
+        assert_eq!(0, t.intersect(VRay::new(VTuple::point(1.0, 1.0, -2.0), d)).len());
+        assert_eq!(0, t.intersect(VRay::new(VTuple::point(-1.0, 1.0, -2.0), d)).len());
+        assert_eq!(0, t.intersect(VRay::new(VTuple::point(0.0, -1.0, -2.0), d)).len());
+    }
+
+    #[test]
+    fn a_ray_strikes_a_triangle() {
+        let r = VRay::new(VTuple::point(0.0, 0.5, -2.0), VTuple::vector(0.0, 0.0, 1.0));
+        let xs = create_triangle().intersect(r);
+
+        assert_eq!(1, xs.len());
+        assert_zeq!(xs[0].t, 2.0);
+    }
+}
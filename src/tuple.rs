@@ -1,12 +1,12 @@
 use std::ops;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::F;
 
 use crate::zequality::ZEq;
 
-#[derive(Debug, Copy, Clone, PartialEq,Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VTuple {
     pub x: F,
     pub y: F,
@@ -1,20 +1,38 @@
+use crate::background::{VBackground, VEnvironmentMap};
 use crate::body::*;
 use crate::canvas::vcolor::VColor;
+use crate::computed_intersection::VComputedIntersection;
 use crate::intersections::*;
-use crate::material::Illuminated;
+use crate::material::{Illuminated, VMaterial};
 use crate::ray::*;
+use crate::rng::VRng;
 use crate::tuple::VTuple;
 use crate::{body::VBody, light::VPointLight};
+use crate::F;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
 // use crate::canvas::vcolor::*;
 // use crate::zequality::*;
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VWorld {
     pub bodies: Vec<VBody>,
     pub lights: Vec<VPointLight>,
+    /// Seen by rays that miss every body.
+    #[serde(default)]
+    pub background: VBackground,
 }
 impl VWorld {
     pub fn new(bodies: Vec<VBody>, lights: Vec<VPointLight>) -> Self {
-        VWorld { bodies, lights }
+        VWorld {
+            bodies,
+            lights,
+            background: VBackground::default(),
+        }
+    }
+    pub fn with_background(mut self, background: VBackground) -> Self {
+        self.background = background;
+        self
     }
     pub fn intersect(&self, ray: VRay) -> VIntersections {
         let xs = self
@@ -25,17 +43,88 @@ impl VWorld {
         VIntersections::new(xs)
     }
     pub fn color_at(&self, ray: VRay) -> VColor {
+        self.hit_color_at(ray)
+            .unwrap_or_else(|| self.background.color_at(ray.direction))
+    }
+    /// Like `color_at`, but `None` instead of the background when the ray
+    /// misses everything.
+    pub fn hit_color_at(&self, ray: VRay) -> Option<VColor> {
+        let o = ray.origin;
+        let d = ray.direction;
+        let mut rng = VRng::for_values(&[o.x, o.y, o.z, d.x, d.y, d.z]);
+        self.hit_color_with(ray, &mut rng)
+    }
+    /// Like `hit_color_at`, drawing the random numbers for lighting from
+    /// the environment map from `rng`.
+    pub fn hit_color_with(&self, ray: VRay, rng: &mut VRng) -> Option<VColor> {
         let xs = self.intersect(ray);
-        let hit = xs.hit();
-        if let Some(hit) = hit {
-            let c = hit.get_computed();
-            let material = hit.body.material();
-
-            //implement proper lighting to allow multiple lights
-            material.lighting(self.lights[0], c.pos, c.camv, c.normalv, self.is_shadowed(c.overpoint))
-        } else {
-            VColor::black()
+        let hit = xs.hit()?;
+        let c = hit.get_computed();
+        let material = hit.body.material();
+
+        //implement proper lighting to allow multiple lights
+        let mut color = match self.lights.first() {
+            Some(light) => material.lighting(
+                *light,
+                c.pos,
+                c.camv,
+                c.normalv,
+                self.is_shadowed(c.overpoint),
+            ),
+            None => VColor::black(),
+        };
+        if let VBackground::Environment(map) = &self.background {
+            color = color + self.environment_light(map, &material, &c, rng);
+        }
+        Some(color)
+    }
+    // Monte Carlo estimate of the light reaching a point from the
+    // environment, with the directions importance sampled from the map and
+    // a shadow ray for each of them. Light from all around is scaled by
+    // 1 / pi, so a uniform map of 1 lights like a point light of 1 straight
+    // above the surface would.
+    fn environment_light(
+        &self,
+        map: &VEnvironmentMap,
+        material: &VMaterial,
+        c: &VComputedIntersection,
+        rng: &mut VRng,
+    ) -> VColor {
+        if map.light_samples == 0 {
+            return VColor::black();
         }
+        let mut sum = VColor::black();
+        for _ in 0..map.light_samples {
+            let (direction, light, pdf) = map.sample(rng.next_f(), rng.next_f());
+            if pdf <= 0.0 || direction.dot(&c.normalv) <= 0.0 {
+                continue;
+            }
+            let shadow = self.intersect(VRay::new(c.overpoint, direction));
+            if shadow.hit().is_some() {
+                continue;
+            }
+            let response = material.lighting_from(direction, light, c.camv, c.normalv);
+            sum = sum + response * (1.0 / (PI * pdf));
+        }
+        sum * (1.0 / map.light_samples as F)
+    }
+    /// First surface the ray hits, with the data that goes into shading it.
+    pub fn surface_at(&self, ray: VRay) -> Option<VSurface> {
+        // Bodies are intersected one by one to know the index of the hit
+        let (body, hit) = self
+            .bodies
+            .iter()
+            .enumerate()
+            .filter_map(|(i, body)| body.intersect(ray).hit().cloned().map(|hit| (i, hit)))
+            .min_by(|a, b| a.1.t.total_cmp(&b.1.t))?;
+        let c = hit.get_computed();
+        Some(VSurface {
+            t: hit.t,
+            normal: c.normalv,
+            albedo: hit.body.material().albedo(),
+            body,
+            shadowed: !self.lights.is_empty() && self.is_shadowed(c.overpoint),
+        })
     }
     pub fn is_shadowed(&self, pos: VTuple) -> bool {
         let shadowv = self.lights[0].pos - pos;
@@ -76,42 +165,65 @@ impl VWorld {
     //     }
     // }
 }
+/// What a ray sees first, see [`VWorld::surface_at`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VSurface {
+    /// Ray parameter of the hit, the distance for normalized rays.
+    pub t: F,
+    /// World space normal, facing the ray.
+    pub normal: VTuple,
+    pub albedo: VColor,
+    /// Index of the hit body in `VWorld::bodies`.
+    pub body: usize,
+    /// Whether the first light is blocked.
+    pub shadowed: bool,
+}
 impl Default for VWorld {
     fn default() -> Self {
         VWorld {
             bodies: vec![],
             lights: vec![],
+            background: VBackground::default(),
         }
     }
 }
 
+/// The default world of the book, a light and two spheres one inside the
+/// other, shared by the tests of the modules rendering it.
+#[cfg(test)]
+pub(crate) fn create_default_world() -> VWorld {
+    use crate::material::VPhong;
+    use crate::matrix::VMatrix;
+    use crate::sphere::VSphere;
+
+    let light = VPointLight::new(
+        VTuple::point(-10.0, 10.0, -10.0),
+        VColor::new(1.0, 1.0, 1.0),
+    );
+    // @FIXME: Rafactor to use new builder pattern.
+    let material = VPhong {
+        col: VColor::new(0.8, 1.0, 0.6),
+        dif: 0.7,
+        spc: 0.2,
+        ..VPhong::default()
+    };
+    let s1 = VBody::from(VSphere::default().with_material(VMaterial::from(material)));
+    let s2 = VBody::from(VSphere::default().with_transform(VMatrix::scaling(0.5, 0.5, 0.5)));
+    VWorld::new(vec![s1, s2], vec![light])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::canvas::vcanvas::VCanvas;
     use crate::canvas::vcolor::*;
     use crate::material::*;
     use crate::matrix::VMatrix;
+    use crate::plane::VPlane;
     use crate::sphere::*;
     use crate::tuple::VTuple;
     use crate::zequality::*;
 
-    fn create_default_world() -> VWorld {
-        let light = VPointLight::new(
-            VTuple::point(-10.0, 10.0, -10.0),
-            VColor::new(1.0, 1.0, 1.0),
-        );
-        // @FIXME: Rafactor to use new builder pattern.
-        let material = VPhong {
-            col: VColor::new(0.8, 1.0, 0.6),
-            dif: 0.7,
-            spc: 0.2,
-            ..VPhong::default()
-        };
-        let s1 = VBody::from(VSphere::default().with_material(VMaterial::from(material)));
-        let s2 = VBody::from(VSphere::default().with_transform(VMatrix::scaling(0.5, 0.5, 0.5)));
-        VWorld::new(vec![s1, s2], vec![light])
-    }
-
     #[test]
     fn the_default_world() {
         let light = VPointLight::new(
@@ -155,6 +267,55 @@ mod tests {
         let c = w.color_at(r);
 
         assert_zeq!(c, VColor::black());
+        assert_eq!(w.hit_color_at(r), None);
+    }
+
+    #[test]
+    fn environment_maps_light_the_bodies() {
+        let mut sky = VCanvas::new(32, 16);
+        for y in 0..16 {
+            for x in 0..32 {
+                sky.write_pixel(x, y, VColor::white());
+            }
+        }
+        let map = VEnvironmentMap::new(sky).with_light_samples(8000);
+        let matte = VMaterial::from(VPhong::new(VColor::white(), 0.0, 1.0, 0.0, 200.0));
+        let sphere = VBody::from(VSphere::default().with_material(matte));
+        let w = VWorld::new(vec![sphere.clone()], vec![])
+            .with_background(VBackground::Environment(map.clone()));
+        let r = VRay::new(VTuple::point(0.0, 0.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+
+        // A uniform sky lights the convex sphere like a head-on light
+        let c = w.color_at(r);
+        assert!((c.r - 1.0).abs() < 0.06, "{:?}", c);
+        assert_zeq!(c, w.color_at(r));
+
+        // A floor right below the point hides the lower half of the sky
+        let floor =
+            VBody::from(VPlane::default().with_transform(VMatrix::translation(0.0, -1.2, 0.0)));
+        let w = VWorld::new(vec![sphere, floor], vec![]);
+        let background =
+            |map: VEnvironmentMap| w.clone().with_background(VBackground::Environment(map));
+        assert_zeq!(
+            background(map.clone().with_light_samples(0)).color_at(r),
+            VColor::black()
+        );
+        let c = background(map).color_at(r);
+        assert!((c.r - 0.5).abs() < 0.06, "{:?}", c);
+    }
+
+    #[test]
+    fn missing_rays_see_the_background() {
+        let w = create_default_world().with_background(VBackground::Gradient {
+            bottom: VColor::black(),
+            top: VColor::new(0.5, 0.7, 1.0),
+        });
+        let r = VRay::new(VTuple::point(0.0, 0.0, -5.0), VTuple::vector(0.0, 1.0, 0.0));
+
+        assert_zeq!(w.color_at(r), VColor::new(0.5, 0.7, 1.0));
+        assert_eq!(w.hit_color_at(r), None);
+        let r = VRay::new(VTuple::point(0.0, 0.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+        assert_zeq!(w.color_at(r), create_default_world().color_at(r));
     }
 
     #[test]
@@ -265,4 +426,28 @@ mod tests {
 
     //     assert_fuzzy_eq!(reflected_color, Color::new(0.375, 0.1875, 0.09375));
     //   }
+
+    #[test]
+    fn the_surface_seen_by_a_ray() {
+        let mut w = create_default_world();
+        w.bodies.push(VBody::from(
+            VSphere::default().with_transform(VMatrix::translation(0.0, 0.0, 10.0)),
+        ));
+        let r = VRay::new(VTuple::point(0.0, 0.0, 5.0), VTuple::vector(0.0, 0.0, 1.0));
+        let s = w.surface_at(r).unwrap();
+
+        assert_zeq!(s.t, 4.0);
+        assert_eq!(s.body, 2);
+        assert_zeq!(s.normal, VTuple::vector(0.0, 0.0, -1.0));
+        assert_zeq!(s.albedo, VColor::white());
+        assert!(!s.shadowed);
+
+        let r = VRay::new(VTuple::point(0.0, 0.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+        let s = w.surface_at(r).unwrap();
+        assert_eq!(s.body, 0);
+        assert_zeq!(s.albedo, VColor::new(0.8, 1.0, 0.6));
+
+        let r = VRay::new(VTuple::point(0.0, 5.0, -5.0), VTuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(w.surface_at(r), None);
+    }
 }
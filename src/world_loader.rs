@@ -1,30 +1,499 @@
-use serde::Deserialize;
+use std::collections::HashMap;
 
+use serde_yaml::{Mapping, Value};
+
+use crate::animation::{
+    VBodyKeys, VCameraKeys, VEasing, VInterpolation, VKey, VLightKeys, VTrack,
+};
+use crate::background::{VBackground, VEnvironmentMap};
 use crate::body::VBody;
 use crate::camera::*;
+use crate::canvas::vcolor::VColor;
+use crate::csg::{VCsg, VCsgOperation};
+use crate::cube::VCube;
+use crate::group::VGroup;
 use crate::light::VPointLight;
+use crate::material::{VMaterial, VPhong};
+use crate::matrix::VMatrix;
+use crate::plane::VPlane;
+use crate::scene::{VScene, VSceneError};
+use crate::sphere::VSphere;
+use crate::tuple::VTuple;
+use crate::world::VWorld;
+use crate::F;
 
-#[derive(Debug, Deserialize)]
-struct WorldConfig {
-    cam: VCamera,
-    light: VPointLight,
-    body: VBody,
+/// Loads a scene written in the YAML format of "The Ray Tracer Challenge".
+///
+/// Supports `add` for cameras, lights, spheres, planes, cubes, groups and
+/// csg, `define` with `extend`, and transform lists which may reference other
+/// definitions. A `background` entry takes a `color`, `top` and `bottom`
+/// colors for a gradient, or an `environment` image with optional
+/// `strength`, `rotation` and `light-samples` to light the scene with it.
+/// Cameras may carry an optional `name`, unnamed cameras are called
+/// `camera`, `camera1`, `camera2`... Material keys this renderer does not
+/// model (e.g. `reflective`) are ignored.
+///
+/// Cameras, lights and top level objects may have an `animate` mapping from
+/// property to keyframes, each a mapping from frame to value. Cameras
+/// animate `from`, `to`, `up` and `field-of-view`, lights `at` and
+/// `intensity`, objects `translate`, `rotate` (x, y and z angles) and
+/// `scale` on top of their transform as well as the material's `color`,
+/// `ambient`, `diffuse`, `specular` and `shininess`. Instead of the frames
+/// a track may list `keys` next to an `interpolation` (`linear`,
+/// `catmull-rom` or `bezier`) and an `easing` such as `ease-in-out`.
+pub fn scene_from_yaml(data: &str) -> Result<VScene, VSceneError> {
+    let items: Vec<Value> = serde_yaml::from_str(data)?;
+    let mut loader = YamlLoader::default();
+    for item in items.iter() {
+        loader.load_item(as_mapping(item, "scene entry")?)?;
+    }
+    Ok(loader.scene)
+}
+
+struct YamlLoader {
+    defines: HashMap<String, Value>,
+    scene: VScene,
+    unnamed_cameras: usize,
+}
+impl Default for YamlLoader {
+    fn default() -> Self {
+        Self {
+            defines: HashMap::new(),
+            scene: VScene::new(VWorld::default()),
+            unnamed_cameras: 0,
+        }
+    }
+}
+impl YamlLoader {
+    fn load_item(&mut self, item: &Mapping) -> Result<(), VSceneError> {
+        if let Some(name) = item.get("define") {
+            let name = as_str(name, "define")?;
+            let value = self.define_value(item)?;
+            self.defines.insert(String::from(name), value);
+            return Ok(());
+        }
+        let item = self.resolve_add(item)?;
+        match as_str(get(&item, "add")?, "add")? {
+            "camera" => {
+                let camera = camera_from(&item)?;
+                let name = match item.get("name") {
+                    Some(name) => String::from(as_str(name, "name")?),
+                    None => {
+                        self.unnamed_cameras += 1;
+                        match self.unnamed_cameras {
+                            1 => String::from("camera"),
+                            n => format!("camera{}", n - 1),
+                        }
+                    }
+                };
+                if let Some(keys) = item.get("animate") {
+                    let keys = camera_keys_from(keys)?;
+                    self.scene.animation.cameras.insert(name.clone(), keys);
+                }
+                self.scene.cameras.insert(name, camera);
+            }
+            "light" => {
+                let light = VPointLight::new(
+                    point_from(get(&item, "at")?)?,
+                    color_from(get(&item, "intensity")?)?,
+                );
+                if let Some(keys) = item.get("animate") {
+                    let keys = light_keys_from(keys)?;
+                    let index = self.scene.world.lights.len();
+                    self.scene.animation.lights.insert(index, keys);
+                }
+                self.scene.world.lights.push(light);
+            }
+            "background" => self.scene.world.background = background_from(&item)?,
+            _ => {
+                let body = self.body_from(&item)?;
+                if let Some(keys) = item.get("animate") {
+                    let keys = body_keys_from(keys)?;
+                    let index = self.scene.world.bodies.len();
+                    self.scene.animation.bodies.insert(index, keys);
+                }
+                self.scene.world.bodies.push(body);
+            }
+        }
+        Ok(())
+    }
+    fn define_value(&self, item: &Mapping) -> Result<Value, VSceneError> {
+        let value = get(item, "value")?.clone();
+        match item.get("extend") {
+            None => Ok(value),
+            Some(base) => {
+                let base = self.lookup(as_str(base, "extend")?)?;
+                Ok(merged(base, &value))
+            }
+        }
+    }
+    fn lookup(&self, name: &str) -> Result<&Value, VSceneError> {
+        self.defines
+            .get(name)
+            .ok_or_else(|| invalid(format!("{:?} has not been defined", name)))
+    }
+    // Expands `add: <defined-name>` into the definition it refers to, with
+    // the keys of the entry itself taking precedence.
+    fn resolve_add(&self, item: &Mapping) -> Result<Mapping, VSceneError> {
+        let mut item = item.clone();
+        let mut depth = 0;
+        loop {
+            let kind = as_str(get(&item, "add")?, "add")?;
+            if !self.defines.contains_key(kind) {
+                return Ok(item);
+            }
+            depth += 1;
+            if depth > 32 {
+                return Err(invalid(format!("{:?} is defined recursively", kind)));
+            }
+            let base = self.lookup(kind)?;
+            let mut own = item.clone();
+            own.remove("add");
+            let expanded = merged(base, &Value::Mapping(own));
+            item = as_mapping(&expanded, kind)?.clone();
+        }
+    }
+    fn body_from(&self, item: &Mapping) -> Result<VBody, VSceneError> {
+        let transform = match item.get("transform") {
+            Some(transforms) => self.transform_from(transforms)?,
+            None => VMatrix::identity(),
+        };
+        let material = match item.get("material") {
+            Some(material) => self.material_from(material)?,
+            None => VMaterial::default(),
+        };
+        let body = match as_str(get(item, "add")?, "add")? {
+            "sphere" => VBody::from(
+                VSphere::default()
+                    .with_transform(transform)
+                    .with_material(material),
+            ),
+            "plane" => VBody::from(
+                VPlane::default()
+                    .with_transform(transform)
+                    .with_material(material),
+            ),
+            "cube" => VBody::from(
+                VCube::default()
+                    .with_transform(transform)
+                    .with_material(material),
+            ),
+            "group" => {
+                let mut group = VGroup::default().with_transform(transform);
+                if let Some(children) = item.get("children") {
+                    for child in as_sequence(children, "children")? {
+                        let child = self.resolve_add(as_mapping(child, "child")?)?;
+                        not_animated(&child)?;
+                        group.add_child(self.body_from(&child)?);
+                    }
+                }
+                VBody::from(group)
+            }
+            "csg" => {
+                let operation = match as_str(get(item, "operation")?, "operation")? {
+                    "union" => VCsgOperation::Union,
+                    "intersection" => VCsgOperation::Intersection,
+                    "difference" => VCsgOperation::Difference,
+                    op => return Err(invalid(format!("unknown csg operation {:?}", op))),
+                };
+                let left = self.resolve_add(as_mapping(get(item, "left")?, "left")?)?;
+                let right = self.resolve_add(as_mapping(get(item, "right")?, "right")?)?;
+                not_animated(&left)?;
+                not_animated(&right)?;
+                VBody::from(
+                    VCsg::new(operation, self.body_from(&left)?, self.body_from(&right)?)
+                        .with_transform(transform),
+                )
+            }
+            kind => return Err(invalid(format!("unknown object type {:?}", kind))),
+        };
+        Ok(body)
+    }
+    fn transform_from(&self, value: &Value) -> Result<VMatrix<4>, VSceneError> {
+        self.nested_transform_from(value, 0)
+    }
+    // Definitions may list other definitions, `depth` stops the ones that
+    // end up listing themselves.
+    fn nested_transform_from(
+        &self,
+        value: &Value,
+        depth: usize,
+    ) -> Result<VMatrix<4>, VSceneError> {
+        let mut transform = VMatrix::identity();
+        for step in as_sequence(value, "transform")? {
+            let step_transform = match step {
+                Value::String(name) if depth >= 32 => {
+                    return Err(invalid(format!("{:?} is defined recursively", name)))
+                }
+                Value::String(name) => self.nested_transform_from(self.lookup(name)?, depth + 1)?,
+                _ => transform_step_from(step)?,
+            };
+            // Each entry is applied after the ones listed before it
+            transform = step_transform * transform;
+        }
+        Ok(transform)
+    }
+    fn material_from(&self, value: &Value) -> Result<VMaterial, VSceneError> {
+        let mapping = match value {
+            Value::String(name) => as_mapping(self.lookup(name)?, name)?,
+            _ => as_mapping(value, "material")?,
+        };
+        let mut phong = VPhong::default();
+        if let Some(color) = mapping.get("color") {
+            phong = phong.with_color(color_from(color)?);
+        }
+        if let Some(ambient) = mapping.get("ambient") {
+            phong = phong.with_ambient(as_f(ambient, "ambient")?);
+        }
+        if let Some(diffuse) = mapping.get("diffuse") {
+            phong = phong.with_diffuse(as_f(diffuse, "diffuse")?);
+        }
+        if let Some(specular) = mapping.get("specular") {
+            phong = phong.with_specular(as_f(specular, "specular")?);
+        }
+        if let Some(shininess) = mapping.get("shininess") {
+            phong = phong.with_shininess(as_f(shininess, "shininess")?);
+        }
+        Ok(VMaterial::from(phong))
+    }
+}
+
+fn camera_from(item: &Mapping) -> Result<VCamera, VSceneError> {
+    let width = as_size(get(item, "width")?, "width")?;
+    let height = as_size(get(item, "height")?, "height")?;
+    let fov = as_f(get(item, "field-of-view")?, "field-of-view")?;
+    Ok(VCamera::new(width, height, fov).positioned_and_pointed(
+        point_from(get(item, "from")?)?,
+        point_from(get(item, "to")?)?,
+        vector_from(get(item, "up")?)?,
+    ))
+}
+fn background_from(item: &Mapping) -> Result<VBackground, VSceneError> {
+    if let Some(path) = item.get("environment") {
+        let path = as_str(path, "environment")?;
+        let mut map = VEnvironmentMap::load(path)
+            .map_err(|e| invalid(format!("environment {}: {}", path, e)))?;
+        if let Some(strength) = item.get("strength") {
+            map = map.with_strength(as_f(strength, "strength")?);
+        }
+        if let Some(rotation) = item.get("rotation") {
+            map = map.with_rotation(as_f(rotation, "rotation")?);
+        }
+        if let Some(samples) = item.get("light-samples") {
+            map = map.with_light_samples(as_f(samples, "light-samples")? as usize);
+        }
+        return Ok(VBackground::Environment(map));
+    }
+    match (item.get("color"), item.get("bottom"), item.get("top")) {
+        (Some(color), None, None) => Ok(VBackground::Solid(color_from(color)?)),
+        (None, Some(bottom), Some(top)) => Ok(VBackground::Gradient {
+            bottom: color_from(bottom)?,
+            top: color_from(top)?,
+        }),
+        _ => Err(invalid(String::from(
+            "background needs a color, top and bottom, or an environment",
+        ))),
+    }
+}
+fn camera_keys_from(value: &Value) -> Result<VCameraKeys, VSceneError> {
+    let mut keys = VCameraKeys::default();
+    for (property, track) in as_mapping(value, "animate")?.iter() {
+        match as_str(property, "animated property")? {
+            "from" => keys.from = track_from(track, point_from)?,
+            "to" => keys.to = track_from(track, point_from)?,
+            "up" => keys.up = track_from(track, vector_from)?,
+            "field-of-view" => keys.fov = track_from(track, |v| as_f(v, "field-of-view"))?,
+            property => return Err(not_animatable("camera", property)),
+        }
+    }
+    Ok(keys)
+}
+fn light_keys_from(value: &Value) -> Result<VLightKeys, VSceneError> {
+    let mut keys = VLightKeys::default();
+    for (property, track) in as_mapping(value, "animate")?.iter() {
+        match as_str(property, "animated property")? {
+            "at" => keys.position = track_from(track, point_from)?,
+            "intensity" => keys.intensity = track_from(track, color_from)?,
+            property => return Err(not_animatable("light", property)),
+        }
+    }
+    Ok(keys)
+}
+fn body_keys_from(value: &Value) -> Result<VBodyKeys, VSceneError> {
+    let mut keys = VBodyKeys::default();
+    for (property, track) in as_mapping(value, "animate")?.iter() {
+        let number = |name| track_from(track, |v| as_f(v, name));
+        match as_str(property, "animated property")? {
+            "translate" => keys.translation = track_from(track, vector_from)?,
+            "rotate" => keys.rotation = track_from(track, vector_from)?,
+            "scale" => keys.scale = track_from(track, vector_from)?,
+            "color" => keys.color = track_from(track, color_from)?,
+            "ambient" => keys.ambient = number("ambient")?,
+            "diffuse" => keys.diffuse = number("diffuse")?,
+            "specular" => keys.specular = number("specular")?,
+            "shininess" => keys.shininess = number("shininess")?,
+            property => return Err(not_animatable("body", property)),
+        }
+    }
+    Ok(keys)
+}
+// Tracks are written as a mapping from frame to value, or as a mapping with
+// those `keys` and the optional `interpolation` and `easing`.
+fn track_from<T>(
+    value: &Value,
+    value_from: impl Fn(&Value) -> Result<T, VSceneError>,
+) -> Result<VTrack<T>, VSceneError> {
+    let mapping = as_mapping(value, "keyframes")?;
+    let (keys, options) = match mapping.get("keys") {
+        Some(keys) => (as_mapping(keys, "keys")?, Some(mapping)),
+        None => (mapping, None),
+    };
+    let keys = keys
+        .iter()
+        .map(|(frame, value)| Ok(VKey::new(as_f(frame, "frame")?, value_from(value)?)))
+        .collect::<Result<Vec<_>, VSceneError>>()?;
+    let mut track = VTrack::new(keys);
+    if let Some(interpolation) = options.and_then(|o| o.get("interpolation")) {
+        track = track.with_interpolation(match as_str(interpolation, "interpolation")? {
+            "linear" => VInterpolation::Linear,
+            "catmull-rom" => VInterpolation::CatmullRom,
+            "bezier" => VInterpolation::Bezier,
+            other => return Err(invalid(format!("unknown interpolation {:?}", other))),
+        });
+    }
+    if let Some(easing) = options.and_then(|o| o.get("easing")) {
+        track = track.with_easing(match as_str(easing, "easing")? {
+            "linear" => VEasing::Linear,
+            "ease-in" => VEasing::EaseIn,
+            "ease-out" => VEasing::EaseOut,
+            "ease-in-out" => VEasing::EaseInOut,
+            "cubic-in" => VEasing::CubicIn,
+            "cubic-out" => VEasing::CubicOut,
+            "cubic-in-out" => VEasing::CubicInOut,
+            "elastic-in" => VEasing::ElasticIn,
+            "elastic-out" => VEasing::ElasticOut,
+            other => return Err(invalid(format!("unknown easing {:?}", other))),
+        });
+    }
+    Ok(track)
+}
+fn not_animatable(kind: &str, property: &str) -> VSceneError {
+    invalid(format!("{} property {:?} can not be animated", kind, property))
+}
+// Keyframes refer to entries of the world, which children are not.
+fn not_animated(item: &Mapping) -> Result<(), VSceneError> {
+    match item.get("animate") {
+        Some(_) => Err(invalid(String::from(
+            "only top level objects can be animated",
+        ))),
+        None => Ok(()),
+    }
+}
+fn transform_step_from(value: &Value) -> Result<VMatrix<4>, VSceneError> {
+    let step = as_sequence(value, "transform")?;
+    let (op, args) = match step.split_first() {
+        Some((op, args)) => (as_str(op, "transform")?, numbers_from(args, op)?),
+        None => return Err(invalid(String::from("empty transform"))),
+    };
+    let expect = |count: usize| -> Result<(), VSceneError> {
+        if args.len() == count {
+            Ok(())
+        } else {
+            Err(invalid(format!("{} expects {} values, got {}", op, count, args.len())))
+        }
+    };
+    match op {
+        "translate" => expect(3).map(|_| VMatrix::translation(args[0], args[1], args[2])),
+        "scale" => expect(3).map(|_| VMatrix::scaling(args[0], args[1], args[2])),
+        "rotate-x" => expect(1).map(|_| VMatrix::rotation_x(args[0])),
+        "rotate-y" => expect(1).map(|_| VMatrix::rotation_y(args[0])),
+        "rotate-z" => expect(1).map(|_| VMatrix::rotation_z(args[0])),
+        "shear" => expect(6).map(|_| {
+            VMatrix::shearing(args[0], args[1], args[2], args[3], args[4], args[5])
+        }),
+        _ => Err(invalid(format!("unknown transform {:?}", op))),
+    }
+}
+
+// Merges two definitions, keys of `value` override those of `base`.
+fn merged(base: &Value, value: &Value) -> Value {
+    match (base, value) {
+        (Value::Mapping(base), Value::Mapping(value)) => {
+            let mut result = base.clone();
+            for (k, v) in value.iter() {
+                result.insert(k.clone(), v.clone());
+            }
+            Value::Mapping(result)
+        }
+        _ => value.clone(),
+    }
+}
+fn invalid(message: String) -> VSceneError {
+    VSceneError::Invalid(message)
+}
+fn get<'a>(item: &'a Mapping, key: &str) -> Result<&'a Value, VSceneError> {
+    item.get(key)
+        .ok_or_else(|| invalid(format!("missing key {:?}", key)))
+}
+fn as_mapping<'a>(value: &'a Value, context: &str) -> Result<&'a Mapping, VSceneError> {
+    value
+        .as_mapping()
+        .ok_or_else(|| invalid(format!("{} must be a mapping", context)))
+}
+fn as_sequence<'a>(value: &'a Value, context: &str) -> Result<&'a Vec<Value>, VSceneError> {
+    value
+        .as_sequence()
+        .ok_or_else(|| invalid(format!("{} must be a list", context)))
+}
+fn as_str<'a>(value: &'a Value, context: &str) -> Result<&'a str, VSceneError> {
+    value
+        .as_str()
+        .ok_or_else(|| invalid(format!("{} must be a string", context)))
+}
+fn as_f(value: &Value, context: &str) -> Result<F, VSceneError> {
+    value
+        .as_f64()
+        .ok_or_else(|| invalid(format!("{} must be a number", context)))
+}
+// Image sizes, which have to be whole numbers above 0.
+fn as_size(value: &Value, context: &str) -> Result<usize, VSceneError> {
+    let size = as_f(value, context)?;
+    if size < 1.0 || size.fract() != 0.0 || size > u32::MAX as F {
+        return Err(invalid(format!(
+            "{} must be a positive whole number, got {}",
+            context, size
+        )));
+    }
+    Ok(size as usize)
+}
+fn numbers_from(values: &[Value], context: &Value) -> Result<Vec<F>, VSceneError> {
+    let context = context.as_str().unwrap_or("value");
+    values.iter().map(|v| as_f(v, context)).collect()
+}
+fn triple_from(value: &Value) -> Result<(F, F, F), VSceneError> {
+    let numbers = numbers_from(as_sequence(value, "vector")?, &Value::Null)?;
+    match numbers[..] {
+        [x, y, z] => Ok((x, y, z)),
+        _ => Err(invalid(format!("expected 3 values, got {}", numbers.len()))),
+    }
+}
+fn point_from(value: &Value) -> Result<VTuple, VSceneError> {
+    triple_from(value).map(|(x, y, z)| VTuple::point(x, y, z))
+}
+fn vector_from(value: &Value) -> Result<VTuple, VSceneError> {
+    triple_from(value).map(|(x, y, z)| VTuple::vector(x, y, z))
+}
+fn color_from(value: &Value) -> Result<VColor, VSceneError> {
+    triple_from(value).map(|(r, g, b)| VColor::new(r, g, b))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::material::VMaterial;
-    use crate::material::VPhong;
-    use crate::matrix::VMatrix;
-    use crate::sphere::VSphere;
-    use crate::tuple::VTuple;
-    use crate::world::*;
-    use std::collections::HashMap;
-    use crate::canvas::vcolor::VColor;
-    use ron::de::from_str;
-
-    //use crate::zequality::*;
+    use crate::body::VIntersectable;
+    use crate::zequality::*;
+    use std::collections::BTreeMap;
+
     #[test]
     fn load_yaml_world() {
         let expected_world = VWorld::new(
@@ -45,7 +514,7 @@ mod tests {
             )],
         );
 
-        let mut expected_cameras = HashMap::new();
+        let mut expected_cameras = BTreeMap::new();
         expected_cameras.insert(
             String::from("output1"),
             VCamera::new(800, 600, 0.785).positioned_and_pointed(
@@ -54,20 +523,275 @@ mod tests {
                 VTuple::vector(9.10, 11.0, 1.2),
             ),
         );
-        let cfg: WorldConfig = match from_str("worldconfig.ron") {
-            Ok(x) => x,
-            Err(e) => {
-                println!("Failed to load config: {}", e);
+        let scene = scene_from_yaml(include_str!("worldconfig.yml")).unwrap();
+
+        assert_eq!(scene.world, expected_world);
+        assert_eq!(scene.cameras, expected_cameras);
+    }
+
+    #[test]
+    fn defines_can_be_extended() {
+        let scene = scene_from_yaml(
+            "
+- define: white-material
+  value:
+    color: [1, 1, 1]
+    diffuse: 0.7
+- define: blue-material
+  extend: white-material
+  value:
+    color: [0.5, 0.8, 0.9]
+- define: standard-transform
+  value:
+    - [translate, 1, -1, 1]
+    - [scale, 0.5, 0.5, 0.5]
+- add: cube
+  material: blue-material
+  transform:
+    - standard-transform
+    - [translate, 4, 0, 0]
+",
+        )
+        .unwrap();
+        let expected = VBody::from(
+            VCube::default()
+                .with_transform(
+                    VMatrix::translation(4.0, 0.0, 0.0)
+                        * VMatrix::scaling(0.5, 0.5, 0.5)
+                        * VMatrix::translation(1.0, -1.0, 1.0),
+                )
+                .with_material(VMaterial::from(
+                    VPhong::default()
+                        .with_color(VColor::new(0.5, 0.8, 0.9))
+                        .with_diffuse(0.7),
+                )),
+        );
+
+        assert_eq!(scene.world.bodies, vec![expected]);
+    }
+
+    #[test]
+    fn groups_load_their_children() {
+        let scene = scene_from_yaml(
+            "
+- define: ball
+  value:
+    add: sphere
+    transform:
+      - [scale, 0.5, 0.5, 0.5]
+- add: group
+  transform:
+    - [translate, 0, 1, 0]
+  children:
+    - add: ball
+    - add: plane
+",
+        )
+        .unwrap();
+
+        match &scene.world.bodies[0] {
+            VBody::VGroup(group) => {
+                assert_eq!(group.len(), 2);
+                assert_zeq!(group.transform, VMatrix::translation(0.0, 1.0, 0.0));
+                assert_eq!(
+                    group.children()[0],
+                    VBody::from(VSphere::default().with_transform(VMatrix::scaling(0.5, 0.5, 0.5)))
+                );
+            }
+            body => panic!("expected a group, got {:?}", body),
+        }
+    }
+
+    #[test]
+    fn csg_loads_both_operands() {
+        let scene = scene_from_yaml(
+            "
+- add: csg
+  operation: difference
+  left:
+    add: cube
+  right:
+    add: sphere
+    transform:
+      - [scale, 0.5, 0.5, 2]
+",
+        )
+        .unwrap();
+
+        match &scene.world.bodies[0] {
+            VBody::VCsg(csg) => {
+                assert_eq!(csg.operation, VCsgOperation::Difference);
+                assert_eq!(*csg.left, VBody::from(VCube::default()));
+                assert_zeq!(csg.right.transform(), VMatrix::scaling(0.5, 0.5, 2.0));
+            }
+            body => panic!("expected a csg, got {:?}", body),
+        }
+    }
+
+    #[test]
+    fn unnamed_cameras_get_default_names() {
+        let camera = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [0, 1, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+";
+        let scene = scene_from_yaml(&format!("{}{}", camera, camera)).unwrap();
+
+        assert!(scene.cameras.contains_key("camera"));
+        assert!(scene.cameras.contains_key("camera1"));
+    }
+
+    #[test]
+    fn loading_backgrounds() {
+        let scene = scene_from_yaml("- add: background\n  color: [0.1, 0.2, 0.3]\n").unwrap();
+        assert_eq!(
+            scene.world.background,
+            VBackground::Solid(VColor::new(0.1, 0.2, 0.3))
+        );
 
-                std::process::exit(1);
+        let scene = scene_from_yaml(
+            "
+- add: background
+  bottom: [1, 1, 1]
+  top: [0.5, 0.7, 1]
+",
+        )
+        .unwrap();
+        assert_eq!(
+            scene.world.background,
+            VBackground::Gradient {
+                bottom: VColor::white(),
+                top: VColor::new(0.5, 0.7, 1.0)
             }
+        );
+
+        assert!(scene_from_yaml("- add: background\n  top: [1, 1, 1]\n").is_err());
+        assert!(scene_from_yaml("- add: background\n  environment: missing.hdr\n").is_err());
+    }
+
+    #[test]
+    fn loading_environment_lighting() {
+        let path = std::env::temp_dir().join(format!("ray_tracer_sky_{}.ppm", std::process::id()));
+        std::fs::write(&path, b"P3 2 1 255 0 0 0 255 255 255").unwrap();
+        let scene = scene_from_yaml(&format!(
+            "- add: background\n  environment: {}\n  strength: 2\n  light-samples: 16\n",
+            path.display()
+        ))
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        match scene.world.background {
+            VBackground::Environment(map) => {
+                assert_eq!((map.strength, map.light_samples), (2.0, 16));
+                assert_eq!(map.image().pixel_at(1, 0), VColor::white());
+            }
+            background => panic!("expected an environment, got {:?}", background),
+        }
+    }
+
+    #[test]
+    fn loading_animations() {
+        let yaml = r#"
+- add: camera
+  width: 10
+  height: 10
+  field-of-view: 1
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+  animate:
+    from: { 0: [0, 0, -5], 10: [0, 0, -10] }
+    field-of-view:
+      keys: { 0: 1, 10: 2 }
+      easing: ease-in
+- add: light
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  animate:
+    intensity: { 0: [0, 0, 0], 20: [1, 1, 1] }
+- add: plane
+- add: sphere
+  transform:
+    - [translate, 0, 1, 0]
+  animate:
+    translate: { 0: [0, 0, 0], 10: [2, 0, 0] }
+    ambient: { 10: 1, 0: 0 }
+"#;
+        let scene = scene_from_yaml(yaml).unwrap();
+
+        assert_zeq!(scene.animation.last_frame(), 20.0);
+        assert!(!scene.animation.bodies.contains_key(&0));
+        let frame = scene.at_frame(5.0);
+        let ray = frame.cameras["camera"].ray_for_subpixel(5, 5, 0.0, 0.0);
+        assert_zeq!(ray.origin, VTuple::point(0.0, 0.0, -7.5));
+        assert_zeq!(frame.cameras["camera"].fov, 1.25);
+        assert_zeq!(frame.world.lights[0].col, VColor::new(0.25, 0.25, 0.25));
+        assert_zeq!(
+            frame.world.bodies[1].transform(),
+            VMatrix::translation(1.0, 1.0, 0.0)
+        );
+        assert_zeq!(
+            frame.world.bodies[1].material(),
+            VMaterial::from(VPhong::default().with_ambient(0.5))
+        );
+
+        let rejected = [
+            "- add: light\n  at: [0, 0, 0]\n  intensity: [1, 1, 1]\n  animate: { color: {} }\n",
+            "- add: sphere\n  animate: { translate: [0, 1] }\n",
+            "- add: sphere\n  animate: { ambient: { keys: {}, easing: bounce } }\n",
+            "- add: group\n  children:\n    - add: cube\n      animate: {}\n",
+        ];
+        for yaml in rejected {
+            assert!(matches!(scene_from_yaml(yaml), Err(VSceneError::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn unknown_objects_are_reported() {
+        let result = scene_from_yaml("- add: teapot\n");
+
+        assert!(matches!(result, Err(VSceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn camera_sizes_must_be_positive_whole_numbers() {
+        let camera = |width: &str| {
+            format!(
+                "- add: camera\n  width: {}\n  height: 10\n  field-of-view: 1\n  from: [0, 0, -5]\n  to: [0, 0, 0]\n  up: [0, 1, 0]\n",
+                width
+            )
         };
 
-        // println!("Camera: {:?}", &cfg.cam);
-        // println!("Light: {:?}", &light);
-        println!("Body: {:?}", &cfg.body);
+        assert_eq!(scene_from_yaml(&camera("20")).unwrap().cameras["camera"].hsize, 20);
+        for width in ["-10", "0", "10.5", "\"ten\""] {
+            assert!(
+                matches!(scene_from_yaml(&camera(width)), Err(VSceneError::Invalid(_))),
+                "width {} was accepted",
+                width
+            );
+        }
+    }
+
+    #[test]
+    fn recursive_transforms_are_reported() {
+        let result = scene_from_yaml(
+            "
+- define: spin
+  value:
+    - [rotate-y, 1]
+    - turn
+- define: turn
+  value:
+    - spin
+- add: sphere
+  transform:
+    - turn
+",
+        );
 
-        //assert_zeq!(loaded_world,expected_world);
-        //assert_zeq!(loaded_cameras,expected_cameras);
+        assert!(matches!(result, Err(VSceneError::Invalid(_))));
     }
 }